@@ -1,4 +1,66 @@
+-- Tags required by each function. Alternatives in the same `any` tag share `any_group`.
 SELECT
-  *
+  hir.name,
+  requirement.tag,
+  requirement.args,
+  requirement.any_group
 FROM
-  hir;
+  requirement
+  JOIN hir USING (hash1, hash2)
+ORDER BY
+  hir.name;
+
+-- Callsites discharging Alias with no reason.
+SELECT
+  callsite.caller,
+  callsite.callee,
+  callsite.file,
+  callsite.line,
+  callsite.col
+FROM
+  callsite
+  JOIN discharge ON discharge.callsite = callsite.id
+WHERE
+  discharge.tag = 'Alias'
+  AND (
+    discharge.reason IS NULL
+    OR trim(discharge.reason) = ''
+  );
+
+-- Tags required by callees but not discharged on callsites, except those in `any` tags.
+SELECT
+  callsite.caller,
+  callsite.callee,
+  requirement.tag,
+  callsite.file,
+  callsite.line
+FROM
+  callsite
+  JOIN requirement ON requirement.hash1 = callsite.callee_hash1
+  AND requirement.hash2 = callsite.callee_hash2
+WHERE
+  requirement.any_group IS NULL
+  AND NOT EXISTS (
+    SELECT
+      1
+    FROM
+      discharge
+    WHERE
+      discharge.callsite = callsite.id
+      AND discharge.tag = requirement.tag
+  );
+
+-- Counts of diagnostics by crate and kind.
+SELECT
+  crate.name,
+  crate.version,
+  diagnostic.kind,
+  diagnostic.level,
+  count(*)
+FROM
+  diagnostic
+  JOIN crate ON crate.id = diagnostic.crate
+GROUP BY
+  crate.id,
+  diagnostic.kind,
+  diagnostic.level;
@@ -21,7 +21,7 @@ FEATURES=${1:-"std"}
 ./gen_rust_toolchain_toml.rs "${FEATURES}"
 
 # Remove data.sqlite3 the cache
-rm -f target/data.sqlite3 tests/demo/data.sqlite3
+rm -f target/data.sqlite3* tests/demo/data.sqlite3*
 
 cargo fmt --check --all
 cargo clippy -F "${FEATURES}" --workspace -- -D clippy::all
@@ -43,7 +43,7 @@ cargo test
 popd
 
 # enable tag definitions
-rm "$DATA_SQLITE3"
+rm "$DATA_SQLITE3"*
 export SP_DIR=$PWD/assets
 
 pushd safety-lsp
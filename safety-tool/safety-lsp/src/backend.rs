@@ -1,24 +1,66 @@
-use crate::rust::Rust;
-use std::sync::Mutex;
-use tower_lsp_server::jsonrpc::Result;
+use crate::{
+    rust::{Rust, legend},
+    spec::{
+        SpecPaths, check_rename, load_spec, tag_definition, tag_name_location, tag_order_references,
+    },
+    spec_file::SpecFile,
+    workspace::{FileIndex, Workspace, replace_all},
+};
+use std::{collections::HashMap, fs, path::PathBuf, sync::Mutex};
+use tower_lsp_server::jsonrpc::{Error, Result};
 use tower_lsp_server::lsp_types::*;
-use tower_lsp_server::{Client, LanguageServer};
+use tower_lsp_server::{Client, LanguageServer, UriExt};
 
 pub struct Backend {
     client: Client,
-    rust: Mutex<Rust>,
+    /// Opened documents.
+    documents: Mutex<HashMap<Uri, Rust>>,
+    /// Opened spec TOML files.
+    spec_files: Mutex<HashMap<Uri, SpecFile>>,
+    workspace: Mutex<Workspace>,
+    /// Where spec TOML files are loaded from.
+    spec: Mutex<SpecPaths>,
 }
 
 impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        let folders = params.workspace_folders.unwrap_or_default();
+        let mut uris: Vec<_> = folders.into_iter().map(|folder| folder.uri).collect();
+        if uris.is_empty() {
+            #[allow(deprecated)]
+            uris.extend(params.root_uri);
+        }
+        let roots: Vec<_> =
+            uris.iter().filter_map(|uri| uri.to_file_path()).map(|p| p.into_owned()).collect();
+        *self.spec.lock().unwrap() = SpecPaths::new(params.initialization_options.as_ref(), &roots);
+        self.with_workspace(|w| w.set_roots(roots));
+
         Ok(InitializeResult {
             capabilities: ServerCapabilities {
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
-                completion_provider: Some(CompletionOptions::default()),
+                completion_provider: Some(CompletionOptions {
+                    // complete tags after `type.`
+                    trigger_characters: Some(vec![".".to_owned()]),
+                    ..Default::default()
+                }),
+                definition_provider: Some(OneOf::Left(true)),
+                references_provider: Some(OneOf::Left(true)),
+                rename_provider: Some(OneOf::Left(true)),
+                code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+                semantic_tokens_provider: Some(
+                    SemanticTokensServerCapabilities::SemanticTokensOptions(
+                        SemanticTokensOptions {
+                            legend: legend(),
+                            full: Some(SemanticTokensFullOptions::Bool(true)),
+                            ..Default::default()
+                        },
+                    ),
+                ),
+                inlay_hint_provider: Some(OneOf::Left(true)),
                 text_document_sync: Some(TextDocumentSyncCapability::Options(
                     TextDocumentSyncOptions {
                         open_close: Some(true),
-                        change: Some(TextDocumentSyncKind::FULL),
+                        change: Some(TextDocumentSyncKind::INCREMENTAL),
                         will_save: Some(true),
                         will_save_wait_until: Some(false),
                         save: Some(TextDocumentSyncSaveOptions::SaveOptions(SaveOptions {
@@ -35,6 +77,16 @@ impl LanguageServer for Backend {
     async fn initialized(&self, _: InitializedParams) {
         let message = "[initialized] safety-tool server initialized!";
         self.client.log_message(MessageType::INFO, message).await;
+
+        self.watch_spec().await;
+        self.reload_spec(true).await;
+
+        // Index without holding the lock, nor blocking the runtime.
+        let roots = self.with_workspace(|w| w.roots().to_vec());
+        let indexed = tokio::task::spawn_blocking(move || Workspace::index_roots(&roots)).await;
+        let count = self.with_workspace(|w| w.extend_index(indexed.unwrap_or_default()));
+        let message = format!("[initialized] indexed tags in {count} Rust files");
+        self.client.log_message(MessageType::INFO, message).await;
     }
 
     async fn shutdown(&self) -> Result<()> {
@@ -42,84 +94,125 @@ impl LanguageServer for Backend {
     }
 
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
-        let text = params.text_document.text;
-        self.update_document(text);
+        let doc = params.text_document;
+        if let Some(path) = self.spec_file_path(&doc.uri) {
+            let spec_file = SpecFile::new(Some(path), doc.text);
+            self.with_spec_files(|files| files.insert(doc.uri.clone(), spec_file));
+            self.update_spec_file(doc.uri, Some(doc.version)).await;
+            return;
+        }
+        let mut rust = Rust::new();
+        rust.set_text(doc.text);
+        self.with_documents(|docs| docs.insert(doc.uri.clone(), rust));
+        self.update_document(doc.uri, doc.version).await;
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
-        let text = params.content_changes.iter().map(|c| &*c.text).collect::<Vec<_>>().join("");
-        self.update_document(text);
+        let doc = params.text_document;
+        let changes = params.content_changes;
+        if self.with_spec_files(|files| files.contains_key(&doc.uri)) {
+            self.with_spec_file(&doc.uri, |s| s.apply_changes(changes));
+            self.update_spec_file(doc.uri, Some(doc.version)).await;
+        } else if self.with_doc(&doc.uri, |r| r.apply_changes(changes)).is_some() {
+            self.update_document(doc.uri, doc.version).await;
+        }
     }
 
-    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
-        let pos = params.text_document_position.position;
-        self.with_rust(|r| {
-            if r.get_attr_range(pos).is_none() {
-                // The cursor is not in an attribute, thus no completion.
-                return Ok(None);
-            }
+    async fn did_change_watched_files(&self, _: DidChangeWatchedFilesParams) {
+        self.reload_spec(false).await;
+    }
 
-            let response = r.for_each_tag(|tag| CompletionItem {
-                label: tag.name.to_owned(),
-                label_details: Some(CompletionItemLabelDetails {
-                    detail: None,
-                    // inline desc left-aligned
-                    description: Some("(safety tag)".to_owned()),
-                }),
-                kind: Some(CompletionItemKind::PROPERTY),
-                detail: Some(tag.hover_detail()),
-                documentation: Some(Documentation::MarkupContent(MarkupContent {
-                    kind: MarkupKind::Markdown,
-                    value: tag.hover_documentation(),
-                })),
-                text_edit: Some(
-                    TextEdit::new(
-                        Range {
-                            start: Position {
-                                line: pos.line,
-                                character: pos.character.saturating_sub(1),
-                            },
-                            end: pos,
-                        },
-                        tag.hover_detail(),
-                    )
-                    .into(),
-                ),
-                ..Default::default()
-            });
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
+        self.with_documents(|docs| docs.remove(&uri));
+        self.with_spec_files(|files| files.remove(&uri));
+        self.client.publish_diagnostics(uri, Vec::new(), None).await;
+    }
 
-            Ok(Some(CompletionResponse::Array(response)))
-        })
+    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        let TextDocumentPositionParams { text_document, position: pos } =
+            params.text_document_position;
+        if let Some(items) = self.with_spec_file(&text_document.uri, |s| s.completion(pos)) {
+            return Ok(items.map(CompletionResponse::Array));
+        }
+        let items = self.with_doc(&text_document.uri, |r| {
+            r.completion(pos, |name| self.with_workspace(|w| w.requires_fn(name)))
+        });
+        Ok(items.flatten().map(CompletionResponse::Array))
     }
 
-    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
-        let pos = params.text_document_position_params.position;
-
-        let attr = self.with_rust(|r| r.get_attr_str(pos));
-        let safety_attr = safety_parser::safety::parse_attr_and_get_properties(
-            attr.as_deref().unwrap_or_default(),
-        );
-
-        let mut safety_doc =
-            safety_attr.iter().map(|attr| attr.gen_hover_doc()).collect::<Vec<_>>().join("\n");
-        let tag_count = safety_attr.iter().map(|attr| attr.tags.len()).sum::<usize>();
-        match tag_count {
-            0 => (),
-            1 => safety_doc.insert_str(0, "# Safety Requirement\n\n"),
-            _ => safety_doc.insert_str(0, "# Safety Requirements\n\n"),
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>> {
+        let TextDocumentPositionParams { text_document, position } =
+            params.text_document_position_params;
+        let Some(name) = self.tag_at(&text_document.uri, position) else { return Ok(None) };
+        Ok(tag_definition(&name).map(GotoDefinitionResponse::Scalar))
+    }
+
+    async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+        let TextDocumentPositionParams { text_document, position } = params.text_document_position;
+        let Some(name) = self.tag_at(&text_document.uri, position) else { return Ok(None) };
+        let mut locations = self.with_workspace(|w| w.references(&name));
+        if params.context.include_declaration {
+            locations.extend(tag_definition(&name));
         }
+        Ok(Some(locations))
+    }
+
+    async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+        let TextDocumentPositionParams { text_document, position } = params.text_document_position;
+        let Some(name) = self.tag_at(&text_document.uri, position) else { return Ok(None) };
+        check_rename(&name, &params.new_name).map_err(Error::invalid_params)?;
+
+        let mut locations = self.with_workspace(|w| w.references(&name));
+        locations.extend(tag_name_location(&name));
+        locations.extend(tag_order_references(&name));
+        Ok(Some(replace_all(locations, &params.new_name)))
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri;
+        let pos = params.range.start;
+        let actions = self.with_doc(&uri, |r| {
+            r.code_actions(&uri, pos, |name| self.with_workspace(|w| w.requires_fn(name)))
+        });
+        Ok(actions.filter(|actions| !actions.is_empty()))
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> Result<Option<SemanticTokensResult>> {
+        let tokens = self.with_doc(&params.text_document.uri, |r| r.semantic_tokens());
+        Ok(tokens.map(|data| SemanticTokens { result_id: None, data }.into()))
+    }
 
-        let pos_end = {
-            let mut pos = pos;
-            pos.character += 1;
-            pos
+    async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        let hints = self.with_doc(&params.text_document.uri, |r| {
+            r.inlay_hints(params.range, |name| self.with_workspace(|w| w.requires_fn(name)))
+        });
+        Ok(hints)
+    }
+
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let TextDocumentPositionParams { text_document, position } =
+            params.text_document_position_params;
+        let uri = &text_document.uri;
+        let hover = match self.with_spec_file(uri, |s| s.hover(position)) {
+            Some(hover) => hover,
+            None => self
+                .with_doc(uri, |r| {
+                    r.hover(position, |name| self.with_workspace(|w| w.requires_fn(name)))
+                })
+                .flatten(),
         };
-        let range = Range { start: pos, end: pos_end };
-        Ok(Some(Hover {
+        Ok(hover.map(|(doc, range)| Hover {
             // render markdown string
             contents: HoverContents::Markup(MarkupContent {
                 kind: MarkupKind::Markdown,
-                value: safety_doc,
+                value: doc,
             }),
             // possibly used to highlight text document in this range (no effect for neovim)
             range: Some(range),
@@ -129,17 +222,117 @@ impl LanguageServer for Backend {
 
 impl Backend {
     pub fn new(client: Client) -> Self {
-        Backend { client, rust: Mutex::new(Rust::new()) }
+        Backend {
+            client,
+            documents: Mutex::new(HashMap::new()),
+            spec_files: Mutex::new(HashMap::new()),
+            workspace: Mutex::new(Workspace::default()),
+            spec: Mutex::new(SpecPaths::default()),
+        }
+    }
+
+    fn with_documents<T>(&self, f: impl FnOnce(&mut HashMap<Uri, Rust>) -> T) -> T {
+        f(&mut self.documents.lock().unwrap())
+    }
+
+    /// None means the document is not opened.
+    fn with_doc<T>(&self, uri: &Uri, f: impl FnOnce(&mut Rust) -> T) -> Option<T> {
+        self.with_documents(|docs| docs.get_mut(uri).map(f))
     }
 
-    fn with_rust<T>(&self, f: impl FnOnce(&mut Rust) -> T) -> T {
-        f(&mut self.rust.lock().unwrap())
+    fn with_spec_files<T>(&self, f: impl FnOnce(&mut HashMap<Uri, SpecFile>) -> T) -> T {
+        f(&mut self.spec_files.lock().unwrap())
+    }
+
+    /// None means the spec file is not opened.
+    fn with_spec_file<T>(&self, uri: &Uri, f: impl FnOnce(&mut SpecFile) -> T) -> Option<T> {
+        self.with_spec_files(|files| files.get_mut(uri).map(f))
+    }
+
+    /// Canonical path of the file if it's a spec TOML.
+    fn spec_file_path(&self, uri: &Uri) -> Option<PathBuf> {
+        let path = uri.to_file_path()?;
+        if !self.spec.lock().unwrap().is_spec_file(&path) {
+            return None;
+        }
+        Some(fs::canonicalize(&path).unwrap_or_else(|_| path.into_owned()))
+    }
+
+    /// The tag name on the cursor in a Rust file or spec TOML file.
+    fn tag_at(&self, uri: &Uri, pos: Position) -> Option<String> {
+        match self.with_spec_file(uri, |s| s.tag_at(pos)) {
+            Some(name) => name,
+            None => self.with_doc(uri, |r| r.tag_at(pos)).flatten(),
+        }
     }
 
-    fn update_document(&self, text: String) {
-        self.with_rust(|r| {
-            _ = r.update_node_tree(text);
-            _ = r.find_attrs();
+    fn with_workspace<T>(&self, f: impl FnOnce(&mut Workspace) -> T) -> T {
+        f(&mut self.workspace.lock().unwrap())
+    }
+
+    /// Ask the client to notify changes on spec files.
+    async fn watch_spec(&self) {
+        let watchers = self.spec.lock().unwrap().watchers();
+        let options = DidChangeWatchedFilesRegistrationOptions { watchers };
+        let registration = Registration {
+            id: "safety-tool-spec".to_owned(),
+            method: "workspace/didChangeWatchedFiles".to_owned(),
+            register_options: serde_json::to_value(options).ok(),
+        };
+        if let Err(err) = self.client.register_capability(vec![registration]).await {
+            let message = format!("Failed to watch spec files: {err}");
+            self.client.log_message(MessageType::WARNING, message).await;
+        }
+    }
+
+    /// Reload spec, and refresh tags and diagnostics in opened documents. See [`load_spec`]
+    /// for errors.
+    async fn reload_spec(&self, initial: bool) {
+        let files = self.spec.lock().unwrap().toml_files();
+        if files.is_empty() {
+            // tags are not checked without spec
+            return;
+        }
+        match load_spec(&files, initial) {
+            Ok(count) => {
+                let message = format!("Loaded {count} tags from {}", files.join(", "));
+                self.client.log_message(MessageType::INFO, message).await;
+            }
+            Err(err) => self.client.show_message(MessageType::ERROR, err).await,
+        }
+
+        let diagnostics: Vec<_> = self.with_documents(|docs| {
+            docs.iter_mut()
+                .map(|(uri, r)| {
+                    r.refresh_tags();
+                    (uri.clone(), r.diagnostics())
+                })
+                .collect()
         });
+        for (uri, diagnostics) in diagnostics {
+            self.client.publish_diagnostics(uri, diagnostics, None).await;
+        }
+        // tags may be defined in other spec files now
+        let spec_files: Vec<_> = self.with_spec_files(|files| files.keys().cloned().collect());
+        for uri in spec_files {
+            self.update_spec_file(uri, None).await;
+        }
+        // tag types and requirements may change; errors mean the client doesn't support it
+        _ = self.client.semantic_tokens_refresh().await;
+        _ = self.client.inlay_hint_refresh().await;
+    }
+
+    /// Publish diagnostics and reindex tags after the document is parsed.
+    async fn update_document(&self, uri: Uri, version: i32) {
+        let updated = self.with_doc(&uri, |r| (r.diagnostics(), FileIndex::new(r)));
+        let Some((diagnostics, index)) = updated else { return };
+        self.with_workspace(|w| w.update(uri.clone(), index));
+        self.client.publish_diagnostics(uri, diagnostics, Some(version)).await;
+    }
+
+    /// Publish diagnostics of the spec file.
+    async fn update_spec_file(&self, uri: Uri, version: Option<i32>) {
+        let Some(diagnostics) = self.with_spec_file(&uri, |s| s.diagnostics()) else { return };
+        self.client.publish_diagnostics(uri, diagnostics, version).await;
     }
 }
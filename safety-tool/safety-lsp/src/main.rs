@@ -2,6 +2,9 @@
 
 pub mod backend;
 pub mod rust;
+pub mod spec;
+pub mod spec_file;
+pub mod workspace;
 
 #[tokio::main]
 async fn main() {
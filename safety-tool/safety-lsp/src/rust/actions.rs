@@ -0,0 +1,266 @@
+//! Code actions to discharge tags required by unsafe callees.
+use super::{Rust, byte_to_pos, pos_to_byte};
+use safety_parser::{
+    configuration::{ANY, env::without_check},
+    safety::{attr_name, parse_attr_and_get_properties},
+};
+use std::collections::HashMap;
+use tower_lsp_server::lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, Position, Range, TextEdit, Uri, WorkspaceEdit,
+};
+use tree_sitter::Node;
+
+/// A function annotated with `#[requires]`, whose tags must be discharged by callers.
+#[derive(Clone, Debug)]
+pub struct RequiresFn {
+    pub name: String,
+    /// Tags to be discharged all together.
+    pub tags: Vec<String>,
+    /// Alternatives in each `any` tag, one of which must be discharged.
+    pub any: Vec<Vec<String>>,
+}
+
+impl RequiresFn {
+    /// Candidate sets of tags to discharge by picking one alternative in each `any` tag.
+    fn discharges(&self) -> Vec<Vec<String>> {
+        let mut candidates = vec![self.tags.clone()];
+        for alternatives in &self.any {
+            let product = candidates.iter().flat_map(|tags| {
+                alternatives.iter().map(|alt| tags.iter().chain([alt]).cloned().collect())
+            });
+            candidates = product.take(MAX_CANDIDATES).collect();
+        }
+        candidates
+    }
+}
+
+/// Limit quick-fixes from combinations of alternatives in `any` tags.
+const MAX_CANDIDATES: usize = 8;
+
+/// Placeholder of the reason to be filled in by users.
+const TODO: &str = "TODO";
+
+impl Rust {
+    /// Functions annotated with `#[requires]` in the document.
+    pub fn requires_fns(&self) -> Vec<RequiresFn> {
+        let Some(tree) = &self.tree else { return Vec::new() };
+        let mut fns = Vec::new();
+        for attr in &self.attrs {
+            let src = &self.text[attr.byte_range.clone()];
+            if attr_name(src).as_deref() != Some("requires") {
+                continue;
+            }
+            let range = attr.byte_range.clone();
+            let Some(node) = tree.root_node().descendant_for_byte_range(range.start, range.end)
+            else {
+                continue;
+            };
+            let Some(name) = self.annotated_fn_name(node) else { continue };
+
+            let mut tags = Vec::new();
+            let mut any = Vec::new();
+            let props = without_check(|| parse_attr_and_get_properties(src));
+            for prop in props.iter().flat_map(|props| &props.tags) {
+                if prop.tag.name() == ANY {
+                    any.push(prop.args_as_string().into_vec());
+                } else if prop.args.is_empty() {
+                    tags.push(prop.tag.name().to_owned());
+                } else {
+                    tags.push(format!("{}({})", prop.tag.name(), prop.args_as_string().join(", ")));
+                }
+            }
+            fns.push(RequiresFn { name, tags, any });
+        }
+        fns
+    }
+
+    /// Name of the function following the attribute, skipping other attributes and comments.
+    fn annotated_fn_name(&self, attr: Node) -> Option<String> {
+        let mut node = attr.next_named_sibling();
+        while let Some(item) = node {
+            match item.kind() {
+                "attribute_item" | "line_comment" | "block_comment" => {
+                    node = item.next_named_sibling()
+                }
+                "function_item" | "function_signature_item" => {
+                    return Some(self.node_text(item.child_by_field_name("name")?).to_owned());
+                }
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    /// Code actions at the cursor:
+    /// * in an unsafe block calling a function annotated with `#[requires]`, insert
+    ///   `#[safety::checked]` before the statement with the callee's tags
+    /// * on a `// SAFETY:` comment, convert it into a `#[safety::checked]` attribute
+    pub fn code_actions(
+        &self,
+        uri: &Uri,
+        pos: Position,
+        requires_fn: impl Fn(&str) -> Option<RequiresFn>,
+    ) -> Vec<CodeActionOrCommand> {
+        let Some(node) = self.node_at(pos) else { return Vec::new() };
+        let mut actions = Vec::new();
+
+        if let Some(comment) = ancestor(node, "line_comment") {
+            actions.extend(self.convert_safety_comment(uri, comment, &requires_fn));
+            return actions;
+        }
+
+        let Some(unsafe_block) = ancestor(node, "unsafe_block") else { return actions };
+        let Some(callee) = self.unsafe_callee(unsafe_block) else { return actions };
+        let Some(requires) = requires_fn(&callee) else { return actions };
+        let Some(stmt) = statement(unsafe_block) else { return actions };
+        if self.has_checked_attr(stmt) {
+            return actions;
+        }
+
+        let start = byte_to_pos(stmt.start_byte(), &self.rope);
+        let indent = " ".repeat(start.character as usize);
+        for tags in requires.discharges() {
+            let attr = checked_attr(&tags, TODO);
+            let title = format!("Discharge `{}` required by `{callee}`", tags.join(", "));
+            let edit = TextEdit::new(Range::new(start, start), format!("{attr}\n{indent}"));
+            actions.push(quick_fix(title, uri, edit, CodeActionKind::QUICKFIX));
+        }
+        actions
+    }
+
+    /// Contiguous line comments starting from `// SAFETY:` are replaced by an attribute
+    /// with the comment as the reason. Tags are those required by the unsafe callee in the
+    /// following statement if any.
+    fn convert_safety_comment(
+        &self,
+        uri: &Uri,
+        comment: Node,
+        requires_fn: &impl Fn(&str) -> Option<RequiresFn>,
+    ) -> Option<CodeActionOrCommand> {
+        let text = self.node_text(comment).trim_start_matches('/').trim_start();
+        let first = text.strip_prefix("SAFETY:")?.trim();
+
+        let mut reason = vec![first];
+        let mut last = comment;
+        let mut next = comment.next_named_sibling();
+        while let Some(node) = next.filter(|node| {
+            node.kind() == "line_comment"
+                && node.start_position().row == last.start_position().row + 1
+        }) {
+            reason.push(self.node_text(node).trim_start_matches('/').trim());
+            last = node;
+            next = node.next_named_sibling();
+        }
+        let reason = reason.into_iter().filter(|s| !s.is_empty()).collect::<Vec<_>>().join(" ");
+
+        let requires = next
+            .and_then(|stmt| find_descendant(stmt, "unsafe_block"))
+            .and_then(|block| self.unsafe_callee(block))
+            .and_then(|callee| requires_fn(&callee));
+        let tags = match requires.and_then(|r| r.discharges().into_iter().next()) {
+            Some(tags) if !tags.is_empty() => tags,
+            _ => vec![TODO.to_owned()],
+        };
+
+        // line comments may end with the newline, which is kept
+        let end = last.start_byte() + self.node_text(last).trim_end().len();
+        let range =
+            Range::new(byte_to_pos(comment.start_byte(), &self.rope), byte_to_pos(end, &self.rope));
+        let edit = TextEdit::new(range, checked_attr(&tags, &reason));
+        let title = "Convert SAFETY comment into `#[safety::checked]`".to_owned();
+        Some(quick_fix(title, uri, edit, CodeActionKind::REFACTOR_REWRITE))
+    }
+
+    /// Name of the first function called in the unsafe block.
+    pub(super) fn unsafe_callee(&self, unsafe_block: Node) -> Option<String> {
+        self.callee_name(find_descendant(unsafe_block, "call_expression")?)
+    }
+
+    /// Name of the function called: the last segment of a path, or the method name.
+    pub(super) fn callee_name(&self, call: Node) -> Option<String> {
+        let mut func = call.child_by_field_name("function")?;
+        if func.kind() == "generic_function" {
+            func = func.child_by_field_name("function")?;
+        }
+        let name = match func.kind() {
+            "identifier" => func,
+            "scoped_identifier" => func.child_by_field_name("name")?,
+            "field_expression" => func.child_by_field_name("field")?,
+            _ => return None,
+        };
+        Some(self.node_text(name).to_owned())
+    }
+
+    fn has_checked_attr(&self, stmt: Node) -> bool {
+        let mut node = stmt.prev_named_sibling();
+        while let Some(attr) = node.filter(|node| node.kind() == "attribute_item") {
+            if attr_name(self.node_text(attr)).as_deref() == Some("checked") {
+                return true;
+            }
+            node = attr.prev_named_sibling();
+        }
+        false
+    }
+
+    pub(super) fn node_at(&self, pos: Position) -> Option<Node<'_>> {
+        let byte = pos_to_byte(pos, &self.rope);
+        self.tree.as_ref()?.root_node().descendant_for_byte_range(byte, byte)
+    }
+
+    pub(super) fn node_text(&self, node: Node) -> &str {
+        &self.text[node.byte_range()]
+    }
+}
+
+fn ancestor<'a>(node: Node<'a>, kind: &str) -> Option<Node<'a>> {
+    let mut node = Some(node);
+    while let Some(n) = node {
+        if n.kind() == kind {
+            return Some(n);
+        }
+        node = n.parent();
+    }
+    None
+}
+
+/// Depth-first search for the first descendant of the kind.
+pub(super) fn find_descendant<'a>(node: Node<'a>, kind: &str) -> Option<Node<'a>> {
+    if node.kind() == kind {
+        return Some(node);
+    }
+    let mut cursor = node.walk();
+    node.named_children(&mut cursor).find_map(|child| find_descendant(child, kind))
+}
+
+/// The statement or item directly in a block containing the node.
+fn statement(node: Node) -> Option<Node> {
+    let mut node = node;
+    loop {
+        let parent = node.parent()?;
+        if matches!(parent.kind(), "block" | "source_file" | "declaration_list") {
+            return Some(node);
+        }
+        node = parent;
+    }
+}
+
+fn checked_attr(tags: &[String], reason: &str) -> String {
+    format!("#[safety::checked {{ {}: {reason:?} }}]", tags.join(", "))
+}
+
+// `Uri` is the key of changes in `WorkspaceEdit`, though it's considered mutable.
+#[allow(clippy::mutable_key_type)]
+fn quick_fix(
+    title: String,
+    uri: &Uri,
+    edit: TextEdit,
+    kind: CodeActionKind,
+) -> CodeActionOrCommand {
+    let changes = HashMap::from([(uri.clone(), vec![edit])]);
+    CodeActionOrCommand::CodeAction(CodeAction {
+        title,
+        kind: Some(kind),
+        edit: Some(WorkspaceEdit { changes: Some(changes), ..Default::default() }),
+        ..Default::default()
+    })
+}
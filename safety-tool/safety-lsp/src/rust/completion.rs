@@ -0,0 +1,201 @@
+//! Completion of tags depending on where the cursor is in a safety attribute.
+use super::{
+    Rust,
+    actions::{RequiresFn, find_descendant},
+    byte_to_pos, pos_to_byte,
+};
+use safety_parser::{
+    configuration::{ANY, DefinedTag, TagType},
+    safety::{attr_name, is_safety_attr},
+};
+use tower_lsp_server::lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionItemLabelDetails, Documentation,
+    InsertTextFormat, MarkupContent, MarkupKind, Position, Range, TextEdit,
+};
+use tree_sitter::Node;
+
+const TAG_TYPES: [TagType; 3] = [TagType::Precond, TagType::Hazard, TagType::Option];
+
+/// Where the cursor is in a safety attribute.
+#[derive(Debug)]
+struct Context {
+    /// The word being typed, to be replaced by the completion.
+    range: Range,
+    prefix: String,
+    /// Type in `type.Tag` typed before the word.
+    typ: Option<TagType>,
+    /// Only tags required by the callee are suggested in a `checked` attribute.
+    callee: Option<String>,
+}
+
+impl Rust {
+    /// Tags and tag types to complete at the cursor, which must be at the position of a tag
+    /// in a safety attribute, i.e. not in the path, args of a tag except `any`, or reasons.
+    pub fn completion(
+        &self,
+        pos: Position,
+        requires_fn: impl Fn(&str) -> Option<RequiresFn>,
+    ) -> Option<Vec<CompletionItem>> {
+        let cx = self.completion_context(pos)?;
+        let required = cx.callee.as_deref().and_then(requires_fn).map(|f| required_tags(&f));
+
+        let mut items = Vec::new();
+        if cx.typ.is_none() {
+            for typ in TAG_TYPES.iter().map(|typ| typ.as_str()) {
+                if typ.starts_with(&cx.prefix) {
+                    items.push(type_item(typ, cx.range));
+                }
+            }
+        }
+        for tag in &self.tags {
+            if !tag.name.starts_with(&cx.prefix)
+                || cx.typ.is_some_and(|typ| !tag.args.types.contains(&typ))
+                || required.as_ref().is_some_and(|names| !names.iter().any(|n| *n == *tag.name))
+            {
+                continue;
+            }
+            items.push(tag_item(tag, cx.range));
+        }
+        Some(items)
+    }
+
+    fn completion_context(&self, pos: Position) -> Option<Context> {
+        let attr_range = self.get_attr_range(pos)?;
+        let src = &self.text[attr_range.clone()];
+        if !is_safety_attr(src) {
+            return None;
+        }
+        let cursor = pos_to_byte(pos, &self.rope).checked_sub(attr_range.start)?;
+        let before = src.get(..cursor)?;
+        if !at_tag_position(before) {
+            return None;
+        }
+
+        let prefix_start = before.trim_end_matches(is_ident_char).len();
+        let prefix = before[prefix_start..].to_owned();
+        let typ = before[..prefix_start].strip_suffix('.').and_then(|before_dot| {
+            let typ = &before_dot[before_dot.trim_end_matches(is_ident_char).len()..];
+            TagType::try_new(typ)
+        });
+
+        let callee = if attr_name(src).as_deref() == Some("checked") {
+            self.checked_callee(attr_range.start)
+        } else {
+            None
+        };
+        let range = Range::new(byte_to_pos(attr_range.start + prefix_start, &self.rope), pos);
+        Some(Context { range, prefix, typ, callee })
+    }
+
+    /// Callee in the unsafe block following the `checked` attribute starting at the byte.
+    fn checked_callee(&self, attr_start: usize) -> Option<String> {
+        let root = self.tree.as_ref()?.root_node();
+        let attr = root.descendant_for_byte_range(attr_start, attr_start)?;
+        let attr = ancestor_attr(attr)?;
+        let mut node = attr.next_named_sibling();
+        while let Some(stmt) = node {
+            if matches!(stmt.kind(), "attribute_item" | "line_comment" | "block_comment") {
+                node = stmt.next_named_sibling();
+                continue;
+            }
+            return self.unsafe_callee(find_descendant(stmt, "unsafe_block")?);
+        }
+        None
+    }
+}
+
+fn ancestor_attr(mut node: Node) -> Option<Node> {
+    while node.kind() != "attribute_item" {
+        node = node.parent()?;
+    }
+    Some(node)
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Whether the text before the cursor in an attribute ends at the position of a tag:
+/// delimiters are all opened by the attribute itself or `any` tags, and not in a string.
+fn at_tag_position(before: &str) -> bool {
+    let after_hash = before.strip_prefix("#[").unwrap_or(before);
+    let offset = before.len() - after_hash.len();
+    let mut openers = Vec::new();
+    let mut word_start = offset;
+    let mut in_str = false;
+    let mut escaped = false;
+    for (idx, c) in after_hash.char_indices() {
+        if in_str {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_str = false,
+                _ => (),
+            }
+            continue;
+        }
+        match c {
+            '"' => in_str = true,
+            '(' | '{' | '[' => openers.push(&before[word_start..offset + idx]),
+            ')' | '}' | ']' => _ = openers.pop(),
+            c if is_ident_char(c) || c == ':' || c == '.' => continue,
+            _ => (),
+        }
+        word_start = offset + idx + c.len_utf8();
+    }
+    // the first delimiter is opened by the attribute, and others must be by `any` tags
+    !in_str
+        && !openers.is_empty()
+        && openers[1..].iter().all(|opener| opener.trim() == ANY)
+        // a reason follows `:`, while `::` is in paths
+        && !before.trim_end_matches(is_ident_char).trim_end().ends_with(':')
+}
+
+/// Names of tags required by the callee, including alternatives in `any` tags.
+fn required_tags(f: &RequiresFn) -> Vec<String> {
+    let tags = f.tags.iter().chain(f.any.iter().flatten());
+    tags.map(|tag| tag.split('(').next().unwrap_or(tag).trim().to_owned()).collect()
+}
+
+fn type_item(typ: &str, range: Range) -> CompletionItem {
+    CompletionItem {
+        label: format!("{typ}."),
+        label_details: Some(CompletionItemLabelDetails {
+            detail: None,
+            description: Some("(tag type)".to_owned()),
+        }),
+        kind: Some(CompletionItemKind::KEYWORD),
+        text_edit: Some(TextEdit::new(range, format!("{typ}.")).into()),
+        ..Default::default()
+    }
+}
+
+fn tag_item(tag: &DefinedTag, range: Range) -> CompletionItem {
+    CompletionItem {
+        label: tag.name.to_string(),
+        label_details: Some(CompletionItemLabelDetails {
+            detail: None,
+            // inline desc left-aligned
+            description: Some("(safety tag)".to_owned()),
+        }),
+        kind: Some(CompletionItemKind::PROPERTY),
+        detail: Some(tag.hover_detail()),
+        documentation: Some(Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: tag.hover_documentation(),
+        })),
+        insert_text_format: Some(InsertTextFormat::SNIPPET),
+        text_edit: Some(TextEdit::new(range, tag_snippet(tag)).into()),
+        ..Default::default()
+    }
+}
+
+/// Tag with a tab stop for each argument, e.g. `Align(${1:p}, ${2:T})`.
+fn tag_snippet(tag: &DefinedTag) -> String {
+    let args = &tag.args.args;
+    if args.is_empty() {
+        return tag.name.to_string();
+    }
+    let args = args.iter().enumerate().map(|(idx, arg)| format!("${{{}:{arg}}}", idx + 1));
+    format!("{}({})", tag.name, args.collect::<Vec<_>>().join(", "))
+}
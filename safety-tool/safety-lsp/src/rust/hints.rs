@@ -0,0 +1,73 @@
+//! Inlay hints of undischarged tags in unsafe blocks.
+use super::{Rust, actions::RequiresFn, byte_to_pos};
+use tower_lsp_server::lsp_types::{InlayHint, InlayHintLabel, Range};
+use tree_sitter::Node;
+
+impl Rust {
+    /// Hints after `unsafe {` listing tags required by the callee but not discharged by
+    /// enclosing `checked` attributes.
+    pub fn inlay_hints(
+        &self,
+        range: Range,
+        requires_fn: impl Fn(&str) -> Option<RequiresFn>,
+    ) -> Vec<InlayHint> {
+        let Some(tree) = &self.tree else { return Vec::new() };
+        let mut blocks = Vec::new();
+        unsafe_blocks(tree.root_node(), &mut blocks);
+
+        let mut hints = Vec::new();
+        for unsafe_block in blocks {
+            let mut cursor = unsafe_block.walk();
+            let Some(block) =
+                unsafe_block.named_children(&mut cursor).find(|n| n.kind() == "block")
+            else {
+                continue;
+            };
+            // right after `{`
+            let position = byte_to_pos(block.start_byte() + 1, &self.rope);
+            if position < range.start || position > range.end {
+                continue;
+            }
+            let callee = self.unsafe_callee(unsafe_block);
+            let Some(requires) = callee.and_then(|callee| requires_fn(&callee)) else { continue };
+
+            let discharged = self.discharged_tags(unsafe_block);
+            let is_discharged = |tag: &String| {
+                let name = tag.split('(').next().unwrap_or(tag).trim();
+                discharged.iter().any(|d| d == name)
+            };
+            let mut undischarged: Vec<_> =
+                requires.tags.iter().filter(|tag| !is_discharged(tag)).cloned().collect();
+            for alternatives in &requires.any {
+                if !alternatives.iter().any(is_discharged) {
+                    undischarged.push(format!("any({})", alternatives.join(", ")));
+                }
+            }
+            if undischarged.is_empty() {
+                continue;
+            }
+
+            hints.push(InlayHint {
+                position,
+                label: InlayHintLabel::String(format!("undischarged: {}", undischarged.join(", "))),
+                kind: None,
+                text_edits: None,
+                tooltip: None,
+                padding_left: Some(true),
+                padding_right: Some(true),
+                data: None,
+            });
+        }
+        hints
+    }
+}
+
+fn unsafe_blocks<'a>(node: Node<'a>, blocks: &mut Vec<Node<'a>>) {
+    if node.kind() == "unsafe_block" {
+        blocks.push(node);
+    }
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        unsafe_blocks(child, blocks);
+    }
+}
@@ -0,0 +1,155 @@
+//! Hover docs on safety attributes, tags in them, and unsafe calls.
+use super::{Rust, actions::RequiresFn, byte_to_pos, span_to_range};
+use safety_parser::{
+    configuration::env::without_check,
+    safety::{
+        attr_name, is_safety_attr, parse_attr_and_flatten_properties,
+        parse_attr_and_get_properties, parse_attr_and_get_tags,
+    },
+};
+use std::fmt::Write;
+use tower_lsp_server::lsp_types::{Position, Range};
+use tree_sitter::Node;
+
+impl Rust {
+    /// Markdown doc and the range it applies to:
+    /// * on a tag, the spec of the tag with args interpolated
+    /// * elsewhere in a safety attribute, docs of all tags in it
+    /// * on an unsafe call, tags required by the callee, and whether they're discharged
+    pub fn hover(
+        &self,
+        pos: Position,
+        requires_fn: impl Fn(&str) -> Option<RequiresFn>,
+    ) -> Option<(String, Range)> {
+        if let Some(range) = self.get_attr_range(pos) {
+            let src = &self.text[range.clone()];
+            if !is_safety_attr(src) {
+                return None;
+            }
+            let start = byte_to_pos(range.start, &self.rope);
+            return self.hover_tag(src, start, pos).or_else(|| {
+                let end = byte_to_pos(range.end, &self.rope);
+                hover_attr(src).map(|doc| (doc, Range::new(start, end)))
+            });
+        }
+        self.hover_unsafe_call(pos, requires_fn)
+    }
+
+    fn hover_tag(
+        &self,
+        attr: &str,
+        attr_start: Position,
+        pos: Position,
+    ) -> Option<(String, Range)> {
+        let found = parse_attr_and_flatten_properties(attr).into_iter().find_map(|prop| {
+            let range = span_to_range(prop.tag.span()?, attr_start);
+            (range.start <= pos && pos <= range.end).then_some((prop, range))
+        });
+        // Release spans whether the cursor is on a tag or not.
+        proc_macro2::extra::invalidate_current_thread_spans();
+        let (prop, range) = found?;
+
+        let name = prop.tag.name();
+        let mut doc = String::new();
+        if prop.args.is_empty() {
+            _ = writeln!(doc, "# {name}\n");
+        } else {
+            _ = writeln!(doc, "# {name}({})\n", prop.args_as_string().join(", "));
+        }
+        let Some(tag) = self.tags.iter().find(|tag| &*tag.name == name) else {
+            _ = writeln!(doc, "Tag `{name}` is not defined in spec.");
+            return Some((doc, range));
+        };
+        if let Some(desc) = without_check(|| prop.gen_doc_with(&tag.args)) {
+            doc.push_str(desc.trim_end());
+            doc.push_str("\n\n---\n\n");
+        }
+        doc.push_str(&tag.hover_documentation());
+        Some((doc, range))
+    }
+
+    fn hover_unsafe_call(
+        &self,
+        pos: Position,
+        requires_fn: impl Fn(&str) -> Option<RequiresFn>,
+    ) -> Option<(String, Range)> {
+        let node = self.node_at(pos)?;
+        let call = ancestor_call(node)?;
+        let callee = self.callee_name(call)?;
+        let requires = requires_fn(&callee)?;
+        let discharged = self.discharged_tags(call);
+
+        let is_discharged = |tag: &String| {
+            let name = tag.split('(').next().unwrap_or(tag).trim();
+            discharged.iter().any(|d| d == name)
+        };
+        let mark = |done: bool| if done { "x" } else { " " };
+
+        let mut doc = format!("# Safety Requirements of `{callee}`\n\n");
+        for tag in &requires.tags {
+            _ = writeln!(doc, "- [{}] {tag}", mark(is_discharged(tag)));
+        }
+        for alternatives in &requires.any {
+            let done = alternatives.iter().any(is_discharged);
+            _ = writeln!(doc, "- [{}] any({})", mark(done), alternatives.join(", "));
+        }
+        let range = Range::new(
+            byte_to_pos(call.start_byte(), &self.rope),
+            byte_to_pos(call.end_byte(), &self.rope),
+        );
+        Some((doc, range))
+    }
+
+    /// Tags discharged by `checked` attributes on the node and its ancestors.
+    pub(super) fn discharged_tags(&self, node: Node) -> Vec<String> {
+        let mut tags = Vec::new();
+        let mut node = Some(node);
+        while let Some(n) = node {
+            let mut prev = n.prev_named_sibling();
+            while let Some(attr) = prev.filter(|prev| {
+                matches!(prev.kind(), "attribute_item" | "line_comment" | "block_comment")
+            }) {
+                let src = self.node_text(attr);
+                if attr.kind() == "attribute_item" && attr_name(src).as_deref() == Some("checked") {
+                    tags.extend(parse_attr_and_get_tags(src).iter().map(|t| t.name().to_owned()));
+                }
+                prev = attr.prev_named_sibling();
+            }
+            node = n.parent();
+        }
+        proc_macro2::extra::invalidate_current_thread_spans();
+        tags
+    }
+}
+
+/// The call expression containing the node in an unsafe block.
+fn ancestor_call(node: Node) -> Option<Node> {
+    let mut node = Some(node);
+    let mut call = None;
+    while let Some(n) = node {
+        match n.kind() {
+            "call_expression" if call.is_none() => call = Some(n),
+            "unsafe_block" => return call,
+            // calls in nested functions or closures aren't in the unsafe block
+            "function_item" | "closure_expression" => return None,
+            _ => (),
+        }
+        node = n.parent();
+    }
+    None
+}
+
+/// Docs of all tags in the attribute.
+fn hover_attr(attr: &str) -> Option<String> {
+    let (props, mut doc) = without_check(|| {
+        let props = parse_attr_and_get_properties(attr);
+        let doc = props.iter().map(|props| props.gen_hover_doc()).collect::<Vec<_>>();
+        (props, doc.join("\n"))
+    });
+    match props.iter().map(|props| props.tags.len()).sum::<usize>() {
+        0 => return None,
+        1 => doc.insert_str(0, "# Safety Requirement\n\n"),
+        _ => doc.insert_str(0, "# Safety Requirements\n\n"),
+    }
+    Some(doc)
+}
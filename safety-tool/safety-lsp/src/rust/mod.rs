@@ -1,13 +1,40 @@
+use proc_macro2::LineColumn;
 use ropey::Rope;
-use safety_parser::configuration::{DefinedTag, get_tags};
-use tower_lsp_server::lsp_types::Position;
-use tree_sitter::{Parser, Tree};
+use safety_parser::{
+    configuration::{DefinedTag, TagType, get_tags},
+    safety::{is_safety_attr, parse_attr_and_get_tags, validate_attr},
+};
+use tower_lsp_server::lsp_types::{
+    Diagnostic, DiagnosticSeverity, Position, Range, TextDocumentContentChangeEvent,
+};
+use tree_sitter::{InputEdit, Parser, Point, Tree};
 
 type ByteRange = std::ops::Range<usize>;
 
+mod actions;
+pub use actions::RequiresFn;
+
+mod completion;
+
+mod hover;
+
+mod hints;
+
+mod tokens;
+pub use tokens::legend;
+
 #[cfg(test)]
 mod tests;
 
+/// A tag used in a safety attribute.
+#[derive(Clone, Debug)]
+pub struct TagUsage {
+    pub name: String,
+    /// Type in `type.Tag`.
+    pub typ: Option<TagType>,
+    pub range: Range,
+}
+
 #[derive(Debug)]
 struct Attr {
     byte_range: ByteRange,
@@ -41,13 +68,63 @@ impl Rust {
     }
 
     pub fn update_node_tree(&mut self, text: String) -> String {
-        self.tree = self.parser.parse(&text, None);
-        self.rope = Rope::from_str(&text);
-        self.text = text;
+        self.set_text(text);
         let tree = self.tree.as_ref().unwrap();
         format!("text={:?}\ntree={tree:?}\nroot_node={}", self.text, tree.root_node())
     }
 
+    /// Replace the whole text and parse it from scratch.
+    pub fn set_text(&mut self, text: String) {
+        self.rope = Rope::from_str(&text);
+        self.text = text;
+        self.tree = None;
+        self.reparse();
+    }
+
+    /// Apply changes in order: the whole text is replaced if a change has no range,
+    /// otherwise the range is replaced and the syntax tree is reparsed incrementally.
+    pub fn apply_changes(&mut self, changes: Vec<TextDocumentContentChangeEvent>) {
+        for change in changes {
+            match change.range {
+                Some(range) => self.edit(range, &change.text),
+                None => {
+                    self.rope = Rope::from_str(&change.text);
+                    self.text = change.text;
+                    self.tree = None;
+                }
+            }
+        }
+        self.reparse();
+    }
+
+    fn edit(&mut self, range: Range, new_text: &str) {
+        let rope = &mut self.rope;
+        let start_byte = pos_to_byte(range.start, rope);
+        let old_end_byte = pos_to_byte(range.end, rope).max(start_byte);
+        let start_position = byte_to_point(start_byte, rope);
+        let old_end_position = byte_to_point(old_end_byte, rope);
+
+        replace_range(rope, &mut self.text, start_byte..old_end_byte, new_text);
+
+        let new_end_byte = start_byte + new_text.len();
+        if let Some(tree) = &mut self.tree {
+            tree.edit(&InputEdit {
+                start_byte,
+                old_end_byte,
+                new_end_byte,
+                start_position,
+                old_end_position,
+                new_end_position: byte_to_point(new_end_byte, rope),
+            });
+        }
+    }
+
+    /// Parse the text, reusing unchanged nodes in the old tree if any.
+    fn reparse(&mut self) {
+        self.tree = self.parser.parse(&self.text, self.tree.as_ref());
+        self.find_attrs();
+    }
+
     fn push_attr(&mut self, node: tree_sitter::Node, v: &mut Vec<String>) {
         if node.grammar_name() == "attribute_item" {
             let range = node.byte_range();
@@ -119,6 +196,62 @@ impl Rust {
         None
     }
 
+    /// Problems in safety attributes, such as undefined tags and wrong syntax.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        for attr in &self.attrs {
+            let src = &self.text[attr.byte_range.clone()];
+            if !is_safety_attr(src) {
+                continue;
+            }
+            for problem in validate_attr(src) {
+                diagnostics.push(Diagnostic {
+                    range: span_to_range(problem.span, attr.start_pos),
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    source: Some("safety-tool".to_owned()),
+                    message: problem.message,
+                    ..Default::default()
+                });
+            }
+        }
+        // Spans are no longer used, thus release the source map growing with each parsing.
+        proc_macro2::extra::invalidate_current_thread_spans();
+        diagnostics
+    }
+
+    /// Tags in safety attributes, including those in `any` tags.
+    pub fn tag_usages(&self) -> Vec<TagUsage> {
+        let mut usages = Vec::new();
+        for attr in &self.attrs {
+            let src = &self.text[attr.byte_range.clone()];
+            if !is_safety_attr(src) {
+                continue;
+            }
+            for tag in parse_attr_and_get_tags(src) {
+                let Some(span) = tag.span() else { continue };
+                let range = span_to_range(span, attr.start_pos);
+                usages.push(TagUsage { name: tag.name().to_owned(), typ: tag.typ(), range });
+            }
+        }
+        proc_macro2::extra::invalidate_current_thread_spans();
+        usages
+    }
+
+    /// Returns the tag name if the cursor is on a tag in a safety attribute.
+    pub fn tag_at(&self, pos: Position) -> Option<String> {
+        self.get_attr_range(pos)?;
+        let usage = self.tag_usages().into_iter().find(|usage| {
+            let Range { start, end } = usage.range;
+            start <= pos && pos <= end
+        })?;
+        Some(usage.name)
+    }
+
+    /// Take tags from the current spec, e.g. after it's reloaded.
+    pub fn refresh_tags(&mut self) {
+        self.tags = get_tags();
+    }
+
     pub fn for_each_tag<T>(&self, f: impl Fn(&DefinedTag) -> T) -> Vec<T> {
         self.tags.iter().map(f).collect()
     }
@@ -130,8 +263,64 @@ fn init_tree_sitter() -> Parser {
     parser
 }
 
-fn byte_to_pos(byte: usize, rope: &Rope) -> Position {
+/// Replace the byte range in both the rope and the string of the same text.
+pub(crate) fn replace_range(rope: &mut Rope, text: &mut String, range: ByteRange, new_text: &str) {
+    let start_char = rope.byte_to_char(range.start);
+    rope.remove(start_char..rope.byte_to_char(range.end));
+    rope.insert(start_char, new_text);
+    text.replace_range(range, new_text);
+}
+
+/// Position in UTF-16 code units, which is the default position encoding in LSP.
+pub(crate) fn byte_to_pos(byte: usize, rope: &Rope) -> Position {
     let line = rope.byte_to_line(byte);
-    let character = byte - rope.line_to_byte(line);
+    let line_start = rope.char_to_utf16_cu(rope.line_to_char(line));
+    let character = rope.char_to_utf16_cu(rope.byte_to_char(byte)) - line_start;
     Position { line: line as u32, character: character as u32 }
 }
+
+/// Positions beyond the end of line or text are clamped.
+pub(crate) fn pos_to_byte(pos: Position, rope: &Rope) -> usize {
+    let line = pos.line as usize;
+    if line >= rope.len_lines() {
+        return rope.len_bytes();
+    }
+    let line_start = rope.char_to_utf16_cu(rope.line_to_char(line));
+    let line_len = {
+        let line = rope.line(line);
+        let eol = line
+            .chars_at(line.len_chars())
+            .reversed()
+            .take_while(|c| matches!(c, '\n' | '\r'))
+            .count();
+        line.len_utf16_cu() - eol
+    };
+    let cu = line_start + (pos.character as usize).min(line_len);
+    rope.char_to_byte(rope.utf16_cu_to_char(cu))
+}
+
+/// Point in tree-sitter, whose column is in bytes.
+fn byte_to_point(byte: usize, rope: &Rope) -> Point {
+    let row = rope.byte_to_line(byte);
+    Point { row, column: byte - rope.line_to_byte(row) }
+}
+
+/// Span is relative to the attribute starting at `attr_start`.
+fn span_to_range(span: proc_macro2::Span, attr_start: Position) -> Range {
+    Range {
+        start: line_column_to_pos(span.start(), attr_start),
+        end: line_column_to_pos(span.end(), attr_start),
+    }
+}
+
+/// Line and column are relative to the attribute starting at `attr_start`.
+/// Line is 1-indexed, and column is 0-indexed.
+fn line_column_to_pos(lc: LineColumn, attr_start: Position) -> Position {
+    let line = lc.line.saturating_sub(1) as u32;
+    let character = lc.column as u32;
+    if line == 0 {
+        Position { line: attr_start.line, character: attr_start.character + character }
+    } else {
+        Position { line: attr_start.line + line, character }
+    }
+}
@@ -1,4 +1,5 @@
-use super::Rust;
+use super::{RequiresFn, Rust};
+use tower_lsp_server::lsp_types::Position;
 
 #[test]
 fn plain1() {
@@ -59,3 +60,382 @@ fn expr2() {
     let attrs = dbg!(rust.find_attrs());
     assert_eq!(attrs.len(), 2);
 }
+
+#[test]
+fn diagnostics() {
+    let _lock = crate::spec::spec_lock();
+    let src = "#[derive(Debug)] struct S;\n\
+               #[safety::requires(SP)] fn f() {}\n\
+               #[safety::requires(unknown.SP)] fn g() {}\n\
+               fn h() {\n    #[safety::checked(SP: reason)]\n    f();\n}";
+    let mut rust = Rust::new();
+
+    rust.update_node_tree(src.to_owned());
+    rust.find_attrs();
+    // `SP` is reported as undefined only when a spec is loaded from the environment
+    let diagnostics: Vec<_> = rust
+        .diagnostics()
+        .into_iter()
+        .filter(|d| !d.message.ends_with("is not defined in spec."))
+        .collect();
+    let messages: Vec<_> = diagnostics.iter().map(|d| &*d.message).collect();
+    assert_eq!(
+        messages,
+        [
+            "Tag type `unknown` is not one of precond, hazard, and option.",
+            "expected string literal"
+        ]
+    );
+
+    // `unknown` in the third line
+    let range = diagnostics[0].range;
+    assert_eq!((range.start.line, range.start.character), (2, 19));
+    assert_eq!((range.end.line, range.end.character), (2, 26));
+
+    // `reason` in the fifth line
+    let range = diagnostics[1].range;
+    assert_eq!((range.start.line, range.start.character), (4, 26));
+}
+
+#[test]
+fn tag_usages() {
+    let src = "#[safety::requires(SP1, any(SP2, hazard.SP3))] fn f() {}\n\
+               #[derive(Debug)] struct S;\n\
+               fn g() {\n    #[safety::checked(SP1: \"reason\")]\n    unsafe { f() }\n}";
+    let mut rust = Rust::new();
+
+    rust.update_node_tree(src.to_owned());
+    rust.find_attrs();
+    let usages = rust.tag_usages();
+    let names: Vec<_> = usages.iter().map(|usage| &*usage.name).collect();
+    assert_eq!(names, ["SP1", "SP2", "SP3", "SP1"]);
+    let range = usages[3].range;
+    assert_eq!((range.start.line, range.start.character), (3, 22));
+    assert_eq!((range.end.line, range.end.character), (3, 25));
+
+    let pos = |line, character| Position { line, character };
+    assert_eq!(rust.tag_at(pos(0, 41)).as_deref(), Some("SP3"));
+    assert_eq!(rust.tag_at(pos(3, 24)).as_deref(), Some("SP1"));
+    assert_eq!(rust.tag_at(pos(0, 3)), None);
+    assert_eq!(rust.tag_at(pos(1, 4)), None);
+}
+
+#[test]
+fn workspace_references() {
+    use crate::workspace::{FileIndex, Workspace};
+    use tower_lsp_server::lsp_types::Uri;
+
+    let mut workspace = Workspace::default();
+    let mut rust = Rust::new();
+    for (uri, src) in [
+        ("file:///a.rs", "#[safety::requires(SP1, SP2)] fn f() {}"),
+        ("file:///b.rs", "fn g() { #[safety::checked(SP2)] unsafe { f() } }"),
+    ] {
+        rust.update_node_tree(src.to_owned());
+        rust.find_attrs();
+        workspace.update(uri.parse::<Uri>().unwrap(), FileIndex::new(&rust));
+    }
+
+    let uris = |name| -> Vec<_> {
+        workspace.references(name).into_iter().map(|loc| loc.uri.to_string()).collect()
+    };
+    assert_eq!(uris("SP1"), ["file:///a.rs"]);
+    assert_eq!(uris("SP2"), ["file:///a.rs", "file:///b.rs"]);
+    assert!(uris("SP3").is_empty());
+}
+
+#[test]
+fn workspace_index_keeps_opened() {
+    use crate::workspace::{FileIndex, Workspace};
+    use tower_lsp_server::lsp_types::Uri;
+
+    let index = |src: &str| {
+        let mut rust = Rust::new();
+        rust.update_node_tree(src.to_owned());
+        rust.find_attrs();
+        FileIndex::new(&rust)
+    };
+    let uri = |s: &str| s.parse::<Uri>().unwrap();
+
+    // a.rs is opened and changed while indexing files on disk.
+    let mut workspace = Workspace::default();
+    workspace.update(uri("file:///a.rs"), index("#[safety::requires(SP3)] fn f() {}"));
+    let indexed = vec![
+        (uri("file:///a.rs"), index("#[safety::requires(SP1)] fn f() {}")),
+        (uri("file:///b.rs"), index("#[safety::requires(SP1)] fn g() {}")),
+    ];
+    assert_eq!(workspace.extend_index(indexed), 2);
+
+    let uris = |name| -> Vec<_> {
+        workspace.references(name).into_iter().map(|loc| loc.uri.to_string()).collect()
+    };
+    assert_eq!(uris("SP1"), ["file:///b.rs"]);
+    assert_eq!(uris("SP3"), ["file:///a.rs"]);
+}
+
+#[test]
+fn incremental_changes() {
+    use tower_lsp_server::lsp_types::{Range, TextDocumentContentChangeEvent};
+
+    let change = |start: (u32, u32), end: (u32, u32), text: &str| TextDocumentContentChangeEvent {
+        range: Some(Range {
+            start: Position { line: start.0, character: start.1 },
+            end: Position { line: end.0, character: end.1 },
+        }),
+        range_length: None,
+        text: text.to_owned(),
+    };
+    let names = |rust: &Rust| -> Vec<_> { rust.tag_usages().into_iter().map(|u| u.name).collect() };
+
+    let mut rust = Rust::new();
+    rust.set_text("// 中文\nfn f() {}\n".to_owned());
+    assert!(rust.tag_usages().is_empty());
+
+    // insert an attribute before `fn` in the second line
+    rust.apply_changes(vec![change((1, 0), (1, 0), "#[safety::requires(SP1)]\n")]);
+    assert_eq!(names(&rust), ["SP1"]);
+
+    // rename SP1 to SP2, and append a tag after the comment with non-ASCII chars
+    rust.apply_changes(vec![
+        change((1, 19), (1, 22), "SP2"),
+        change((0, 5), (0, 5), " #[safety::requires(SP3)] fn g() {}"),
+    ]);
+    assert_eq!(names(&rust), ["SP2"]);
+    let src = "// 中文 #[safety::requires(SP3)] fn g() {}\n#[safety::requires(SP2)]\nfn f() {}\n";
+    assert_eq!(rust.text, src);
+
+    // remove the comment mark, and positions beyond the line end are clamped
+    rust.apply_changes(vec![change((0, 0), (0, 6), ""), change((0, 99), (0, 99), "")]);
+    assert_eq!(names(&rust), ["SP3", "SP2"]);
+
+    // the whole text is replaced without range
+    rust.apply_changes(vec![TextDocumentContentChangeEvent {
+        range: None,
+        range_length: None,
+        text: "#[safety::checked(SP4)] fn h() {}".to_owned(),
+    }]);
+    assert_eq!(names(&rust), ["SP4"]);
+}
+
+#[test]
+fn code_actions() {
+    use tower_lsp_server::lsp_types::{CodeActionOrCommand, Uri};
+
+    let src = "#[safety::requires(Align(p), any(Init, Zeroed))]\n\
+               unsafe fn read<T>(p: *const T) {}\n\
+               fn f() {\n    let _ = unsafe { self::read(p) };\n    \
+               // SAFETY: p is aligned\n    // and initialized.\n    \
+               unsafe { read(p) }\n    unsafe { other() }\n}";
+    let mut rust = Rust::new();
+    rust.set_text(src.to_owned());
+
+    let fns = rust.requires_fns();
+    assert_eq!(fns.len(), 1);
+    assert_eq!(fns[0].name, "read");
+    assert_eq!(fns[0].tags, ["Align(p)"]);
+    assert_eq!(fns[0].any, [["Init", "Zeroed"]]);
+
+    let uri: Uri = "file:///a.rs".parse().unwrap();
+    let requires_fn = |name: &str| fns.iter().find(|f| f.name == name).cloned();
+    let edits = |line, character| -> Vec<_> {
+        let pos = Position { line, character };
+        let actions = rust.code_actions(&uri, pos, requires_fn);
+        actions
+            .into_iter()
+            .map(|action| {
+                let CodeActionOrCommand::CodeAction(action) = action else { unreachable!() };
+                let edit = &action.edit.unwrap().changes.unwrap()[&uri][0];
+                (edit.range.start.line, edit.range.end.line, edit.new_text.clone())
+            })
+            .collect()
+    };
+
+    // a quick-fix for each alternative in `any`
+    assert_eq!(
+        edits(3, 22),
+        [
+            (3, 3, "#[safety::checked { Align(p), Init: \"TODO\" }]\n    ".to_owned()),
+            (3, 3, "#[safety::checked { Align(p), Zeroed: \"TODO\" }]\n    ".to_owned()),
+        ]
+    );
+
+    // SAFETY comment
+    assert_eq!(
+        edits(4, 8),
+        [(
+            4,
+            5,
+            "#[safety::checked { Align(p), Init: \"p is aligned and initialized.\" }]".to_owned()
+        )]
+    );
+
+    // unknown callee
+    assert!(edits(7, 14).is_empty());
+    // not in an unsafe block
+    assert!(edits(2, 3).is_empty());
+}
+
+/// Tags defined in tests without spec TOML.
+fn define_tags(rust: &mut Rust, spec: &str) {
+    use safety_parser::configuration::{DefinedTag, Tag};
+
+    // sorted by names as in the spec cache
+    let tags: std::collections::BTreeMap<String, Tag> = toml::from_str(spec).unwrap();
+    rust.tags =
+        tags.into_iter().map(|(name, tag)| DefinedTag { name: name.into(), args: tag }).collect();
+}
+
+#[test]
+fn completion() {
+    use tower_lsp_server::lsp_types::CompletionTextEdit;
+
+    let src = "#[safety::requires(Al)]\n\
+               unsafe fn read(p: *const u8) {}\n\
+               #[safety::requires(any(Init, hazard.))] fn g() {}\n\
+               fn f() {\n    #[safety::checked(I: \"x\")]\n    unsafe { read(p) }\n}\n\
+               #[derive(Al)] struct S;";
+    let mut rust = Rust::new();
+    define_tags(
+        &mut rust,
+        "[Align]\nargs = [\"p\", \"T\"]\n[Alias]\ntypes = [\"hazard\"]\n\
+         [Init]\n[Allocated]\n[InBound]",
+    );
+    rust.set_text(src.to_owned());
+
+    let required = RequiresFn { name: "read".into(), tags: vec!["Init".into()], any: vec![] };
+    let complete = |line, character| -> Option<Vec<(String, String)>> {
+        let pos = Position { line, character };
+        let items = rust.completion(pos, |name| (name == "read").then(|| required.clone()))?;
+        let items = items.into_iter().map(|item| {
+            let Some(CompletionTextEdit::Edit(edit)) = item.text_edit else { unreachable!() };
+            (item.label, edit.new_text)
+        });
+        Some(items.collect())
+    };
+    let s = |pairs: &[(&str, &str)]| -> Vec<_> {
+        pairs.iter().map(|(a, b)| (a.to_string(), b.to_string())).collect()
+    };
+
+    // filter by the prefix, with a snippet for args
+    assert_eq!(
+        complete(0, 21).unwrap(),
+        s(&[("Alias", "Alias"), ("Align", "Align(${1:p}, ${2:T})"), ("Allocated", "Allocated")])
+    );
+    // type prefixes without a prefix
+    let labels: Vec<_> = complete(0, 19).unwrap().into_iter().map(|(label, _)| label).collect();
+    assert_eq!(labels[..4], ["precond.", "hazard.", "option.", "Alias"]);
+    // tags in `any`
+    assert_eq!(complete(2, 25).unwrap(), s(&[("InBound", "InBound"), ("Init", "Init")]));
+    // only tags of the type after `type.`
+    assert_eq!(complete(2, 36).unwrap(), s(&[("Alias", "Alias")]));
+    // only tags required by the callee in `checked`
+    assert_eq!(complete(4, 23).unwrap(), s(&[("Init", "Init")]));
+    // not in reasons, paths, args of tags, or other attributes
+    assert_eq!(complete(4, 27), None);
+    assert_eq!(complete(0, 10), None);
+    assert_eq!(complete(7, 11), None);
+}
+
+#[test]
+fn hover() {
+    let src = "#[safety::requires(Align(p, u8), Init)]\n\
+               unsafe fn read(p: *const u8) {}\n\
+               fn f() {\n    #[safety::checked(Align(p, u8): \"x\")]\n    \
+               let _ = unsafe { read(p) };\n    unsafe { read(p) }\n}";
+    let mut rust = Rust::new();
+    define_tags(
+        &mut rust,
+        "[Align]\nargs = [\"p\", \"T\"]\ndesc = \"{p} is aligned for {T}\"\n[Init]\ndesc = \"init\"",
+    );
+    rust.set_text(src.to_owned());
+
+    let required = RequiresFn {
+        name: "read".into(),
+        tags: vec!["Align(p, u8)".into(), "Init".into()],
+        any: vec![vec!["Zeroed".into(), "Init".into()]],
+    };
+    let hover = |line, character| {
+        let pos = Position { line, character };
+        rust.hover(pos, |name| (name == "read").then(|| required.clone()))
+    };
+
+    // on a tag with args interpolated
+    let (doc, range) = hover(0, 21).unwrap();
+    assert!(doc.starts_with("# Align(p, u8)\n\np is aligned for u8\n"), "{doc}");
+    assert!(doc.contains("**desc**: {p} is aligned for {T}"), "{doc}");
+    assert_eq!((range.start.character, range.end.character), (19, 24));
+
+    // on the attribute but not on a tag
+    let (doc, _) = hover(0, 5).unwrap();
+    assert!(doc.starts_with("# Safety Requirements\n\n"), "{doc}");
+
+    // on unsafe calls with discharged tags checked
+    let (doc, range) = hover(4, 22).unwrap();
+    assert_eq!(
+        doc,
+        "# Safety Requirements of `read`\n\n\
+         - [x] Align(p, u8)\n- [ ] Init\n- [ ] any(Zeroed, Init)\n"
+    );
+    assert_eq!((range.start.line, range.start.character, range.end.character), (4, 21, 28));
+    let (doc, _) = hover(5, 14).unwrap();
+    assert!(doc.contains("- [ ] Align(p, u8)"), "{doc}");
+
+    // outside attributes and unsafe calls
+    assert!(hover(2, 3).is_none());
+}
+
+#[test]
+fn semantic_tokens() {
+    let src = "#[safety::requires(Align(p), hazard.Alias, Undef)]\n\
+               unsafe fn read(p: *const u8) {}\n\
+               fn f() {\n    #[safety::checked(Align(p): \"x\")]\n    unsafe { read(p) }\n}";
+    let mut rust = Rust::new();
+    define_tags(&mut rust, "[Align]\nargs = [\"p\"]\n[Alias]\ntypes = [\"option\", \"hazard\"]");
+    rust.set_text(src.to_owned());
+
+    let tokens: Vec<_> = rust
+        .semantic_tokens()
+        .into_iter()
+        .map(|t| (t.delta_line, t.delta_start, t.length, t.token_type, t.token_modifiers_bitset))
+        .collect();
+    // precond, explicit hazard, undefined, and precond on another line
+    assert_eq!(tokens, [(0, 19, 5, 0, 0), (0, 17, 5, 1, 0), (0, 7, 5, 0, 1), (3, 22, 5, 0, 0)]);
+}
+
+#[test]
+fn inlay_hints() {
+    use tower_lsp_server::lsp_types::{InlayHintLabel, Range};
+
+    let src = "fn f() {\n    #[safety::checked(Align(p): \"x\")]\n    unsafe { read(p) }\n    \
+               #[safety::checked(Zeroed: \"x\")]\n    unsafe { read(p) }\n    \
+               unsafe { other() }\n}";
+    let mut rust = Rust::new();
+    rust.set_text(src.to_owned());
+
+    let required = RequiresFn {
+        name: "read".into(),
+        tags: vec!["Align(p)".into()],
+        any: vec![vec!["Init".into(), "Zeroed".into()]],
+    };
+    let hints = |range| -> Vec<_> {
+        let hints = rust.inlay_hints(range, |name| (name == "read").then(|| required.clone()));
+        hints
+            .into_iter()
+            .map(|hint| {
+                let InlayHintLabel::String(label) = hint.label else { unreachable!() };
+                (hint.position.line, hint.position.character, label)
+            })
+            .collect()
+    };
+
+    let all = Range::new(Position::new(0, 0), Position::new(7, 0));
+    assert_eq!(
+        hints(all),
+        [
+            (2, 12, "undischarged: any(Init, Zeroed)".to_owned()),
+            (4, 12, "undischarged: Align(p)".to_owned()),
+        ]
+    );
+    // only hints in the range
+    assert_eq!(hints(Range::new(Position::new(3, 0), Position::new(7, 0))).len(), 1);
+}
@@ -0,0 +1,60 @@
+//! Semantic tokens coloring tags in safety attributes by their types.
+use super::Rust;
+use safety_parser::configuration::TagType;
+use tower_lsp_server::lsp_types::{
+    SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokensLegend,
+};
+
+/// Token types for tags of precond, hazard, and option in order. Standard types are taken
+/// to be colored by themes without extra configuration.
+const TOKEN_TYPES: [SemanticTokenType; 3] =
+    [SemanticTokenType::PROPERTY, SemanticTokenType::MACRO, SemanticTokenType::ENUM_MEMBER];
+
+/// Undefined tags are marked deprecated, which is usually rendered as strikethrough.
+const UNDEFINED: u32 = 1 << 0;
+
+pub fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: TOKEN_TYPES.to_vec(),
+        token_modifiers: vec![SemanticTokenModifier::DEPRECATED],
+    }
+}
+
+fn token_type(typ: TagType) -> u32 {
+    match typ {
+        TagType::Precond => 0,
+        TagType::Hazard => 1,
+        TagType::Option => 2,
+    }
+}
+
+impl Rust {
+    /// Tokens of tags in safety attributes. A tag without `type.` takes the first type in
+    /// its definition. Undefined tags are flagged only if spec is given.
+    pub fn semantic_tokens(&self) -> Vec<SemanticToken> {
+        let mut usages = self.tag_usages();
+        usages.sort_by_key(|usage| usage.range.start);
+
+        let (mut prev_line, mut prev_start) = (0, 0);
+        let mut tokens = Vec::with_capacity(usages.len());
+        for usage in usages {
+            let defined = self.tags.iter().find(|tag| *tag.name == *usage.name);
+            let typ = usage.typ.or_else(|| defined?.args.types.first().copied());
+            let undefined = defined.is_none() && !self.tags.is_empty();
+
+            let start = usage.range.start;
+            let delta_line = start.line - prev_line;
+            let delta_start =
+                if delta_line == 0 { start.character - prev_start } else { start.character };
+            tokens.push(SemanticToken {
+                delta_line,
+                delta_start,
+                length: usage.range.end.character.saturating_sub(start.character),
+                token_type: token_type(typ.unwrap_or_default()),
+                token_modifiers_bitset: if undefined { UNDEFINED } else { 0 },
+            });
+            (prev_line, prev_start) = (start.line, start.character);
+        }
+        tokens
+    }
+}
@@ -0,0 +1,364 @@
+//! Spec TOML files where tags are defined.
+use crate::rust::byte_to_pos;
+use ropey::Rope;
+use safety_parser::configuration::{
+    ANY, cache,
+    env::{ENV_SP_DIR, ENV_SP_FILE, LOCAL_SP_DIR, LOCAL_SP_FILE},
+    reload, unload,
+};
+use std::{
+    collections::BTreeSet,
+    env, fs, ops,
+    path::{Path, PathBuf},
+};
+use toml::de::DeTable;
+use tower_lsp_server::{
+    UriExt,
+    lsp_types::{
+        FileSystemWatcher, GlobPattern, LSPAny, Location, Position, Range, Uri, WatchKind,
+    },
+};
+
+/// Where spec TOML files are found, in the order of priority:
+/// * `specPaths` in `initializationOptions`, relative to the first workspace root
+/// * `SP_FILE` or `SP_DIR` environment variable
+/// * `safety-tags.toml` or `safety-tags/` under workspace roots
+///
+/// A path can be either a TOML file or a folder of them.
+#[derive(Debug, Default)]
+pub struct SpecPaths {
+    paths: Vec<PathBuf>,
+}
+
+impl SpecPaths {
+    pub fn new(options: Option<&LSPAny>, roots: &[PathBuf]) -> Self {
+        let base = roots.first().map(|root| &**root).unwrap_or(Path::new("."));
+        let options = options.and_then(|opt| opt.get("specPaths"));
+        let options: Vec<_> = match options {
+            Some(LSPAny::String(path)) => vec![base.join(path)],
+            Some(LSPAny::Array(paths)) => {
+                paths.iter().filter_map(|path| path.as_str()).map(|path| base.join(path)).collect()
+            }
+            _ => Vec::new(),
+        };
+        if !options.is_empty() {
+            return SpecPaths { paths: options };
+        }
+
+        // only respect `SP_FILE` if both are given
+        if let Some(path) = env::var_os(ENV_SP_FILE).or_else(|| env::var_os(ENV_SP_DIR)) {
+            return SpecPaths { paths: vec![path.into()] };
+        }
+
+        // only respect `safety-tags.toml` if both exist
+        let local = roots.iter().filter_map(|root| {
+            [root.join(LOCAL_SP_FILE), root.join(LOCAL_SP_DIR)].into_iter().find(|p| p.exists())
+        });
+        SpecPaths { paths: local.collect() }
+    }
+
+    /// TOML files to load spec from.
+    pub fn toml_files(&self) -> Vec<String> {
+        let mut files = Vec::new();
+        for path in &self.paths {
+            if path.is_dir() {
+                let Ok(entries) = fs::read_dir(path) else { continue };
+                let mut tomls: Vec<_> = entries
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+                    .collect();
+                tomls.sort_unstable();
+                files.extend(tomls);
+            } else if path.is_file() {
+                files.push(path.clone());
+            }
+        }
+        files.into_iter().filter_map(|path| path.into_os_string().into_string().ok()).collect()
+    }
+
+    /// Whether the file is a spec TOML: a local spec file anywhere in the workspace, or one
+    /// of given paths.
+    pub fn is_spec_file(&self, path: &Path) -> bool {
+        if path.extension().is_none_or(|ext| ext != "toml") {
+            return false;
+        }
+        if path.file_name() == Some(LOCAL_SP_FILE.as_ref())
+            || path.parent().and_then(Path::file_name) == Some(LOCAL_SP_DIR.as_ref())
+        {
+            return true;
+        }
+        let Ok(path) = fs::canonicalize(path) else { return false };
+        self.toml_files().iter().any(|file| fs::canonicalize(file).is_ok_and(|file| file == path))
+    }
+
+    /// Spec files to watch: local spec files anywhere in the workspace, and given paths.
+    pub fn watchers(&self) -> Vec<FileSystemWatcher> {
+        let mut globs = vec![format!("**/{LOCAL_SP_FILE}"), format!("**/{LOCAL_SP_DIR}/*.toml")];
+        for path in &self.paths {
+            let path = path.to_string_lossy();
+            if path.ends_with(".toml") {
+                globs.push(path.into_owned());
+            } else {
+                globs.push(format!("{}/*.toml", path.trim_end_matches('/')));
+            }
+        }
+        let kind = WatchKind::Create | WatchKind::Change | WatchKind::Delete;
+        globs
+            .into_iter()
+            .map(|glob| FileSystemWatcher {
+                glob_pattern: GlobPattern::String(glob),
+                kind: Some(kind),
+            })
+            .collect()
+    }
+}
+
+/// Load spec from TOML files, and return the number of tags. The previous spec is kept on
+/// errors, except the initial loading where no spec is taken instead: an empty spec would
+/// report every tag as undefined, and spec from environment variables may panic on the errors.
+pub fn load_spec(files: &[String], initial: bool) -> Result<usize, String> {
+    reload(files).inspect_err(|_| {
+        if initial {
+            unload();
+        }
+    })
+}
+
+/// Location of `[tag.Name]` table in the spec TOML defining the tag.
+pub fn tag_definition(name: &str) -> Option<Location> {
+    let cache = cache();
+    let src = &*cache.map.get(name)?.src;
+    let text = fs::read_to_string(src).ok()?;
+    let range = tag_table_range(&text, name)?;
+    let path = fs::canonicalize(src).ok()?;
+    Some(Location { uri: Uri::from_file_path(path)?, range })
+}
+
+/// Range of the header of `[tag.Name]` or `[tag."Name"]` table.
+fn tag_table_range(text: &str, name: &str) -> Option<Range> {
+    let (line, src, _) = tag_headers(text).find(|(_, src, range)| &src[range.clone()] == name)?;
+    let start = (src.len() - src.trim_start().len()) as u32;
+    let end = src.trim_end().len() as u32;
+    Some(Range {
+        start: Position { line, character: start },
+        end: Position { line, character: end },
+    })
+}
+
+/// Location of the name in `[tag.Name]` header defining the tag.
+pub fn tag_name_location(name: &str) -> Option<Location> {
+    let cache = cache();
+    let src = &*cache.map.get(name)?.src;
+    let text = fs::read_to_string(src).ok()?;
+    let (line, _, range) =
+        tag_headers(&text).find(|(_, src, range)| &src[range.clone()] == name)?;
+    let range = Range {
+        start: Position { line, character: range.start as u32 },
+        end: Position { line, character: range.end as u32 },
+    };
+    let path = fs::canonicalize(src).ok()?;
+    Some(Location { uri: Uri::from_file_path(path)?, range })
+}
+
+/// Locations of the tag name in `follows` and `precedes` of other tags in spec TOMLs.
+pub fn tag_order_references(name: &str) -> Vec<Location> {
+    let cache = cache();
+    let srcs: BTreeSet<&str> = cache
+        .map
+        .values()
+        .filter(|key| key.tag.follows.iter().chain(&key.tag.precedes).any(|t| **t == *name))
+        .map(|key| &*key.src)
+        .collect();
+    let mut locations = Vec::new();
+    for src in srcs {
+        let Ok(text) = fs::read_to_string(src) else { continue };
+        let Some(uri) = fs::canonicalize(src).ok().and_then(Uri::from_file_path) else { continue };
+        locations.extend(
+            order_reference_ranges(&text, name)
+                .into_iter()
+                .map(|range| Location { uri: uri.clone(), range }),
+        );
+    }
+    locations
+}
+
+/// Ranges of the tag name in strings of `follows` and `precedes` fields, without quotes.
+fn order_reference_ranges(text: &str, name: &str) -> Vec<Range> {
+    let Ok(doc) = DeTable::parse(text) else { return Vec::new() };
+    let Some(tags) = doc.get_ref().get("tag").and_then(|tags| tags.get_ref().as_table()) else {
+        return Vec::new();
+    };
+    let rope = Rope::from_str(text);
+    let mut ranges = Vec::new();
+    for tag in tags.values() {
+        let Some(fields) = tag.get_ref().as_table() else { continue };
+        for field in ["follows", "precedes"] {
+            let Some(value) = fields.get(field) else { continue };
+            let values = match value.get_ref().as_array() {
+                Some(values) => values.iter().collect(),
+                None => vec![value],
+            };
+            for value in values {
+                let span = value.span();
+                // the span covers the quotes, and the string may be escaped
+                let inner = span.start + 1..span.end.saturating_sub(1);
+                if value.get_ref().as_str() == Some(name) && text.get(inner.clone()) == Some(name) {
+                    ranges.push(Range::new(
+                        byte_to_pos(inner.start, &rope),
+                        byte_to_pos(inner.end, &rope),
+                    ));
+                }
+            }
+        }
+    }
+    ranges.sort_by_key(|range| (range.start.line, range.start.character));
+    ranges
+}
+
+/// The tag name if the cursor is on it in a `[tag.Name]` header of the spec TOML.
+pub fn tag_at(text: &str, pos: Position) -> Option<String> {
+    let (_, src, range) = tag_headers(text).find(|(line, _, _)| *line == pos.line)?;
+    let col = pos.character as usize;
+    (range.start <= col && col <= range.end).then(|| src[range].to_owned())
+}
+
+/// Headers of tag tables: line number, the line, and the byte range of the tag name in it.
+fn tag_headers(text: &str) -> impl Iterator<Item = (u32, &str, ops::Range<usize>)> {
+    text.lines().enumerate().filter_map(|(line, src)| {
+        let range = header_name(src)?;
+        Some((line as u32, src, range))
+    })
+}
+
+/// Byte range of the name in `[tag.Name]` or `[tag."Name"]`, with whitespaces allowed
+/// around the dot and brackets.
+fn header_name(src: &str) -> Option<ops::Range<usize>> {
+    let rest = src.trim_start().strip_prefix('[')?.trim_start().strip_prefix("tag")?;
+    let rest = rest.trim_start().strip_prefix('.')?.trim_start();
+    let (rest, quoted) = match rest.strip_prefix('"') {
+        Some(rest) => (rest, true),
+        None => (rest, false),
+    };
+    let start = src.len() - rest.len();
+    let len = rest.find(|c: char| !is_name_char(c)).unwrap_or(rest.len());
+    let mut after = &rest[len..];
+    if quoted {
+        after = after.strip_prefix('"')?;
+    }
+    let after = after.trim_start().strip_prefix(']')?;
+    let after = after.trim_start();
+    (len != 0 && (after.is_empty() || after.starts_with('#'))).then_some(start..start + len)
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Check the new name of a tag in renaming: an identifier other than `any`, and not
+/// colliding with another defined tag.
+pub fn check_rename(name: &str, new_name: &str) -> Result<(), String> {
+    let mut chars = new_name.chars();
+    let valid =
+        chars.next().is_some_and(|c| c.is_alphabetic() || c == '_') && chars.all(is_name_char);
+    if !valid {
+        return Err(format!("`{new_name}` is not a valid tag name"));
+    }
+    if new_name == ANY {
+        return Err(format!("`{ANY}` is reserved for alternative tags"));
+    }
+    if new_name != name && cache().map.contains_key(new_name) {
+        return Err(format!("Tag `{new_name}` is already defined"));
+    }
+    Ok(())
+}
+
+/// Serializes tests that read the process-wide spec, since `malformed_spec` unloads it.
+#[cfg(test)]
+pub fn spec_lock() -> std::sync::MutexGuard<'static, ()> {
+    static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    LOCK.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+#[test]
+fn find_tag_table() {
+    let text = "[tag.Init]\ndesc = \"\"\n\n  [ tag.Align ]\nargs = []\n[tag.\"Allocated\"]\n";
+    let range = tag_table_range(text, "Align").unwrap();
+    assert_eq!((range.start.line, range.start.character), (3, 2));
+    assert_eq!(range.end.character, 15);
+    assert_eq!(tag_table_range(text, "Allocated").unwrap().start.line, 5);
+    assert!(tag_table_range(text, "Alias").is_none());
+
+    let pos = |line, character| Position { line, character };
+    assert_eq!(tag_at(text, pos(3, 10)).as_deref(), Some("Align"));
+    assert_eq!(tag_at(text, pos(5, 8)).as_deref(), Some("Allocated"));
+    assert_eq!(tag_at(text, pos(3, 3)), None);
+    assert_eq!(tag_at(text, pos(1, 0)), None);
+    assert_eq!(header_name("[tag.Init] # comment"), Some(5..9));
+    assert_eq!(header_name("[tag.Init.x]"), None);
+    assert_eq!(header_name("[tags.Init]"), None);
+}
+
+#[test]
+fn rename_check() {
+    let _lock = spec_lock();
+    assert!(check_rename("Init", "Initialized").is_ok());
+    assert!(check_rename("Init", "any").is_err());
+    assert!(check_rename("Init", "1Init").is_err());
+    assert!(check_rename("Init", "In it").is_err());
+}
+
+#[test]
+fn order_references() {
+    let text = "[tag.Init]\n\n[tag.Read]\nfollows = [\"Alloc\", \"Init\"]\n\
+                [tag.Drop]\nfollows = 'Init'\nprecedes = \"Read\"\ndesc = \"Init\"\n";
+    let ranges: Vec<_> = order_reference_ranges(text, "Init")
+        .into_iter()
+        .map(|r| (r.start.line, r.start.character, r.end.character))
+        .collect();
+    assert_eq!(ranges, [(3, 21, 25), (5, 11, 15)]);
+    assert_eq!(order_reference_ranges(text, "Read").len(), 1);
+    assert!(order_reference_ranges(text, "Alias").is_empty());
+}
+
+#[test]
+fn spec_paths() {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("..");
+    let options = serde_json::json!({ "specPaths": ["assets/sp-core.toml", "tests/snippets"] });
+    let spec = SpecPaths::new(Some(&options), std::slice::from_ref(&root));
+    let files = spec.toml_files();
+    assert!(files[0].ends_with("assets/sp-core.toml"), "{files:?}");
+    assert!(files.len() > 2 && files[1..].iter().all(|f| f.contains("tests/snippets/")));
+
+    let globs: Vec<_> = spec
+        .watchers()
+        .into_iter()
+        .map(|w| match w.glob_pattern {
+            GlobPattern::String(glob) => glob,
+            _ => unreachable!(),
+        })
+        .collect();
+    assert_eq!(globs[..2], ["**/safety-tags.toml", "**/safety-tags/*.toml"]);
+    assert!(spec.is_spec_file(&root.join("assets/sp-core.toml")));
+    assert!(spec.is_spec_file(Path::new("/path/to/safety-tags/a.toml")));
+    assert!(!spec.is_spec_file(&root.join("Cargo.toml")));
+    assert!(
+        globs[2].ends_with("assets/sp-core.toml") && globs[3].ends_with("tests/snippets/*.toml")
+    );
+}
+
+#[test]
+fn malformed_spec() {
+    use safety_parser::{configuration::spec_exists, safety::validate_attr};
+
+    let _lock = spec_lock();
+    let path = env::temp_dir().join("safety-lsp-malformed-spec.toml");
+    fs::write(&path, "[tag.Init\ndesc = \"\"\n").unwrap();
+    let files = [path.to_str().unwrap().to_owned()];
+    assert!(load_spec(&files, true).is_err());
+
+    // tags are not checked, rather than all undefined
+    assert!(!spec_exists());
+    assert!(cache().map.is_empty());
+    assert!(validate_attr("#[safety::requires(Init)]").is_empty());
+}
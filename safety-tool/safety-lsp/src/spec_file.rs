@@ -0,0 +1,338 @@
+//! Spec TOML files opened in the editor: diagnostics, completion of fields, and hover docs.
+use crate::{
+    rust::{byte_to_pos, pos_to_byte, replace_range},
+    spec,
+};
+use ropey::Rope;
+use safety_parser::{
+    configuration::{ANY, Configuration, TagType, cache, placeholders},
+    safety::parse_attr_and_flatten_properties,
+};
+use std::{fs, ops, path::PathBuf};
+use toml::{
+    Spanned,
+    de::{DeString, DeTable, DeValue},
+};
+use tower_lsp_server::lsp_types::{
+    CompletionItem, CompletionItemKind, Diagnostic, DiagnosticSeverity, Documentation,
+    InsertTextFormat, Position, Range, TextDocumentContentChangeEvent, TextEdit,
+};
+
+/// A field in spec tables with the snippet of its value.
+struct Field {
+    name: &'static str,
+    value: &'static str,
+    doc: &'static str,
+}
+
+const fn field(name: &'static str, value: &'static str, doc: &'static str) -> Field {
+    Field { name, value, doc }
+}
+
+const TAG_FIELDS: &[Field] = &[
+    field("args", "[\"$1\"]", "Argument names, which can be interpolated in desc as `{arg}`."),
+    field("desc", "\"$1\"", "Description of the tag in generated docs."),
+    field("expr", "\"$1\"", "Formal expression of the tag."),
+    field("types", "[\"${1|precond,hazard,option|}\"]", "Types of the tag, defaults to precond."),
+    field("url", "\"$1\"", "Link to more details."),
+    field("follows", "[\"$1\"]", "Calls requiring this tag must follow calls requiring these."),
+    field("precedes", "[\"$1\"]", "Calls requiring this tag must precede calls requiring these."),
+    field("since", "\"$1\"", "Spec version where the tag is introduced."),
+    field("changed_in", "\"$1\"", "Spec version where the tag is changed last time."),
+    field("require_reason", "${1|true,false|}", "Discharges must be justified by reasons."),
+];
+
+const PACKAGE_FIELDS: &[Field] = &[
+    field("name", "\"$1\"", "Name of the spec package."),
+    field("version", "\"$1\"", "Version of the spec package."),
+    field("crate_name", "\"$1\"", "Crate the spec is written for."),
+];
+
+const DOC_FIELDS: &[Field] = &[
+    field("heading_safety_title", "${1|true,false|}", "Generate `/// Safety` at the beginning."),
+    field("heading_tag", "${1|true,false|}", "Generate `Tag:` before desc."),
+];
+
+const POLICY_FIELDS: &[Field] =
+    &[field("require_reason", "${1|true,false|}", "Every discharge must be justified by reasons.")];
+
+/// A problem in the spec at the byte range.
+struct Problem {
+    span: ops::Range<usize>,
+    severity: DiagnosticSeverity,
+    message: String,
+}
+
+impl Problem {
+    fn error(span: ops::Range<usize>, message: String) -> Self {
+        Problem { span, severity: DiagnosticSeverity::ERROR, message }
+    }
+}
+
+pub struct SpecFile {
+    /// Canonical path to tell tags defined in other spec files.
+    path: Option<PathBuf>,
+    text: String,
+    rope: Rope,
+}
+
+impl SpecFile {
+    pub fn new(path: Option<PathBuf>, text: String) -> Self {
+        SpecFile { path, rope: Rope::from_str(&text), text }
+    }
+
+    /// Apply changes in order: the whole text is replaced if a change has no range.
+    pub fn apply_changes(&mut self, changes: Vec<TextDocumentContentChangeEvent>) {
+        for change in changes {
+            match change.range {
+                Some(range) => {
+                    let start = pos_to_byte(range.start, &self.rope);
+                    let end = pos_to_byte(range.end, &self.rope).max(start);
+                    replace_range(&mut self.rope, &mut self.text, start..end, &change.text);
+                }
+                None => {
+                    self.rope = Rope::from_str(&change.text);
+                    self.text = change.text;
+                }
+            }
+        }
+    }
+
+    fn range(&self, span: ops::Range<usize>) -> Range {
+        Range::new(byte_to_pos(span.start, &self.rope), byte_to_pos(span.end, &self.rope))
+    }
+
+    /// Syntax errors, and tags that can't be loaded or interpolated.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut problems = Vec::new();
+        let (doc, errors) = DeTable::parse_recoverable(&self.text);
+        for err in errors {
+            problems.push(Problem::error(err.span().unwrap_or_default(), err.message().to_owned()));
+        }
+        if let Some(tags) = doc.get_ref().get("tag").and_then(|tags| tags.get_ref().as_table()) {
+            for (name, tag) in tags {
+                self.check_tag(name, tag, &mut problems);
+            }
+        }
+        // errors of field types, which overlap with problems above
+        let err = problems.is_empty().then(|| toml::from_str::<Configuration>(&self.text).err());
+        if let Some(err) = err.flatten() {
+            problems.push(Problem::error(err.span().unwrap_or_default(), err.message().to_owned()));
+        }
+
+        problems.sort_by_key(|problem| problem.span.start);
+        problems
+            .into_iter()
+            .map(|problem| Diagnostic {
+                range: self.range(problem.span),
+                severity: Some(problem.severity),
+                source: Some("safety-tool".to_owned()),
+                message: problem.message,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn check_tag(
+        &self,
+        name: &Spanned<DeString>,
+        tag: &Spanned<DeValue>,
+        problems: &mut Vec<Problem>,
+    ) {
+        let span = name.span();
+        let name = &**name.get_ref();
+        if name == ANY {
+            problems.push(Problem::error(span.clone(), format!("`{ANY}` is a builtin tag")));
+        }
+        if let Some(key) = cache().map.get(name) {
+            let elsewhere =
+                |path: &PathBuf| fs::canonicalize(&*key.src).ok().as_ref() != Some(path);
+            if self.path.as_ref().is_some_and(elsewhere) {
+                let message = format!("Tag `{name}` has been defined in {}", key.src);
+                problems.push(Problem::error(span, message));
+            }
+        }
+
+        let Some(fields) = tag.get_ref().as_table() else { return };
+        for key in fields.keys() {
+            let (span, key) = (key.span(), &**key.get_ref());
+            if !TAG_FIELDS.iter().any(|field| field.name == key) {
+                problems.push(Problem {
+                    span,
+                    severity: DiagnosticSeverity::WARNING,
+                    message: format!("Unknown field `{key}` is ignored"),
+                });
+            }
+        }
+
+        let types = fields.get("types").map(|types| match types.get_ref().as_array() {
+            Some(types) => types.into_iter().collect(),
+            None => vec![types],
+        });
+        for typ in types.into_iter().flatten() {
+            let Some(s) = typ.get_ref().as_str() else { continue };
+            if TagType::try_new(s).is_none() {
+                let message = format!("Unknown type `{s}`: only precond, hazard, and option");
+                problems.push(Problem::error(typ.span(), message));
+            }
+        }
+
+        let args = fields.get("args").and_then(|args| args.get_ref().as_array());
+        let args: Vec<_> =
+            args.into_iter().flatten().filter_map(|arg| arg.get_ref().as_str()).collect();
+        let Some(desc) = fields.get("desc") else { return };
+        for placeholder in desc.get_ref().as_str().map(placeholders).unwrap_or_default() {
+            if !args.contains(&placeholder) {
+                let message = format!("Placeholder `{{{placeholder}}}` is not in args");
+                problems.push(Problem::error(desc.span(), message));
+            }
+        }
+    }
+
+    /// Fields of the table to complete at the start of a line, except existing ones.
+    pub fn completion(&self, pos: Position) -> Option<Vec<CompletionItem>> {
+        let cursor = pos_to_byte(pos, &self.rope);
+        let line_start = self.rope.line_to_byte(pos.line as usize);
+        let prefix = self.text[line_start..cursor].trim_start();
+        if !prefix.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return None;
+        }
+
+        let lines: Vec<_> = self.text.lines().collect();
+        let (header, table) = table_at(&lines, pos.line as usize)?;
+        let fields = match &*table {
+            "package" => PACKAGE_FIELDS,
+            "doc" => DOC_FIELDS,
+            "policy" => POLICY_FIELDS,
+            table if table.starts_with("tag.") => TAG_FIELDS,
+            _ => return None,
+        };
+        let existing: Vec<_> = lines[header + 1..]
+            .iter()
+            .take_while(|line| table_header(line).is_none())
+            .filter_map(|line| Some(line.split_once('=')?.0.trim()))
+            .collect();
+
+        let range = Range::new(byte_to_pos(cursor - prefix.len(), &self.rope), pos);
+        let items = fields
+            .iter()
+            .filter(|field| field.name.starts_with(prefix) && !existing.contains(&field.name))
+            .map(|field| CompletionItem {
+                label: field.name.to_owned(),
+                kind: Some(CompletionItemKind::FIELD),
+                documentation: Some(Documentation::String(field.doc.to_owned())),
+                insert_text_format: Some(InsertTextFormat::SNIPPET),
+                text_edit: Some(
+                    TextEdit::new(range, format!("{} = {}", field.name, field.value)).into(),
+                ),
+                ..Default::default()
+            });
+        Some(items.collect())
+    }
+
+    /// Docs generated from the tag table containing the cursor, as written in the editor.
+    pub fn hover(&self, pos: Position) -> Option<(String, Range)> {
+        let lines: Vec<_> = self.text.lines().collect();
+        let (header, table) = table_at(&lines, pos.line as usize)?;
+        let name = table.strip_prefix("tag.")?.trim_matches('"');
+        let config: Configuration = toml::from_str(&self.text).ok()?;
+        let tag = config.tag.get(name)?;
+
+        let detail = if tag.args.is_empty() {
+            name.to_owned()
+        } else {
+            format!("{name}({})", tag.args.join(", "))
+        };
+        let mut doc = format!("# {detail}\n\n");
+        let props = parse_attr_and_flatten_properties(&format!("#[safety::requires({detail})]"));
+        proc_macro2::extra::invalidate_current_thread_spans();
+        if let Some(desc) = props.first().and_then(|prop| prop.gen_doc_with(tag)) {
+            doc.push_str(desc.trim_end());
+            doc.push_str("\n\n---\n\n");
+        }
+        doc.push_str(&tag.hover_documentation());
+
+        let end = lines[header + 1..]
+            .iter()
+            .position(|line| table_header(line).is_some())
+            .map_or(lines.len(), |idx| header + 1 + idx);
+        let range = Range::new(Position::new(header as u32, 0), Position::new(end as u32, 0));
+        Some((doc, range))
+    }
+
+    /// The tag name if the cursor is on it in a `[tag.Name]` header.
+    pub fn tag_at(&self, pos: Position) -> Option<String> {
+        spec::tag_at(&self.text, pos)
+    }
+}
+
+/// The header of the table containing the line: its line number and the table name like
+/// `tag.Align` with whitespaces removed.
+fn table_at(lines: &[&str], line: usize) -> Option<(usize, String)> {
+    let lines = lines.get(..=line).unwrap_or(lines);
+    lines.iter().enumerate().rev().find_map(|(idx, line)| Some((idx, table_header(line)?)))
+}
+
+fn table_header(line: &str) -> Option<String> {
+    let header = line.trim().strip_prefix('[')?;
+    let header = &header[..header.find(']')?];
+    Some(header.split_whitespace().collect())
+}
+
+#[test]
+fn spec_diagnostics() {
+    let _lock = crate::spec::spec_lock();
+    let text = "[tag.any]\n\n[tag.Align]\nargs = [\"p\"]\ndesc = \"{p} is aligned for {T}\"\n\
+                types = [\"precond\", \"unsafe\"]\nurl = \"\"\nrequire = true\n\n\
+                [tag.Init]\n[tag.Init]\n";
+    let diagnostics = SpecFile::new(None, text.to_owned()).diagnostics();
+    let diagnostics: Vec<_> = diagnostics
+        .iter()
+        .map(|d| (d.range.start.line, d.severity.unwrap(), &*d.message))
+        .collect();
+    let (error, warning) = (DiagnosticSeverity::ERROR, DiagnosticSeverity::WARNING);
+    assert_eq!(
+        diagnostics,
+        [
+            (0, error, "`any` is a builtin tag"),
+            (4, error, "Placeholder `{T}` is not in args"),
+            (5, error, "Unknown type `unsafe`: only precond, hazard, and option"),
+            (7, warning, "Unknown field `require` is ignored"),
+            (10, error, "duplicate key"),
+        ]
+    );
+
+    // field types are checked if nothing else is wrong
+    let diagnostics = SpecFile::new(None, "[tag.Align]\nargs = \"p\"".to_owned()).diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].range.start.line, 1);
+}
+
+#[test]
+fn spec_completion() {
+    let text = "[package]\nname = \"core\"\n\n[tag.Align]\nargs = [\"p\"]\n\nre\n[tag.Init]\n";
+    let spec_file = SpecFile::new(None, text.to_owned());
+    let labels = |line, character| -> Option<Vec<String>> {
+        let items = spec_file.completion(Position { line, character })?;
+        Some(items.into_iter().map(|item| item.label).collect())
+    };
+
+    assert_eq!(labels(2, 0).unwrap(), ["version", "crate_name"]);
+    assert_eq!(labels(6, 2).unwrap(), ["require_reason"]);
+    let all = labels(5, 0).unwrap();
+    assert!(!all.contains(&"args".to_owned()) && all.contains(&"desc".to_owned()), "{all:?}");
+    // not at the position of keys
+    assert_eq!(labels(4, 8), None);
+    assert_eq!(labels(3, 2), None);
+}
+
+#[test]
+fn spec_hover() {
+    let text = "[tag.Align]\nargs = [\"p\", \"T\"]\ndesc = \"{p} is aligned for {T}\"\n\n\
+                [tag.Init]\n";
+    let spec_file = SpecFile::new(None, text.to_owned());
+    let (doc, range) = spec_file.hover(Position { line: 2, character: 3 }).unwrap();
+    assert!(doc.starts_with("# Align(p, T)\n\np is aligned for T\n\n---\n\n"), "{doc}");
+    assert_eq!((range.start.line, range.end.line), (0, 4));
+    assert!(spec_file.hover(Position { line: 4, character: 3 }).unwrap().0.starts_with("# Init"));
+}
@@ -0,0 +1,138 @@
+//! Workspace-wide index of tags used in Rust files.
+use crate::rust::{RequiresFn, Rust, TagUsage};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use tower_lsp_server::{
+    UriExt,
+    lsp_types::{Location, TextEdit, Uri, WorkspaceEdit},
+};
+
+#[derive(Default)]
+pub struct Workspace {
+    /// Root folders of the workspace.
+    roots: Vec<PathBuf>,
+    /// Tags used and functions requiring tags in each Rust file.
+    files: HashMap<Uri, FileIndex>,
+}
+
+#[derive(Default)]
+pub struct FileIndex {
+    pub usages: Vec<TagUsage>,
+    pub fns: Vec<RequiresFn>,
+}
+
+impl FileIndex {
+    pub fn new(rust: &Rust) -> Self {
+        FileIndex { usages: rust.tag_usages(), fns: rust.requires_fns() }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.usages.is_empty() && self.fns.is_empty()
+    }
+}
+
+impl Workspace {
+    pub fn set_roots(&mut self, roots: Vec<PathBuf>) {
+        self.roots = roots;
+    }
+
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// Index all Rust files under the roots, skipping hidden and `target` folders.
+    /// This reads files in blocking ways, thus shouldn't be called in async tasks.
+    pub fn index_roots(roots: &[PathBuf]) -> Vec<(Uri, FileIndex)> {
+        let mut files = Vec::new();
+        for root in roots {
+            collect_rust_files(root, &mut files);
+        }
+
+        let mut rust = Rust::new();
+        let mut indexed = Vec::with_capacity(files.len());
+        for file in files {
+            let (Ok(text), Some(uri)) = (fs::read_to_string(&file), Uri::from_file_path(&file))
+            else {
+                continue;
+            };
+            rust.set_text(text);
+            let index = FileIndex::new(&rust);
+            if !index.is_empty() {
+                indexed.push((uri, index));
+            }
+        }
+        indexed
+    }
+
+    /// Add the index from [`Workspace::index_roots`]. Files indexed meanwhile, i.e. opened
+    /// documents, are kept since they're newer. Returns the number of indexed files.
+    pub fn extend_index(&mut self, indexed: Vec<(Uri, FileIndex)>) -> usize {
+        for (uri, index) in indexed {
+            self.files.entry(uri).or_insert(index);
+        }
+        self.files.len()
+    }
+
+    /// Replace the index of the file, e.g. when the document is changed.
+    pub fn update(&mut self, uri: Uri, index: FileIndex) {
+        if index.is_empty() {
+            self.files.remove(&uri);
+        } else {
+            self.files.insert(uri, index);
+        }
+    }
+
+    /// A function annotated with `#[requires]` by name. If multiple functions share the
+    /// name, the one in the first file by path is returned.
+    pub fn requires_fn(&self, name: &str) -> Option<RequiresFn> {
+        let mut files: Vec<_> = self.files.iter().collect();
+        files.sort_unstable_by_key(|(uri, _)| uri.as_str());
+        files.into_iter().flat_map(|(_, index)| &index.fns).find(|f| f.name == name).cloned()
+    }
+
+    /// Locations where the tag is used, sorted by file and position.
+    pub fn references(&self, name: &str) -> Vec<Location> {
+        let mut locations: Vec<_> = self
+            .files
+            .iter()
+            .flat_map(|(uri, index)| {
+                let usages = index.usages.iter().filter(|usage| usage.name == name);
+                usages.map(|usage| Location { uri: uri.clone(), range: usage.range })
+            })
+            .collect();
+        locations.sort_unstable_by(|a, b| {
+            (a.uri.as_str(), a.range.start).cmp(&(b.uri.as_str(), b.range.start))
+        });
+        locations
+    }
+}
+
+/// Replace the text at all locations.
+// `Uri` is the key of changes in `WorkspaceEdit`, though it's considered mutable.
+#[allow(clippy::mutable_key_type)]
+pub fn replace_all(locations: Vec<Location>, new_text: &str) -> WorkspaceEdit {
+    let mut changes = HashMap::<Uri, Vec<TextEdit>>::new();
+    for Location { uri, range } in locations {
+        changes.entry(uri).or_default().push(TextEdit::new(range, new_text.to_owned()));
+    }
+    WorkspaceEdit { changes: Some(changes), ..Default::default() }
+}
+
+fn collect_rust_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if path.is_dir() {
+            if !name.starts_with('.') && name != "target" {
+                collect_rust_files(&path, files);
+            }
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            files.push(path);
+        }
+    }
+}
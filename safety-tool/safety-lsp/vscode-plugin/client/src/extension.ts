@@ -40,7 +40,12 @@ export function activate(_context: ExtensionContext) {
   // Options to control the language client
   const clientOptions: LanguageClientOptions = {
     // Register the server for rust documents
-    documentSelector: [{ scheme: "file", language: "rust" }],
+    documentSelector: [
+      { scheme: "file", language: "rust" },
+      // spec files
+      { scheme: "file", pattern: "**/safety-tags.toml" },
+      { scheme: "file", pattern: "**/safety-tags/*.toml" },
+    ],
     synchronize: {
       // Notify the server about file changes to '.clientrc files contained in the workspace
       fileEvents: workspace.createFileSystemWatcher("**/.clientrc"),
@@ -127,3 +127,45 @@ pub fn checked(attr: TokenStream, item: TokenStream) -> TokenStream {
     ts.extend(item);
     ts
 }
+
+/// Link sites sharing a single safety justification, i.e. the entity reference system.
+///
+/// A site referring to an id reuses the discharge on another site with the same id,
+/// instead of repeating tags and reasons. Items like a `Drop` impl can also be linked
+/// to state that these discharges rely on them.
+///
+/// # Syntax Example
+///
+/// ```
+/// #![feature(stmt_expr_attributes)]
+/// #![feature(proc_macro_hygiene)]
+/// #![feature(register_tool)]
+/// #![register_tool(rapx)]
+/// # use safety_macro::{checked, r#ref, requires};
+///
+/// #[requires { SP1 }] unsafe fn foo() {}
+///
+/// #[r#ref(foo_in_loop)] // the discharge below relies on this function
+/// fn guard() {}
+///
+/// // Discharge SPs once:
+/// #[r#ref(foo_in_loop)]
+/// #[checked { SP1: "reason" }]
+/// unsafe { foo() };
+/// // Refer to the discharge above:
+/// #[r#ref(foo_in_loop)]
+/// unsafe { foo() };
+/// ```
+#[proc_macro_attribute]
+pub fn r#ref(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut ts = TokenStream::new();
+
+    let tool_attr: TokenStream = {
+        let attr = TokenStream2::from(attr);
+        quote! { #[rapx::r#ref(#attr)] }.into()
+    };
+    ts.extend(tool_attr);
+
+    ts.extend(item);
+    ts
+}
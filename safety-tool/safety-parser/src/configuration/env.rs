@@ -1,4 +1,5 @@
 use std::{
+    cell::Cell,
     env::{self, var},
     fs,
     path::Path,
@@ -9,8 +10,11 @@ use std::{
 pub const ENV_SP_FILE: &str = "SP_FILE";
 /// Folder where all toml files are searched.
 pub const ENV_SP_DIR: &str = "SP_DIR";
-/// Disable tag check. This is necessary for language server to not panic.
+/// Disable tag check. Language server doesn't need this, since it parses tags through
+/// [`without_check`].
 pub const ENV_SP_DISABLE_CHECK: &str = "SP_DISABLE_CHECK";
+/// Spec version the crate being compiled is written against.
+pub const ENV_SP_VERSION: &str = "SP_VERSION";
 /// SP file to crate being compiled.
 pub const LOCAL_SP_FILE: &str = "safety-tags.toml";
 /// SP folder to crate being compiled.
@@ -46,7 +50,21 @@ pub fn disable_check() -> bool {
 
 /// Whether tags are needed to check as defined in sepc TOML.
 pub fn need_check() -> bool {
-    ENV.need_check
+    ENV.need_check && !SUSPEND_CHECK.get()
+}
+
+thread_local! {
+    static SUSPEND_CHECK: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Parse tags in `f` without checking them against spec TOML, thus never panicking on
+/// wrongly specified tags. This is what the language server needs in the same process,
+/// where problems are reported through [`validate_attr`](crate::safety::validate_attr).
+pub fn without_check<T>(f: impl FnOnce() -> T) -> T {
+    let prev = SUSPEND_CHECK.replace(true);
+    let ret = f();
+    SUSPEND_CHECK.set(prev);
+    ret
 }
 
 fn list_toml_files(dir: &str) -> Vec<String> {
@@ -101,3 +119,17 @@ pub fn toml_file_paths() -> Vec<String> {
         Vec::new()
     }
 }
+
+/// Spec version the crate being compiled is written against:
+/// * if `SP_VERSION` is specified, use that version
+/// * otherwise, read `spec-version` in `[package.metadata.safety-tool]` of
+///   `CARGO_MANIFEST_DIR/Cargo.toml`
+pub fn declared_spec_version() -> Option<String> {
+    if let Ok(version) = var(ENV_SP_VERSION) {
+        return Some(version);
+    }
+    let manifest = Path::new(&var("CARGO_MANIFEST_DIR").ok()?).join("Cargo.toml");
+    let manifest: toml::Table = toml::from_str(&fs::read_to_string(manifest).ok()?).ok()?;
+    let metadata = manifest.get("package")?.get("metadata")?.get("safety-tool")?;
+    Some(metadata.get("spec-version")?.as_str()?.to_owned())
+}
@@ -1,7 +1,10 @@
 //! Property definition through config file.
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
-use std::{fs, sync::LazyLock};
+use std::{
+    fs, ops,
+    sync::{Arc, LazyLock, RwLock},
+};
 
 pub mod env;
 
@@ -14,6 +17,8 @@ pub struct Configuration {
     pub tag: IndexMap<Str, Tag>,
     #[serde(default)]
     pub doc: GenDocOption,
+    #[serde(default)]
+    pub policy: Policy,
 }
 
 impl Configuration {
@@ -43,6 +48,126 @@ pub struct Tag {
     #[serde(default = "default_types")]
     pub types: Box<[TagType]>,
     pub url: OptStr,
+    /// A call requiring this tag must follow a call requiring one of these tags
+    /// on every path in the same function. A single string is accepted.
+    #[serde(default, deserialize_with = "one_or_many", skip_serializing_if = "<[_]>::is_empty")]
+    pub follows: Box<[Str]>,
+    /// A call requiring this tag must precede a call requiring one of these tags
+    /// on every path in the same function. A single string is accepted.
+    #[serde(default, deserialize_with = "one_or_many", skip_serializing_if = "<[_]>::is_empty")]
+    pub precedes: Box<[Str]>,
+    /// Spec version where this tag is introduced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: OptStr,
+    /// Spec version where the definition of this tag is changed last time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changed_in: OptStr,
+    /// A discharge of this tag must be justified by a reason string at `checked` sites.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub require_reason: bool,
+}
+
+impl Tag {
+    /// Whether ordering between calls is constrained by this tag.
+    pub fn is_temporal(&self) -> bool {
+        !self.follows.is_empty() || !self.precedes.is_empty()
+    }
+
+    /// Markdown listing fields of the definition.
+    pub fn hover_documentation(&self) -> String {
+        use std::fmt::Write;
+
+        let Tag {
+            desc,
+            expr,
+            types,
+            url,
+            follows,
+            precedes,
+            since,
+            changed_in,
+            require_reason,
+            ..
+        } = self;
+        let mut doc = String::new();
+
+        let types_field = if types.len() == 1 { "type" } else { "types" };
+        let types = types.iter().map(|t| t.as_str()).collect::<Vec<_>>().join(", ");
+        _ = writeln!(&mut doc, "**{types_field}**: {types}\n");
+
+        if let Some(desc) = desc {
+            _ = writeln!(&mut doc, "**desc**: {desc}\n");
+        }
+        if let Some(expr) = expr {
+            _ = writeln!(&mut doc, "**expr**: {expr}\n");
+        }
+        if !follows.is_empty() {
+            _ = writeln!(&mut doc, "**follows**: {}\n", follows.join(", "));
+        }
+        if !precedes.is_empty() {
+            _ = writeln!(&mut doc, "**precedes**: {}\n", precedes.join(", "));
+        }
+        if let Some(since) = since {
+            _ = writeln!(&mut doc, "**since**: {since}\n");
+        }
+        if let Some(changed_in) = changed_in {
+            _ = writeln!(&mut doc, "**changed_in**: {changed_in}\n");
+        }
+        if *require_reason {
+            _ = writeln!(&mut doc, "**require_reason**: true\n");
+        }
+        if let Some(url) = url {
+            _ = writeln!(&mut doc, "**url**: <{url}>");
+        }
+        doc
+    }
+
+    /// Placeholders in desc that are not listed in args, which can't be interpolated.
+    pub fn unknown_placeholders(&self) -> Vec<&str> {
+        let Some(desc) = self.desc.as_deref() else { return Vec::new() };
+        placeholders(desc)
+            .into_iter()
+            .filter(|p| !self.args.iter().any(|arg| &**arg == *p))
+            .collect()
+    }
+}
+
+/// Names in `{name}` placeholders of desc, skipping escaped `\{`, `{{ blocks }}`, and
+/// `{# comments #}` in the template syntax. Only the first segment of `{a.b | formatter}`
+/// is returned.
+pub fn placeholders(desc: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = desc;
+    while let Some(idx) = rest.find('{') {
+        let escaped = rest[..idx].ends_with('\\');
+        rest = &rest[idx + 1..];
+        let Some(end) = rest.find('}') else { break };
+        let inner = &rest[..end];
+        rest = &rest[end + 1..];
+        if escaped || inner.starts_with(['{', '#']) {
+            continue;
+        }
+        let path = inner.split('|').next().unwrap_or(inner).trim();
+        let name = path.split('.').next().unwrap_or(path);
+        if !name.is_empty() && !name.starts_with('@') {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// Deserialize `"Tag"` or `["Tag1", "Tag2"]` to a list of tags.
+fn one_or_many<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Box<[Str]>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(Str),
+        Many(Box<[Str]>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(s) => Box::new([s]),
+        OneOrMany::Many(v) => v,
+    })
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, Serialize, Default, PartialEq, Eq)]
@@ -56,18 +181,23 @@ pub enum TagType {
 
 impl TagType {
     pub fn new(s: &str) -> Self {
+        Self::try_new(s).unwrap_or_else(|| panic!("Only support: precond, hazard, and option."))
+    }
+
+    /// None means the type is not one of precond, hazard, and option.
+    pub fn try_new(s: &str) -> Option<Self> {
         match s {
-            "precond" => Self::Precond,
-            "hazard" => Self::Hazard,
-            "option" => Self::Option,
-            _ => panic!("Only support: precond, hazard, and option."),
+            "precond" => Some(Self::Precond),
+            "hazard" => Some(Self::Hazard),
+            "option" => Some(Self::Option),
+            _ => None,
         }
     }
 
     pub fn as_str(&self) -> &'static str {
         match self {
             TagType::Precond => "precond",
-            TagType::Hazard => "Hazard",
+            TagType::Hazard => "hazard",
             TagType::Option => "option",
         }
     }
@@ -99,6 +229,22 @@ impl GenDocOption {
     }
 }
 
+/// Policies on the usage of all tags.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Default)]
+pub struct Policy {
+    /// Every discharge must be justified by a reason string at `checked` sites.
+    #[serde(default)]
+    pub require_reason: bool,
+}
+
+impl Policy {
+    fn merge(&mut self, other: &Self) {
+        if other.require_reason {
+            self.require_reason = true;
+        }
+    }
+}
+
 /// `any` tag is denied in user's spec, and special in doc generation.
 pub const ANY: &str = "any";
 
@@ -110,6 +256,9 @@ pub struct Key {
     /// File path where the tag is defined: we must be sure each tag only
     /// derives from single file path.
     pub src: Str,
+    /// Version of the spec package where the tag is defined.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: OptStr,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -118,56 +267,151 @@ pub struct Cache {
     pub map: IndexMap<Str, Key>,
     /// Merged doc generation options: if any is true, set true.
     pub doc: GenDocOption,
+    /// Merged policies: if any is true, set true.
+    #[serde(default)]
+    pub policy: Policy,
 }
 
 pub static CACHE: LazyLock<Cache> = LazyLock::new(|| {
-    let mut cache = Cache::default();
-
-    let configs: Vec<_> = env::toml_file_paths()
-        .into_iter()
-        .map(|f| (Configuration::read_toml(&f), f.into_boxed_str()))
-        .collect();
-    let cap = configs.iter().map(|c| c.0.tag.len()).sum();
-    cache.map.reserve(cap);
-
-    for (config, path) in configs {
-        for (name, tag) in config.tag {
-            if &*name == ANY {
-                panic!("`any` is a builtin tag. Please remove it from spec.");
-            }
-            if let Some(old) = cache.map.get(&name) {
-                panic!("Tag {name:?} has been defined: {old:?}");
+    let cache = Cache::load(&env::toml_file_paths()).unwrap_or_else(|err| panic!("{err}"));
+    eprintln!("Got {} tags.", cache.map.len());
+    cache
+});
+
+impl Cache {
+    /// Read and merge spec TOML files. Unlike [`Configuration::read_toml`], an error is returned
+    /// rather than panicking on unreadable files or invalid specs. Unknown placeholders in desc
+    /// are only warned.
+    pub fn load(paths: &[String]) -> Result<Cache, String> {
+        let mut cache = Cache::default();
+
+        let mut configs = Vec::with_capacity(paths.len());
+        for path in paths {
+            let text =
+                fs::read_to_string(path).map_err(|e| format!("Failed to read {path}:\n{e}"))?;
+            let config: Configuration =
+                toml::from_str(&text).map_err(|e| format!("Failed to parse {path}:\n{e}"))?;
+            configs.push((config, Str::from(&**path)));
+        }
+        let cap = configs.iter().map(|c| c.0.tag.len()).sum();
+        cache.map.reserve(cap);
+
+        for (config, path) in configs {
+            let version = config.package.and_then(|package| package.version);
+            for (name, tag) in config.tag {
+                if &*name == ANY {
+                    return Err("`any` is a builtin tag. Please remove it from spec.".to_owned());
+                }
+                if let Some(old) = cache.map.get(&name) {
+                    return Err(format!("Tag {name:?} has been defined: {old:?}"));
+                }
+                // the desc is kept as is in docs, so don't fail the whole spec on it
+                if let Some(p) = tag.unknown_placeholders().first() {
+                    eprintln!(
+                        "Placeholder {{{p}}} in the desc of tag {name:?} is not in args: {path}"
+                    );
+                }
+                _ = cache
+                    .map
+                    .insert(name, Key { tag, src: path.clone(), version: version.clone() });
             }
-            _ = cache.map.insert(name, Key { tag, src: path.clone() });
+            cache.doc.merge(&config.doc);
+            cache.policy.merge(&config.policy);
         }
-        cache.doc.merge(&config.doc);
+
+        cache.map.sort_unstable_keys();
+        Ok(cache)
     }
+}
 
-    cache.map.sort_unstable_keys();
-    eprintln!("Got {} tags.", cache.map.len());
-    cache
-});
+/// Spec taken at runtime in place of [`CACHE`] in queries below.
+enum Reloaded {
+    Spec(Arc<Cache>),
+    /// No spec, e.g. the initial loading fails, so that tags are not checked.
+    NoSpec,
+}
+
+static RELOADED: RwLock<Option<Reloaded>> = RwLock::new(None);
 
-pub fn get_tag(name: &str) -> &'static Tag {
-    &CACHE.map.get(name).unwrap_or_else(|| panic!("Tag {name:?} is not defined")).tag
+/// Reload spec from TOML files, e.g. when they are edited while the language server is
+/// running. The previous spec is kept if an error occurs. Returns the number of tags.
+///
+/// A replaced spec is freed once no [`SpecRef`] from [`cache`] refers to it.
+pub fn reload(paths: &[String]) -> Result<usize, String> {
+    let cache = Cache::load(paths)?;
+    let count = cache.map.len();
+    *RELOADED.write().unwrap() = Some(Reloaded::Spec(Arc::new(cache)));
+    Ok(count)
 }
 
-pub fn get_tag_opt(name: &str) -> Option<&'static Tag> {
-    CACHE.map.get(name).map(|val| &val.tag)
+/// Take no spec until the next successful [`reload`]: no tag is defined, and
+/// [`spec_exists`] is false even if environment variables are given.
+pub fn unload() {
+    *RELOADED.write().unwrap() = Some(Reloaded::NoSpec);
+}
+
+/// The current spec: reloaded one if any, otherwise the one from environment variables.
+pub fn cache() -> SpecRef {
+    static EMPTY: LazyLock<Cache> = LazyLock::new(Cache::default);
+    match &*RELOADED.read().unwrap() {
+        Some(Reloaded::Spec(cache)) => SpecRef::Reloaded(cache.clone()),
+        Some(Reloaded::NoSpec) => SpecRef::Static(&EMPTY),
+        None => SpecRef::Static(&CACHE),
+    }
+}
+
+/// Spec returned by [`cache`], which keeps a reloaded spec alive while it's used.
+pub enum SpecRef {
+    Static(&'static Cache),
+    Reloaded(Arc<Cache>),
+}
+
+impl ops::Deref for SpecRef {
+    type Target = Cache;
+
+    fn deref(&self) -> &Cache {
+        match self {
+            SpecRef::Static(cache) => cache,
+            SpecRef::Reloaded(cache) => cache,
+        }
+    }
+}
+
+/// Whether spec is given, either through environment variables or reloading.
+pub fn spec_exists() -> bool {
+    match *RELOADED.read().unwrap() {
+        Some(Reloaded::Spec(_)) => true,
+        Some(Reloaded::NoSpec) => false,
+        None => env::config_exists(),
+    }
+}
+
+pub fn get_tag(name: &str) -> Tag {
+    get_tag_opt(name).unwrap_or_else(|| panic!("Tag {name:?} is not defined"))
+}
+
+pub fn get_tag_opt(name: &str) -> Option<Tag> {
+    cache().map.get(name).map(|val| val.tag.clone())
 }
 
 pub fn doc_option() -> GenDocOption {
-    CACHE.doc
+    cache().doc
+}
+
+/// Whether discharges of the tag must be justified by a reason string,
+/// either required by the tag itself or the global policy.
+pub fn require_reason(name: &str) -> bool {
+    cache().policy.require_reason || get_tag_opt(name).is_some_and(|tag| tag.require_reason)
 }
 
 pub struct DefinedTag {
-    pub name: &'static str,
-    pub args: &'static Tag,
+    pub name: Str,
+    pub args: Tag,
 }
 
 impl DefinedTag {
     pub fn hover_detail(&self) -> String {
-        let name = self.name;
+        let name = &*self.name;
         let args = &*self.args.args;
         if args.is_empty() {
             name.to_owned()
@@ -178,29 +422,12 @@ impl DefinedTag {
     }
 
     pub fn hover_documentation(&self) -> String {
-        use std::fmt::Write;
-
-        let DefinedTag { args: Tag { desc, expr, types, url, .. }, .. } = self;
-        let mut doc = String::new();
-
-        let types_field = if types.len() == 1 { "type" } else { "types" };
-        let types = types.iter().map(|t| t.as_str()).collect::<Vec<_>>().join(", ");
-        _ = writeln!(&mut doc, "**{types_field}**: {types}\n");
-
-        if let Some(desc) = desc {
-            _ = writeln!(&mut doc, "**desc**: {desc}\n");
-        }
-        if let Some(expr) = expr {
-            _ = writeln!(&mut doc, "**expr**: {expr}\n");
-        }
-        if let Some(url) = url {
-            _ = writeln!(&mut doc, "**url**: <{url}>");
-        }
-        doc
+        self.args.hover_documentation()
     }
 }
 
 /// Get all tags defined in all spec TOMLs.
 pub fn get_tags() -> Box<[DefinedTag]> {
-    CACHE.map.iter().map(|(k, v)| DefinedTag { name: k, args: &v.tag }).collect()
+    let cache = cache();
+    cache.map.iter().map(|(k, v)| DefinedTag { name: k.clone(), args: v.tag.clone() }).collect()
 }
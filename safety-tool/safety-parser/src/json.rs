@@ -3,15 +3,15 @@ use crate::{
     safety::{PropertiesAndReason, Property},
 };
 use indexmap::IndexMap;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Serialize)]
+#[derive(Deserialize, Serialize)]
 pub struct Ouput {
     pub v_fn: IndexMap<String, Vec<OutputProperties>>,
     pub spec: IndexMap<Box<str>, configuration::Key>,
 }
 
-#[derive(Serialize)]
+#[derive(Deserialize, Serialize)]
 pub struct OutputProperties {
     pub tags: Vec<OutputProperty>,
     pub desc: Option<Box<str>>,
@@ -26,7 +26,7 @@ impl OutputProperties {
     }
 }
 
-#[derive(Serialize)]
+#[derive(Deserialize, Serialize)]
 pub struct OutputProperty {
     pub sp: Property,
     pub doc: Box<str>,
@@ -1,6 +1,10 @@
 use crate::{
     Str,
-    configuration::{ANY, Tag, TagType, doc_option, env::need_check, get_tag, get_tag_opt},
+    configuration::{
+        ANY, Tag, TagType, doc_option,
+        env::{need_check, without_check},
+        get_tag, get_tag_opt,
+    },
 };
 use indexmap::IndexMap;
 use proc_macro2::TokenStream;
@@ -8,7 +12,8 @@ use quote::quote;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use syn::{
-    parse::{Parse, ParseStream},
+    ext::IdentExt,
+    parse::{Parse, ParseStream, Parser},
     punctuated::Punctuated,
     token::{Brace, Paren},
     *,
@@ -16,6 +21,9 @@ use syn::{
 
 mod utils;
 
+mod validate;
+pub use validate::{Problem, attr_name, is_safety_attr, validate_attr};
+
 #[cfg(test)]
 mod tests;
 
@@ -38,6 +46,11 @@ impl Parse for SafetyAttr {
         let attr = attrs.remove(0);
         drop(attrs);
 
+        if is_ref_attr(&attr) {
+            // `#[ref(id)]` carries entity ids rather than tags.
+            return Ok(SafetyAttr { attr, args: SafetyAttrArgs { args: Punctuated::new() } });
+        }
+
         // We don't check attribute name. Normally, it's #[safety { ... }],
         // but it can be #[path::to::safety {}], or #[reexported {}], or #[rapx::inner {}].
 
@@ -52,6 +65,44 @@ pub fn parse_attr_and_get_properties(attr: &str) -> Box<[PropertiesAndReason]> {
     attr.args.args.into_iter().collect()
 }
 
+/// Parse a full attribute to get tags in it, including those in `any` tags, without checking
+/// the spec. Malformed tags in `any` are skipped.
+pub fn parse_attr_and_get_tags(attr: &str) -> Vec<TagNameType> {
+    parse_attr_and_flatten_properties(attr).into_iter().map(|prop| prop.tag).collect()
+}
+
+/// Parse a full attribute to get properties in it, with `any` tags replaced by properties
+/// in them, without checking the spec. Malformed properties in `any` are skipped.
+pub fn parse_attr_and_flatten_properties(attr: &str) -> Vec<Property> {
+    without_check(|| {
+        let Ok(attr) = parse_str::<SafetyAttr>(attr) else { return Vec::new() };
+        let mut props = Vec::new();
+        for (prop, _) in attr.args.property_reason() {
+            prop.flatten_into(&mut props);
+        }
+        props
+    })
+}
+
+/// Tool attribute name in the entity reference system: `#[ref(id)]` links
+/// sites sharing a single safety justification.
+pub const REF: &str = "ref";
+
+fn is_ref_attr(attr: &Attribute) -> bool {
+    attr.path().segments.last().is_some_and(|seg| seg.ident.unraw() == REF)
+}
+
+/// Parse a full attribute such as `#[rapx::r#ref(id)]` to get entity ids.
+/// None means the attribute is not a ref attribute or malformed.
+pub fn parse_ref_attr(attr: &str) -> Option<Box<[Str]>> {
+    let attr = Attribute::parse_outer.parse_str(attr).ok()?.pop()?;
+    if !is_ref_attr(&attr) {
+        return None;
+    }
+    let ids = attr.parse_args_with(Punctuated::<Ident, Token![,]>::parse_terminated).ok()?;
+    Some(ids.iter().map(|id| id.unraw().to_string().into()).collect())
+}
+
 #[derive(Debug)]
 pub struct SafetyAttrArgs {
     pub args: Punctuated<PropertiesAndReason, Token![;]>,
@@ -204,7 +255,7 @@ impl PropertiesAndReason {
     pub fn new_single_sp(name: &str) -> Self {
         PropertiesAndReason {
             tags: Box::new([Property {
-                tag: TagNameType { typ: None, name: name.into() },
+                tag: TagNameType { typ: None, name: name.into(), span: None },
                 args: Box::default(),
             }]),
             desc: None,
@@ -256,7 +307,11 @@ impl Property {
             return Some(doc);
         }
 
-        let defined_tag = get_tag_opt(name)?;
+        self.gen_doc_with(&get_tag_opt(name)?)
+    }
+
+    /// Generate doc from the desc of the given definition, with args interpolated.
+    pub fn gen_doc_with(&self, defined_tag: &Tag) -> Option<String> {
         // NOTE: this tolerates missing args, but position matters.
         let args_len = self.args.len().min(defined_tag.args.len());
 
@@ -280,6 +335,19 @@ impl Property {
             .then(|| utils::parse_args_in_any_tag(&self.args))
     }
 
+    /// Push the property or properties in `any` tag.
+    fn flatten_into(&self, props: &mut Vec<Property>) {
+        if self.tag.name() != ANY {
+            props.push(self.clone());
+            return;
+        }
+        for expr in &self.args {
+            if let Ok(any) = syn::parse2::<PropertiesAndReason>(quote! { #expr }) {
+                any.tags.iter().for_each(|prop| prop.flatten_into(props));
+            }
+        }
+    }
+
     pub fn args_as_string(&self) -> Box<[String]> {
         self.args.iter().map(utils::expr_to_string).collect()
     }
@@ -294,6 +362,9 @@ pub struct TagNameType {
     typ: Option<TagType>,
     /// Single ident string.
     name: Str,
+    /// Span of the tag name in the parsed source, which is unknown for deserialized tags.
+    #[serde(skip)]
+    span: Option<proc_macro2::Span>,
 }
 
 impl Parse for TagNameType {
@@ -303,10 +374,14 @@ impl Parse for TagNameType {
         Ok(if input.peek(Token![.]) {
             let _: Token![.] = input.parse()?;
             let second: Ident = input.parse()?;
-            let name = second.to_string().into();
-            TagNameType { name, typ: Some(TagType::new(&first)) }
+            let Some(typ) = TagType::try_new(&first) else {
+                let msg = format!("Tag type `{first}` is not one of precond, hazard, and option.");
+                return Err(Error::new(ident.span(), msg));
+            };
+            let span = Some(second.span());
+            TagNameType { name: second.to_string().into(), typ: Some(typ), span }
         } else {
-            TagNameType { name: first.into(), typ: None }
+            TagNameType { name: first.into(), typ: None, span: Some(ident.span()) }
         })
     }
 }
@@ -327,6 +402,11 @@ impl TagNameType {
         (&self.name, self.typ)
     }
 
+    /// Span of `SP` in `SP` or `type.SP` if the tag is parsed from source.
+    pub fn span(&self) -> Option<proc_macro2::Span> {
+        self.span
+    }
+
     /// Check if the tag in macro is wrongly specified.
     pub fn check_type(&self) {
         let (name, typ) = self.name_type();
@@ -334,7 +414,7 @@ impl TagNameType {
             // FIXME: check SP args here
             return;
         }
-        let defined_types = &get_tag(name).types;
+        let defined_types = get_tag(name).types;
         if let Some(typ) = typ {
             assert!(
                 defined_types.contains(&typ),
@@ -354,7 +434,7 @@ impl TagNameType {
     }
 
     /// Get specification of the tag in TOML.
-    pub fn get_spec(&self) -> Option<&'static Tag> {
+    pub fn get_spec(&self) -> Option<Tag> {
         get_tag_opt(&self.name)
     }
 }
@@ -103,3 +103,55 @@ fn parse_sp_str() {
     assert_eq!(&*args[0].tags[0].tag.name, tag1);
     assert_eq!(&*args[1].tags[0].tag.name, tag2);
 }
+
+#[test]
+fn parse_ref() {
+    let ids = parse_ref_attr("#[rapx::r#ref(try_fold)]").unwrap();
+    assert_eq!(&*ids, [Str::from("try_fold")]);
+
+    let ids = parse_ref_attr("#[safety::r#ref(a, b)]").unwrap();
+    assert_eq!(&*ids, [Str::from("a"), Str::from("b")]);
+
+    assert!(parse_ref_attr("#[rapx::checked(SP)]").is_none());
+
+    // ref attributes have no tags to check
+    let attr = parse_str::<SafetyAttr>("#[rapx::r#ref(try_fold)]").unwrap();
+    assert!(attr.args.args.is_empty());
+}
+
+#[test]
+fn validate_safety_attr() {
+    assert!(is_safety_attr("#[safety { SP }]"));
+    assert!(is_safety_attr("#[safety::requires(SP)]"));
+    assert!(is_safety_attr("#[rapx::inner(SP)]"));
+    // recognized while being edited
+    assert!(is_safety_attr("#[safety::checked(SP"));
+    assert!(!is_safety_attr("#[rapx::r#ref(id)]"));
+    assert!(!is_safety_attr("#[derive(Debug)]"));
+    assert!(!is_safety_attr("#[inline]"));
+
+    assert!(validate_attr("#[safety::requires(SP1, SP2(p): \"reason\")]").is_empty());
+
+    // wrong syntax
+    let problems = validate_attr("#[safety::requires(SP: reason)]");
+    assert_eq!(problems.len(), 1, "{problems:?}");
+
+    // wrong tag type doesn't panic
+    let problems = validate_attr("#[safety::requires(unknown.SP)]");
+    assert_eq!(problems.len(), 1, "{problems:?}");
+    assert!(problems[0].message.contains("`unknown`"), "{problems:?}");
+
+    // wrong syntax in any tag
+    let problems = validate_attr("#[safety::requires(any(SP1, unknown.SP2))]");
+    assert_eq!(problems.len(), 1, "{problems:?}");
+}
+
+#[test]
+fn get_tags_with_spans() {
+    let tags = parse_attr_and_get_tags("#[safety::checked(SP1, any(hazard.SP2, SP3(p)): \"\")]");
+    let names: Vec<_> = tags.iter().map(|tag| tag.name()).collect();
+    assert_eq!(names, ["SP1", "SP2", "SP3"]);
+
+    let columns: Vec<_> = tags.iter().map(|tag| tag.span().unwrap().start().column).collect();
+    assert_eq!(columns, [18, 34, 39]);
+}
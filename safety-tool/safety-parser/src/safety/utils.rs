@@ -80,10 +80,12 @@ pub fn parse_args_in_any_tag(args: &[Expr]) -> Vec<PropertiesAndReason> {
     v_sp
 }
 
+/// Interpolate args into desc. Desc is kept as is if it's not a valid template, or contains
+/// placeholders not in args, which are warned when spec is loaded.
 pub fn template(desc: &str, map: &IndexMap<&str, String>) -> String {
     let mut template = tinytemplate::TinyTemplate::new();
-    template.add_template("", desc).unwrap();
-    let mut doc = template.render("", map).unwrap();
+    let rendered = template.add_template("", desc).and_then(|_| template.render("", map));
+    let mut doc = rendered.unwrap_or_else(|_| desc.to_owned());
     doc.push('\n'); // add extra newline
     doc.push('\n'); // add extra newline
     doc
@@ -0,0 +1,132 @@
+//! Report wrongly specified tags as problems rather than panics.
+use super::{PropertiesAndReason, Property, REF, SafetyAttr};
+use crate::configuration::{ANY, env::without_check, get_tag_opt, spec_exists};
+use proc_macro2::Span;
+use quote::ToTokens;
+use syn::parse_str;
+
+/// A problem in a safety attribute.
+#[derive(Debug)]
+pub struct Problem {
+    /// Span in the attribute string. Enable `span-locations` feature of proc-macro2 to
+    /// get line and column out of it.
+    pub span: Span,
+    pub message: String,
+}
+
+impl Problem {
+    fn new(span: Span, message: String) -> Self {
+        Problem { span, message }
+    }
+}
+
+/// Attribute names carrying tags, e.g. `#[safety { SP }]`, `#[safety::requires(SP)]`.
+const TAG_ATTRS: &[&str] = &["safety", "requires", "checked"];
+/// Registered tool of tool attributes, e.g. `#[rapx::inner(SP)]`.
+const TOOL: &str = "rapx";
+
+/// Whether the attribute is meant to carry tags, judged by its path only such that
+/// an attribute being edited with broken syntax is still recognized.
+/// `#[ref(id)]` is not since it carries entity ids.
+pub fn is_safety_attr(attr: &str) -> bool {
+    let segments = attr_path(attr);
+    match segments.as_slice() {
+        [.., last] if *last == REF => false,
+        [first, ..] if *first == TOOL => true,
+        [.., last] => TAG_ATTRS.contains(&&**last),
+        [] => false,
+    }
+}
+
+/// The last segment in the path of an attribute string, e.g. `requires` for
+/// `#[safety::requires(SP)]`, which is recognized even with broken syntax.
+pub fn attr_name(attr: &str) -> Option<String> {
+    attr_path(attr).pop()
+}
+
+fn attr_path(attr: &str) -> Vec<String> {
+    let Some(attr) = attr.trim_start().strip_prefix("#[") else { return Vec::new() };
+    let path: String = attr
+        .chars()
+        .take_while(|c| !matches!(c, '(' | '{' | '[' | ']' | '='))
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    path.split("::")
+        .filter(|s| !s.is_empty())
+        .map(|s| s.trim_start_matches("r#").to_owned())
+        .collect()
+}
+
+/// Validate a full attribute such as `#[safety::requires(SP)]`:
+/// * syntax errors
+/// * tags not defined in spec TOML
+/// * tag types not defined for tags, or missing when multiple types are defined
+/// * the number of args differs from the definition if args are given
+///
+/// Spec checks are skipped if no spec TOML is given.
+pub fn validate_attr(attr: &str) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    match without_check(|| parse_str::<SafetyAttr>(attr)) {
+        Ok(attr) => {
+            for (prop, _) in attr.args.property_reason() {
+                validate_property(prop, &mut problems);
+            }
+        }
+        Err(err) => push_syn_error(err, &mut problems),
+    }
+    problems
+}
+
+fn push_syn_error(err: syn::Error, problems: &mut Vec<Problem>) {
+    problems.extend(err.into_iter().map(|err| Problem::new(err.span(), err.to_string())));
+}
+
+fn validate_property(prop: &Property, problems: &mut Vec<Problem>) {
+    let (name, typ) = prop.tag.name_type();
+    let span = prop.tag.span().unwrap_or_else(Span::call_site);
+
+    if name == ANY {
+        for expr in &prop.args {
+            let tokens = expr.to_token_stream();
+            match without_check(|| syn::parse2::<PropertiesAndReason>(tokens)) {
+                Ok(props) => props.tags.iter().for_each(|prop| validate_property(prop, problems)),
+                Err(err) => push_syn_error(err, problems),
+            }
+        }
+        return;
+    }
+
+    if !spec_exists() {
+        return;
+    }
+    let Some(defined) = get_tag_opt(name) else {
+        problems.push(Problem::new(span, format!("Tag `{name}` is not defined in spec.")));
+        return;
+    };
+
+    let types = || defined.types.iter().map(|t| t.as_str()).collect::<Vec<_>>().join(", ");
+    match typ {
+        Some(typ) if !defined.types.contains(&typ) => {
+            let msg = format!("Tag `{name}` is not of type {}, but {}.", typ.as_str(), types());
+            problems.push(Problem::new(span, msg));
+        }
+        None if defined.types.len() > 1 => {
+            let msg = format!(
+                "Tag `{name}` is defined with multiple types: {}. \
+                 Choose one to be `type.{name}`.",
+                types()
+            );
+            problems.push(Problem::new(span, msg));
+        }
+        _ => (),
+    }
+
+    let (expected, given) = (defined.args.len(), prop.args.len());
+    if given != 0 && given != expected {
+        let msg = format!(
+            "Tag `{name}` expects {expected} args ({}), but {given} are given.",
+            defined.args.join(", ")
+        );
+        problems.push(Problem::new(span, msg));
+    }
+}
@@ -1,5 +1,8 @@
 use std::{env::var, process::Command};
 
+/// Entry of a subcommand taking arguments after its name.
+type Subcommand = fn(&[String]) -> safety_tool::Result<()>;
+
 fn main() {
     // Search cargo-safety-tool and safety-tool CLI through environment variables,
     // or just use the name if absent.
@@ -29,6 +32,21 @@ fn main() {
                 // [cargo, safety-tool, args...]
                 args.remove(0);
             }
+            let subcommand: Option<Subcommand> = match args.get(1).map(String::as_str) {
+                Some("diff") => Some(safety_tool::diff::run),
+                // Query the database collected during compilation.
+                Some("query") => Some(safety_tool::query::run),
+                // Merge Stat files of all crates into a workspace report.
+                Some("report") => Some(safety_tool::report::run),
+                // Remove crates no longer in `cargo metadata` from the database.
+                Some("gc") => Some(safety_tool::db::gc_with_cargo_metadata),
+                _ => None,
+            };
+            if let Some(subcommand) = subcommand {
+                // [cargo-safety-tool, subcommand, args...]
+                exit_on_err(subcommand(&args[2..]));
+                return;
+            }
             args[0] = "build".to_owned();
         } else {
             unimplemented!("Need to support this case: {args:#?}")
@@ -38,6 +56,14 @@ fn main() {
     }
 }
 
+/// Print the error of a subcommand and exit with failure.
+fn exit_on_err(result: safety_tool::Result<()>) {
+    if let Err(err) = result {
+        eprintln!("{err}");
+        std::process::exit(1)
+    }
+}
+
 fn run(cmd: &str, args: &[String], vars: &[(&str, &str)]) {
     let status = Command::new(cmd)
         .args(args)
@@ -0,0 +1,105 @@
+//! Audit records of the local crate for queries in SQL: unsafe callsites with tags discharged
+//! on them, and diagnostics. Tags required by functions are derived from [`Data`].
+use super::{Data, PrimaryKey};
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+use safety_parser::safety::parse_attr_and_get_properties;
+
+/// Records of the local crate other than tagged functions.
+#[derive(Debug)]
+pub struct Audit {
+    pub callsites: Vec<Callsite>,
+    pub diagnostics: Vec<DiagnosticRecord>,
+}
+
+/// A tag required by a function.
+#[derive(Debug)]
+pub struct Requirement {
+    pub tag: String,
+    /// Args separated by comma, or empty.
+    pub args: String,
+    /// Alternatives in the same `any` tag share the index of the group,
+    /// while a plain tag has none.
+    pub any_group: Option<u32>,
+}
+
+impl Data {
+    /// Tags required by the function in the order of definition.
+    pub fn requirements(&self) -> Vec<Requirement> {
+        let mut v = Vec::new();
+        let mut group = 0;
+        for attr in &self.func.tool_attrs {
+            for prop in &*parse_attr_and_get_properties(attr) {
+                for tag in &*prop.tags {
+                    if let Some(v_sp) = tag.args_in_any_tag() {
+                        for sp in v_sp.iter().flat_map(|p| &*p.tags) {
+                            v.push(Requirement::new(sp, Some(group)));
+                        }
+                        group += 1;
+                    } else {
+                        v.push(Requirement::new(tag, None));
+                    }
+                }
+            }
+        }
+        v
+    }
+}
+
+impl Requirement {
+    fn new(sp: &safety_parser::safety::Property, any_group: Option<u32>) -> Self {
+        let tag = sp.tag.name().to_owned();
+        Requirement { tag, args: sp.args_as_string().join(", "), any_group }
+    }
+}
+
+/// An unsafe call in the body of a local function.
+#[derive(Debug)]
+pub struct Callsite {
+    pub caller: PrimaryKey,
+    pub caller_path: String,
+    pub callee: PrimaryKey,
+    pub callee_path: String,
+    pub location: Location,
+    /// Tags in the nearest `checked` attributes for the call.
+    pub discharges: Vec<Discharge>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Discharge {
+    pub tag: String,
+    /// None if no reason is given.
+    pub reason: Option<String>,
+}
+
+/// An emitted diagnostic in plain text.
+#[derive(Debug)]
+pub struct DiagnosticRecord {
+    pub kind: String,
+    pub level: &'static str,
+    pub message: String,
+    /// None for diagnostics on the spec.
+    pub location: Option<Location>,
+}
+
+#[derive(Debug)]
+pub struct Location {
+    pub file: String,
+    /// Starting from 1.
+    pub line: u32,
+    /// Starting from 1.
+    pub col: u32,
+}
+
+impl Location {
+    pub fn new(span: Span, tcx: TyCtxt) -> Self {
+        // Point to the macro call in user code rather than the expansion.
+        let span = span.source_callsite();
+        let loc = tcx.sess.source_map().lookup_char_pos(span.lo());
+        Location {
+            file: loc.file.name.prefer_local().to_string(),
+            line: loc.line as u32,
+            col: loc.col.0 as u32 + 1,
+        }
+    }
+}
@@ -1,38 +1,61 @@
-use super::super::{HirFn, is_tool_attr};
+use super::super::{HirFn, is_ref_attr, is_tool_attr};
 use itertools::Itertools;
-use rustc_data_structures::fx::FxIndexMap;
-use rustc_hir::{Attribute, HirId, def_id::DefId};
-use rustc_middle::ty::TyCtxt;
+use rustc_data_structures::fx::{FxHashMap, FxHashSet, FxIndexMap};
+use rustc_hir::{
+    Attribute, HirId,
+    def::DefKind,
+    def_id::{CrateNum, DefId, LOCAL_CRATE},
+};
+use rustc_middle::ty::{
+    TyCtxt,
+    print::{with_no_trimmed_paths, with_no_visible_paths},
+};
+use rustc_span::Symbol;
 use safety_parser::{
-    configuration::Tag,
+    configuration::{Tag, env::without_check},
     safety::{Property as SP, parse_attr_and_get_properties},
 };
-use std::{borrow::Cow, fmt};
+use safety_tool::{
+    Utf8Path, Utf8PathBuf,
+    extern_tags::{ExternTags, requires_attr},
+};
+use serde::{Deserialize, Serialize};
+use std::{borrow::Cow, fmt, sync::Arc};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct PrimaryKey {
     pub hash1: u64,
     pub hash2: u64,
 }
 
 impl PrimaryKey {
-    fn new(def_id: DefId, tcx: TyCtxt) -> Self {
+    pub fn new(def_id: DefId, tcx: TyCtxt) -> Self {
         let (hash1, hash2) = tcx.def_path_hash(def_id).0.split();
         PrimaryKey { hash1: hash1.as_u64(), hash2: hash2.as_u64() }
     }
 }
 
-#[derive(Debug)]
+/// Def path qualified by the crate name without re-exports, e.g. `core::ptr::read`,
+/// to be matched with paths written by users.
+pub fn qualified_def_path(tcx: TyCtxt, def_id: DefId) -> String {
+    let path = with_no_visible_paths!(with_no_trimmed_paths!(tcx.def_path_str(def_id)));
+    if def_id.is_local() { format!("{}::{path}", tcx.crate_name(LOCAL_CRATE)) } else { path }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Func {
     /// Safety tool attributes
     pub tool_attrs: Vec<String>,
     /// Definition path (for debug purpose)
     pub def_path: String,
+    /// Qualified def path through [`qualified_def_path`]
+    #[serde(default)]
+    pub name: String,
     /// Function source code without attributes (for debug purpose)
     pub function: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Data {
     pub hash: PrimaryKey,
     pub func: Func,
@@ -59,6 +82,7 @@ impl Data {
                 .filter_map(|attr| opt_attribute_to_string(tcx, attr))
                 .collect(),
             def_path: tcx.def_path_debug_str(def_id),
+            name: qualified_def_path(tcx, def_id),
             function,
         };
 
@@ -66,7 +90,7 @@ impl Data {
     }
 }
 
-fn get_attrs(tcx: TyCtxt<'_>, hid: HirId) -> impl Iterator<Item = &'_ Attribute> {
+pub fn get_attrs(tcx: TyCtxt<'_>, hid: HirId) -> impl Iterator<Item = &'_ Attribute> {
     crossfig::switch! {
         crate::asterinas => {
             tcx.hir_attrs(hid.owner).get(hid.local_id).iter()
@@ -75,12 +99,36 @@ fn get_attrs(tcx: TyCtxt<'_>, hid: HirId) -> impl Iterator<Item = &'_ Attribute>
     }
 }
 
-/// Convert the tool attribute to string.
+/// Tool attributes on all HIR nodes in the local crate, including ref attributes.
+///
+/// Attributes on a statement are also on its expression, so they're only yielded once.
+pub fn crate_tool_attrs(tcx: TyCtxt<'_>) -> Vec<(HirId, &'_ Attribute)> {
+    let mut v = Vec::new();
+    let mut seen = FxHashSet::default();
+    for owner in tcx.hir_crate_items(()).owners() {
+        crossfig::switch! {
+            crate::asterinas => { let attr_map = tcx.hir_attrs(owner); }
+            _ => { let attr_map = tcx.hir_attr_map(owner); }
+        }
+        for (local_id, attrs) in attr_map.map.iter() {
+            let hir_id = HirId { owner, local_id: *local_id };
+            v.extend(
+                attrs
+                    .iter()
+                    .filter(|attr| is_tool_attr(attr) && seen.insert(attr.span()))
+                    .map(|attr| (hir_id, attr)),
+            );
+        }
+    }
+    v
+}
+
+/// Convert the tool attribute to string. Ref attributes are excluded, since they carry no tag.
 fn opt_attribute_to_string(tcx: TyCtxt<'_>, attr: &rustc_hir::Attribute) -> Option<String> {
-    is_tool_attr(attr).then(|| attribute_to_string(tcx, attr))
+    (is_tool_attr(attr) && !is_ref_attr(attr)).then(|| attribute_to_string(tcx, attr))
 }
 
-fn attribute_to_string(tcx: TyCtxt<'_>, attr: &rustc_hir::Attribute) -> String {
+pub fn attribute_to_string(tcx: TyCtxt<'_>, attr: &rustc_hir::Attribute) -> String {
     rustc_hir_pretty::attribute_to_string(&tcx, attr).trim().to_owned()
 }
 
@@ -93,7 +141,8 @@ pub struct TagState {
     ///
     /// [`args_in_any_tag`]: safety_parser::safety::Property::args_in_any_tag
     group_of_any: Vec<FxIndexMap<Property, bool>>,
-    /// If undischarged is called once. This ensures undischarged diagnostics are emitted only once.
+    /// If undischarged is called once. This ensures undischarged diagnostics are emitted only once
+    /// per callsite, and is reset along with tags of the next callsite.
     undischarged: bool,
 }
 
@@ -101,6 +150,7 @@ impl TagState {
     fn clear(&mut self) {
         self.vanilla.clear();
         self.group_of_any.clear();
+        self.undischarged = false;
     }
 
     fn refresh(&mut self, props: &Properties) {
@@ -251,6 +301,8 @@ impl Properties {
 #[derive(Debug, Default)]
 pub struct ToolAttrs {
     map: FxIndexMap<PrimaryKey, Properties>,
+    /// Tags of extern functions without tool attrs, keyed by def paths.
+    extern_map: FxIndexMap<String, ExternProperties>,
     /// State of safety tags shows if thet are discharged.
     tagged: TagState,
 }
@@ -267,20 +319,211 @@ impl ToolAttrs {
                     (d.hash, props)
                 })
                 .collect(),
+            extern_map: Default::default(),
             tagged: Default::default(),
         }
     }
 
-    pub fn get_tags(&mut self, def_id: DefId, tcx: TyCtxt) -> Option<&mut TagState> {
-        let key = PrimaryKey::new(def_id, tcx);
-        self.get_tags_via_key(key)
+    /// Tags of extern functions are only looked up when they have no tool attrs.
+    /// They're written for other crates, thus not checked against the spec.
+    pub fn with_extern_tags(mut self, tags: &ExternTags) -> Self {
+        self.extern_map = without_check(|| {
+            tags.iter()
+                .map(|(def_path, listed)| {
+                    let mut props = Properties::default();
+                    props.push_attr(&requires_attr(&listed.tags));
+                    (def_path.to_owned(), ExternProperties { props, file: listed.file.clone() })
+                })
+                .collect()
+        });
+        self
     }
 
-    fn get_tags_via_key(&mut self, key: PrimaryKey) -> Option<&mut TagState> {
-        let props = self.map.get(&key)?;
+    pub fn get_tags(&mut self, def_id: DefId, tcx: TyCtxt) -> Option<&mut TagState> {
+        let props = properties(&self.map, &self.extern_map, def_id, tcx)?;
         self.tagged.refresh(props);
         Some(&mut self.tagged)
     }
+
+    /// Names of tags required by the function, including those in `any` tags.
+    pub fn tag_names(&self, def_id: DefId, tcx: TyCtxt) -> Vec<&str> {
+        let Some(props) = properties(&self.map, &self.extern_map, def_id, tcx) else {
+            return Vec::new();
+        };
+        let any = props.group_of_any.iter().flat_map(|group| group.iter());
+        props.vanilla.iter().chain(any).map(|prop| prop.name()).collect()
+    }
+
+    /// Def paths with extern tags that no function resolves to, along with files listing them.
+    /// Only paths into crates loaded for the local crate are checked, since others may be
+    /// listed for different dependents.
+    ///
+    /// Paths are resolved along module children from crate roots, rather than by going through
+    /// all items in crates, which are collected only for methods in trait impls on demand.
+    pub fn unresolved_extern_paths(&self, tcx: TyCtxt) -> Vec<(&str, &Utf8Path)> {
+        let mut crates = FxHashMap::<_, Vec<CrateNum>>::default();
+        for &cnum in tcx.crates(()) {
+            crates.entry(tcx.crate_name(cnum).to_string()).or_default().push(cnum);
+        }
+        let mut trait_impl_methods = FxHashMap::<CrateNum, FxHashSet<(DefId, Symbol)>>::default();
+
+        let mut unresolved = Vec::new();
+        for (path, extern_props) in &self.extern_map {
+            let mut segments = path.split("::");
+            let Some(cnums) = segments.next().and_then(|krate| crates.get(krate)) else {
+                continue;
+            };
+            let segments: Vec<_> = segments.collect();
+            let Some((name, parents)) = segments.split_last() else { continue };
+            let resolved = cnums.iter().any(|&cnum| {
+                let Some(modules) = resolve_modules(tcx, cnum.as_def_id(), parents) else {
+                    return false;
+                };
+                let module = modules[modules.len() - 1];
+                resolves_to_fn(tcx, &modules, name)
+                    || trait_impl_methods
+                        .entry(cnum)
+                        .or_insert_with(|| collect_trait_impl_methods(tcx, cnum))
+                        .contains(&(module, Symbol::intern(name)))
+            });
+            if !resolved {
+                unresolved.push((&**path, &*extern_props.file));
+            }
+        }
+        unresolved
+    }
+}
+
+/// Modules, traits, or enums along the path from the crate root, starting with the root.
+fn resolve_modules(tcx: TyCtxt, root: DefId, segments: &[&str]) -> Option<Vec<DefId>> {
+    let mut modules = vec![root];
+    for segment in segments {
+        let parent = modules[modules.len() - 1];
+        let is_parent = |kind| matches!(kind, DefKind::Mod | DefKind::Trait | DefKind::Enum);
+        modules.push(child(tcx, parent, segment, is_parent)?);
+    }
+    Some(modules)
+}
+
+/// Whether the name resolves to a function defined in the last module, or a method in an
+/// inherent impl defined in it.
+fn resolves_to_fn(tcx: TyCtxt, modules: &[DefId], name: &str) -> bool {
+    let module = modules[modules.len() - 1];
+    if child(tcx, module, name, |kind| matches!(kind, DefKind::Fn | DefKind::AssocFn)).is_some() {
+        return true;
+    }
+    inherent_impls(tcx, modules).any(|impl_id| {
+        tcx.parent(impl_id) == module
+            && tcx.associated_item_def_ids(impl_id).iter().any(|&item| {
+                tcx.def_kind(item) == DefKind::AssocFn && tcx.item_name(item).as_str() == name
+            })
+    })
+}
+
+/// The child item defined, rather than re-exported, in a module, trait, or enum.
+fn child(
+    tcx: TyCtxt,
+    parent: DefId,
+    name: &str,
+    is_kind: impl Fn(DefKind) -> bool,
+) -> Option<DefId> {
+    if !matches!(tcx.def_kind(parent), DefKind::Mod | DefKind::Trait | DefKind::Enum) {
+        return None;
+    }
+    tcx.module_children(parent)
+        .iter()
+        .filter(|child| child.reexport_chain.is_empty() && child.ident.name.as_str() == name)
+        .filter_map(|child| child.res.opt_def_id())
+        .find(|&def_id| is_kind(tcx.def_kind(def_id)))
+}
+
+/// Methods in trait impls of the crate, along with modules defining the impls.
+fn collect_trait_impl_methods(tcx: TyCtxt, cnum: CrateNum) -> FxHashSet<(DefId, Symbol)> {
+    let mut methods = FxHashSet::default();
+    for &impl_id in tcx.trait_impls_in_crate(cnum) {
+        for &item in tcx.associated_item_def_ids(impl_id) {
+            if tcx.def_kind(item) == DefKind::AssocFn {
+                methods.insert((tcx.parent(impl_id), tcx.item_name(item)));
+            }
+        }
+    }
+    methods
+}
+
+/// Inherent impls that may be defined in the last module: impls of types defined in the
+/// modules, e.g. `Box` in `alloc::boxed` for impls in `alloc::boxed::convert`, impls of trait
+/// objects, e.g. `dyn Any` in `core::any`, and impls of primitive types, e.g. `*const T` for
+/// impls in `core::ptr::const_ptr`.
+fn inherent_impls(tcx: TyCtxt, modules: &[DefId]) -> impl Iterator<Item = DefId> {
+    use rustc_middle::ty::{FloatTy, IntTy, Mutability, UintTy, fast_reject::SimplifiedType};
+
+    let local_types = modules.iter().flat_map(move |&module| {
+        let children = tcx.module_children(module).iter();
+        children.filter_map(|child| child.res.opt_def_id()).filter(move |&def_id| {
+            let kind = tcx.def_kind(def_id);
+            matches!(kind, DefKind::Struct | DefKind::Enum | DefKind::Union | DefKind::Trait)
+        })
+    });
+    let local_impls = local_types.flat_map(move |ty| tcx.inherent_impls(ty).iter().copied());
+
+    let mut primitives = vec![SimplifiedType::Bool, SimplifiedType::Char, SimplifiedType::Str];
+    primitives.extend([SimplifiedType::Array, SimplifiedType::Slice]);
+    for mutbl in [Mutability::Not, Mutability::Mut] {
+        primitives.extend([SimplifiedType::Ptr(mutbl), SimplifiedType::Ref(mutbl)]);
+    }
+    primitives.extend(
+        [IntTy::Isize, IntTy::I8, IntTy::I16, IntTy::I32, IntTy::I64, IntTy::I128]
+            .map(SimplifiedType::Int),
+    );
+    primitives.extend(
+        [UintTy::Usize, UintTy::U8, UintTy::U16, UintTy::U32, UintTy::U64, UintTy::U128]
+            .map(SimplifiedType::Uint),
+    );
+    primitives.extend(
+        [FloatTy::F16, FloatTy::F32, FloatTy::F64, FloatTy::F128].map(SimplifiedType::Float),
+    );
+    let primitive_impls =
+        primitives.into_iter().flat_map(move |simp| tcx.incoherent_impls(simp).iter().copied());
+
+    local_impls.chain(primitive_impls)
+}
+
+#[derive(Debug)]
+struct ExternProperties {
+    props: Properties,
+    /// The file listing the def path.
+    file: Utf8PathBuf,
+}
+
+/// Properties from tool attrs, or extern tags if the extern function has no tool attrs.
+fn properties<'a>(
+    map: &'a FxIndexMap<PrimaryKey, Properties>,
+    extern_map: &'a FxIndexMap<String, ExternProperties>,
+    def_id: DefId,
+    tcx: TyCtxt,
+) -> Option<&'a Properties> {
+    if let Some(props) = map.get(&PrimaryKey::new(def_id, tcx)) {
+        return Some(props);
+    }
+    if def_id.is_local() || extern_map.is_empty() {
+        return None;
+    }
+    let mut paths = extern_def_paths(tcx, def_id).into_iter();
+    paths.find_map(|path| extern_map.get(&path)).map(|extern_props| &extern_props.props)
+}
+
+/// Def paths to look up extern tags: the qualified def path, and for a method in an impl,
+/// the path of the module defining the impl joined with the method name, e.g.
+/// `core::ptr::const_ptr::as_ref`.
+fn extern_def_paths(tcx: TyCtxt, def_id: DefId) -> Vec<String> {
+    let mut paths = vec![qualified_def_path(tcx, def_id)];
+    if let Some(parent) = tcx.opt_parent(def_id)
+        && let DefKind::Impl { .. } = tcx.def_kind(parent)
+    {
+        let module = qualified_def_path(tcx, tcx.parent(parent));
+        paths.push(format!("{module}::{}", tcx.item_name(def_id)));
+    }
+    paths
 }
 
 #[derive(Clone)]
@@ -288,7 +531,7 @@ pub struct Property {
     // SP name. This represents a unique property, so spec is not involved
     // when Self type is implemented basic traits.
     name: Box<str>,
-    spec: Option<&'static Tag>,
+    spec: Option<Arc<Tag>>,
 }
 
 impl std::hash::Hash for Property {
@@ -356,7 +599,7 @@ impl Property {
     pub fn info(&self) -> Cow<'static, str> {
         const SP_DESC: &str = "This SP has no description.";
 
-        if let Some(tag) = self.spec {
+        if let Some(tag) = &self.spec {
             return match (&tag.desc, &tag.url) {
                 (Some(desc), None) => format!("{desc}").into(),
                 (Some(desc), Some(url)) => format!("{desc}\n See {url}",).into(),
@@ -380,7 +623,7 @@ fn push_properties(s: &str, v: &mut Vec<Property>) {
 }
 
 fn to_prop(sp: &SP) -> Property {
-    Property { name: sp.tag.name().into(), spec: sp.tag.get_spec() }
+    Property { name: sp.tag.name().into(), spec: sp.tag.get_spec().map(Arc::new) }
 }
 
 pub fn tool_attr_on_hir<'tcx>(
@@ -1,21 +1,59 @@
+use rustc_hir::def_id::LOCAL_CRATE;
+use rustc_middle::ty::TyCtxt;
+use safety_tool::extern_tags::ExternTags;
+
 mod storage;
-pub use storage::Database;
+pub use storage::{CrateKey, Database};
+
+mod sidecar;
+
+mod audit;
+pub use audit::{Audit, Callsite, DiagnosticRecord, Discharge, Location};
 
 mod data;
-pub use data::{Data, Func, PrimaryKey, Property, TagState, ToolAttrs, tool_attr_on_hir};
+pub use data::{
+    Data, PrimaryKey, Property, TagState, ToolAttrs, attribute_to_string, crate_tool_attrs,
+    get_attrs, qualified_def_path, tool_attr_on_hir,
+};
+
+/// Tags of functions in the local crate and dependencies.
+///
+/// Tags of the local crate are exported as a sidecar file for dependents, unless compilation
+/// is stopped, in which case no artifact is there to depend on. They're also saved into the
+/// database for queries across crates, replacing rows of the crate from the last compilation,
+/// which are not read here. Extern functions without tool attrs may require tags listed in
+/// files specified by `SP_EXTERN_TAGS`, e.g. `data/std.json` for the standard library, or in
+/// `extern-tags.toml` alongside `safety-tags.toml`.
+pub fn get_all_tool_attrs(
+    tcx: TyCtxt,
+    iter: impl IntoIterator<Item = Data>,
+) -> crate::Result<ToolAttrs> {
+    let local: Vec<_> = iter.into_iter().collect();
+    if !crate::stop_compilation() {
+        sidecar::write(tcx, &local)?;
+    }
 
-pub fn get_all_tool_attrs(iter: impl IntoIterator<Item = Data>) -> crate::Result<ToolAttrs> {
-    // Recommend setting the DATA_SQLITE3 environment variable to an absolute path.
-    // The default path is relative to the folder where the crate is being compiled,
-    // leading to a sqlite3 file in each crate folder.
-    const DATA_SQLITE3: &str = "data.sqlite3";
-    let path = std::env::var("DATA_SQLITE3");
-    let path = path.as_deref().unwrap_or(DATA_SQLITE3);
+    open_database()?.save_data(&local_crate(tcx), &local)?;
 
-    let mut db = Database::new(path)?;
+    let mut v_data = sidecar::read_deps(tcx)?;
+    v_data.extend(local);
+    let extern_tags = ExternTags::load()?;
+    Ok(ToolAttrs::new(&v_data).with_extern_tags(&extern_tags))
+}
+
+/// Save callsites and diagnostics of the local crate into the database.
+pub fn save_audit(tcx: TyCtxt, audit: &Audit) -> crate::Result<()> {
+    open_database()?.save_audit(&local_crate(tcx), audit)
+}
 
-    db.save_data(iter)?;
-    let v_data = db.get_all_data()?;
+fn open_database() -> crate::Result<Database> {
+    Database::new(&safety_tool::db::path())
+}
 
-    Ok(ToolAttrs::new(&v_data))
+fn local_crate(tcx: TyCtxt) -> CrateKey {
+    CrateKey {
+        name: tcx.crate_name(LOCAL_CRATE).to_string(),
+        version: std::env::var("CARGO_PKG_VERSION").unwrap_or_default(),
+        hash: tcx.stable_crate_id(LOCAL_CRATE).as_u64(),
+    }
 }
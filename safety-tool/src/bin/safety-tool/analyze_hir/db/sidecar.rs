@@ -0,0 +1,58 @@
+//! Tagged functions exported as a sidecar file next to the crate metadata, and discovered
+//! for dependencies through their rlib or rmeta paths. Thus tags of dependencies are known
+//! regardless of build order and parallelism.
+use super::Data;
+use crate::Result;
+use eyre::Context;
+use rustc_middle::ty::TyCtxt;
+use rustc_session::config::{OutFileName, OutputType};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Replacing the extension of `libfoo-hash.rlib` or `libfoo-hash.rmeta`.
+const EXTENSION: &str = "safety-tool.json";
+
+fn sidecar_path(artifact: &Path) -> PathBuf {
+    artifact.with_extension(EXTENSION)
+}
+
+/// Write tagged functions of the local crate next to its metadata. A stale sidecar is
+/// removed if no function is tagged.
+pub fn write(tcx: TyCtxt, data: &[Data]) -> Result<()> {
+    let OutFileName::Real(metadata) = tcx.output_filenames(()).path(OutputType::Metadata) else {
+        return Ok(());
+    };
+    let path = sidecar_path(&metadata);
+    if data.is_empty() {
+        if path.exists() {
+            fs::remove_file(&path).with_context(|| format!("Failed to remove {path:?}"))?;
+        }
+        return Ok(());
+    }
+
+    let json = serde_json::to_string_pretty(data)?;
+    // Rename a complete file into place, so readers never see a partial one.
+    let tmp = path.with_extension(format!("{EXTENSION}.{}", std::process::id()));
+    fs::write(&tmp, json).with_context(|| format!("Failed to write {tmp:?}"))?;
+    fs::rename(&tmp, &path).with_context(|| format!("Failed to rename {tmp:?} to {path:?}"))?;
+    Ok(())
+}
+
+/// Tagged functions exported by dependencies.
+pub fn read_deps(tcx: TyCtxt) -> Result<Vec<Data>> {
+    let mut v_data = Vec::new();
+    for &krate in tcx.crates(()) {
+        let source = tcx.used_crate_source(krate);
+        let artifacts = source.rlib.iter().chain(&source.rmeta).map(|(path, _)| path);
+        let Some(path) = artifacts.map(|p| sidecar_path(p)).find(|p| p.exists()) else {
+            continue;
+        };
+        let json = fs::read_to_string(&path).with_context(|| format!("Failed to read {path:?}"))?;
+        let data: Vec<Data> =
+            serde_json::from_str(&json).with_context(|| format!("Failed to parse {path:?}"))?;
+        v_data.extend(data);
+    }
+    Ok(v_data)
+}
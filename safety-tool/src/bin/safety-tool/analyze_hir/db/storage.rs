@@ -1,62 +1,186 @@
-use super::{Data, Func, PrimaryKey};
+#[cfg(test)]
+use super::data::Func;
+use super::{Audit, Data, PrimaryKey};
 use crate::Result;
 use eyre::Context;
-use rusqlite::{Connection, named_params};
+use rusqlite::{Connection, Transaction, TransactionBehavior, named_params};
+use safety_tool::db::{open, retry};
+#[cfg(test)]
+use std::thread;
 
 pub struct Database {
     conn: Connection,
 }
 
-const CREATE: &str = "
-CREATE TABLE IF NOT EXISTS hir (
-  hash1 INTEGER NOT NULL,
-  hash2 INTEGER NOT NULL,
-  tool_attrs TEXT NOT NULL,
-  def_path TEXT NOT NULL,
-  function TEXT NOT NULL,
-  timestamp TEXT NOT NULL,
-  PRIMARY KEY (hash1, hash2)
-) STRICT;
-";
+/// The crate being compiled, whose rows are replaced as a whole.
+#[derive(Debug)]
+pub struct CrateKey {
+    pub name: String,
+    pub version: String,
+    /// `StableCrateId`, distinct among crates of the same name, e.g. a lib and its tests.
+    pub hash: u64,
+}
 
 impl Database {
     pub fn new(path: &str) -> Result<Database> {
-        let conn = Connection::open(path).with_context(|| format!("Failed to open {path}"))?;
-        conn.execute(CREATE, ()).with_context(|| format!("Failed to execute sql:\n{CREATE}"))?;
-        Ok(Database { conn })
+        Ok(Database { conn: open(path)? })
     }
 
-    pub fn save_data(&mut self, iter: impl IntoIterator<Item = Data>) -> Result<()> {
+    /// Replace the crate with all data and their requirements in a single transaction,
+    /// which is retried as a whole if the database is busy.
+    ///
+    /// Rows of the crate from the last compilation are removed, including callsites and
+    /// diagnostics, which are saved later through [`Self::save_audit`].
+    pub fn save_data(&mut self, krate: &CrateKey, v_data: &[Data]) -> Result<()> {
+        const DELETE_CRATE: &str = "
+DELETE FROM crate WHERE name = :name AND hash = :hash
+";
+        const INSERT_CRATE: &str = "
+INSERT INTO crate (name, version, hash, timestamp) VALUES (:name, :version, :hash, :timestamp)
+";
         const UPSERT: &str = "
-INSERT OR REPLACE INTO hir (hash1, hash2, tool_attrs, def_path, function, timestamp)
-VALUES (:hash1, :hash2, :tool_attrs, :def_path, :function, :timestamp)
+INSERT OR REPLACE INTO hir (crate, hash1, hash2, tool_attrs, def_path, name, function)
+VALUES (:crate, :hash1, :hash2, :tool_attrs, :def_path, :name, :function)
 ";
-        let mut stmt = self.conn.prepare(UPSERT)?;
+        const INSERT_REQUIREMENT: &str = "
+INSERT INTO requirement (hash1, hash2, tag, args, any_group)
+VALUES (:hash1, :hash2, :tag, :args, :any_group)
+";
+        let v_tool_attrs = v_data
+            .iter()
+            .map(|data| serde_json::to_string_pretty(data.func.tool_attrs.as_slice()))
+            .collect::<serde_json::Result<Vec<_>>>()?;
+        let v_requirements: Vec<_> = v_data.iter().map(Data::requirements).collect();
 
         let timestamp = jiff::Timestamp::now();
-        for data in iter {
-            let tool_attrs = serde_json::to_string_pretty(data.func.tool_attrs.as_slice())?;
-            stmt.execute(named_params! {
-                ":hash1": data.hash.hash1.cast_signed(),
-                ":hash2": data.hash.hash2.cast_signed(),
-                ":tool_attrs": tool_attrs,
-                ":def_path": data.func.def_path,
-                ":function": data.func.function,
-                ":timestamp": timestamp,
-            })?;
-        }
-
-        stmt.finalize()?;
+        retry(|| {
+            // Take the write lock at the beginning rather than upgrading a read lock, which
+            // fails immediately without waiting on the busy handler if another writer exists.
+            let tx = self.conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            {
+                let crate_params =
+                    named_params! { ":name": krate.name, ":hash": krate.hash.cast_signed() };
+                tx.execute(DELETE_CRATE, crate_params)?;
+                let id = tx.prepare_cached(INSERT_CRATE)?.insert(named_params! {
+                    ":name": krate.name,
+                    ":version": krate.version,
+                    ":hash": krate.hash.cast_signed(),
+                    ":timestamp": timestamp,
+                })?;
+
+                let mut upsert = tx.prepare_cached(UPSERT)?;
+                let mut insert = tx.prepare_cached(INSERT_REQUIREMENT)?;
+                for ((data, tool_attrs), requirements) in
+                    v_data.iter().zip(&v_tool_attrs).zip(&v_requirements)
+                {
+                    let (hash1, hash2) = hash_params(data.hash);
+                    upsert.execute(named_params! {
+                        ":crate": id,
+                        ":hash1": hash1,
+                        ":hash2": hash2,
+                        ":tool_attrs": tool_attrs,
+                        ":def_path": data.func.def_path,
+                        ":name": data.func.name,
+                        ":function": data.func.function,
+                    })?;
+                    for req in requirements {
+                        insert.execute(named_params! {
+                            ":hash1": hash1,
+                            ":hash2": hash2,
+                            ":tag": req.tag,
+                            ":args": req.args,
+                            ":any_group": req.any_group,
+                        })?;
+                    }
+                }
+            }
+            tx.commit()
+        })
+        .context("Failed to save data")?;
 
         Ok(())
     }
 
-    pub fn get_all_data(&mut self) -> Result<Vec<Data>> {
+    /// Save callsites with their discharges, and diagnostics of the crate in a single
+    /// transaction. The crate must have been saved through [`Self::save_data`].
+    pub fn save_audit(&mut self, krate: &CrateKey, audit: &Audit) -> Result<()> {
+        const INSERT_CALLSITE: &str = "
+INSERT INTO callsite
+  (crate, caller_hash1, caller_hash2, caller, callee_hash1, callee_hash2, callee, file, line, col)
+VALUES
+  (:crate, :caller_hash1, :caller_hash2, :caller, :callee_hash1, :callee_hash2, :callee,
+   :file, :line, :col)
+";
+        const INSERT_DISCHARGE: &str = "
+INSERT INTO discharge (callsite, tag, reason) VALUES (:callsite, :tag, :reason)
+";
+        const INSERT_DIAGNOSTIC: &str = "
+INSERT INTO diagnostic (crate, kind, level, message, file, line, col)
+VALUES (:crate, :kind, :level, :message, :file, :line, :col)
+";
+        retry(|| {
+            let tx = self.conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            {
+                let id = crate_id(&tx, krate)?;
+
+                let mut insert_callsite = tx.prepare_cached(INSERT_CALLSITE)?;
+                let mut insert_discharge = tx.prepare_cached(INSERT_DISCHARGE)?;
+                for callsite in &audit.callsites {
+                    let (caller_hash1, caller_hash2) = hash_params(callsite.caller);
+                    let (callee_hash1, callee_hash2) = hash_params(callsite.callee);
+                    let callsite_id = insert_callsite.insert(named_params! {
+                        ":crate": id,
+                        ":caller_hash1": caller_hash1,
+                        ":caller_hash2": caller_hash2,
+                        ":caller": callsite.caller_path,
+                        ":callee_hash1": callee_hash1,
+                        ":callee_hash2": callee_hash2,
+                        ":callee": callsite.callee_path,
+                        ":file": callsite.location.file,
+                        ":line": callsite.location.line,
+                        ":col": callsite.location.col,
+                    })?;
+                    for discharge in &callsite.discharges {
+                        insert_discharge.execute(named_params! {
+                            ":callsite": callsite_id,
+                            ":tag": discharge.tag,
+                            ":reason": discharge.reason,
+                        })?;
+                    }
+                }
+
+                let mut insert_diagnostic = tx.prepare_cached(INSERT_DIAGNOSTIC)?;
+                for diagnostic in &audit.diagnostics {
+                    let location = diagnostic.location.as_ref();
+                    insert_diagnostic.execute(named_params! {
+                        ":crate": id,
+                        ":kind": diagnostic.kind,
+                        ":level": diagnostic.level,
+                        ":message": diagnostic.message,
+                        ":file": location.map(|loc| &loc.file),
+                        ":line": location.map(|loc| loc.line),
+                        ":col": location.map(|loc| loc.col),
+                    })?;
+                }
+            }
+            tx.commit()
+        })
+        .context("Failed to save callsites and diagnostics")?;
+
+        Ok(())
+    }
+
+    // Checks take tags from sidecars rather than the database, so only tests read them back.
+    #[cfg(test)]
+    pub fn get_data(&mut self, krate: &CrateKey) -> Result<Vec<Data>> {
         const QUERY: &str = "
-SELECT hash1, hash2, tool_attrs, def_path, function FROM hir
+SELECT hir.hash1, hir.hash2, hir.tool_attrs, hir.def_path, hir.name, hir.function FROM hir
+JOIN crate ON crate.id = hir.crate
+WHERE crate.name = :name AND crate.hash = :hash
 ";
         let mut stmt = self.conn.prepare(QUERY)?;
-        stmt.query_and_then([], |row| {
+        let params = named_params! { ":name": krate.name, ":hash": krate.hash.cast_signed() };
+        stmt.query_and_then(params, |row| {
             eyre::Ok(Data {
                 hash: PrimaryKey {
                     hash1: row.get::<_, i64>(0)?.cast_unsigned(),
@@ -65,7 +189,8 @@ SELECT hash1, hash2, tool_attrs, def_path, function FROM hir
                 func: Func {
                     tool_attrs: serde_json::from_str(row.get_ref(2)?.as_str()?)?,
                     def_path: row.get(3)?,
-                    function: row.get(4)?,
+                    name: row.get(4)?,
+                    function: row.get(5)?,
                 },
             })
         })?
@@ -73,21 +198,161 @@ SELECT hash1, hash2, tool_attrs, def_path, function FROM hir
     }
 }
 
+fn crate_id(tx: &Transaction, krate: &CrateKey) -> rusqlite::Result<i64> {
+    const QUERY: &str = "SELECT id FROM crate WHERE name = :name AND hash = :hash";
+    let params = named_params! { ":name": krate.name, ":hash": krate.hash.cast_signed() };
+    tx.query_row(QUERY, params, |row| row.get(0))
+}
+
+/// Hashes are stored as signed integers, since sqlite has no unsigned ones.
+fn hash_params(key: PrimaryKey) -> (i64, i64) {
+    (key.hash1.cast_signed(), key.hash2.cast_signed())
+}
+
 #[test]
 fn test_db() -> Result<()> {
     safety_tool::logger::init();
     let path = "a.sqlite3";
     let _span = error_span!("test_db", path).entered();
     let mut db = Database::new(path)?;
-    db.save_data([Data {
-        hash: PrimaryKey { hash1: 1, hash2: 2 },
+    let krate = test_crate("a", 0);
+    db.save_data(
+        &krate,
+        &[Data {
+            hash: PrimaryKey { hash1: 1, hash2: 2 },
+            func: Func {
+                tool_attrs: vec!["Safety".to_owned()],
+                def_path: "a::b".to_owned(),
+                name: "a::b".to_owned(),
+                function: "fn f() {}".to_owned(),
+            },
+        }],
+    )?;
+    dbg!(db.get_data(&krate)?);
+    drop(db);
+    remove_db_files(path);
+    Ok(())
+}
+
+#[cfg(test)]
+fn test_crate(name: &str, hash: u64) -> CrateKey {
+    CrateKey { name: name.to_owned(), version: "0.1.0".to_owned(), hash }
+}
+
+/// Remove the database with WAL files.
+#[cfg(test)]
+fn remove_db_files(path: &str) {
+    for suffix in ["", "-wal", "-shm"] {
+        _ = std::fs::remove_file(format!("{path}{suffix}"));
+    }
+}
+
+#[test]
+fn parallel_writes() -> Result<()> {
+    let path = "target/parallel.sqlite3";
+    let data = |idx: u64| Data {
+        hash: PrimaryKey { hash1: idx, hash2: idx },
         func: Func {
             tool_attrs: vec!["Safety".to_owned()],
-            def_path: "a::b".to_owned(),
-            function: "fn f() {}".to_owned(),
+            def_path: format!("a::f{idx}"),
+            name: format!("a::f{idx}"),
+            function: String::new(),
+        },
+    };
+    let handles: Vec<_> = (0..16)
+        .map(|idx| {
+            thread::spawn(move || -> Result<()> {
+                let mut db = Database::new(path)?;
+                let v_data: Vec<_> = (0..64).map(|n| data(idx * 64 + n)).collect();
+                db.save_data(&test_crate(&format!("c{idx}"), idx), &v_data)
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap()?;
+    }
+    let db = Database::new(path)?;
+    let count: usize = db.conn.query_row("SELECT count(*) FROM hir", [], |row| row.get(0))?;
+    assert_eq!(count, 16 * 64);
+    drop(db);
+    remove_db_files(path);
+    Ok(())
+}
+
+#[test]
+fn requirements() -> Result<()> {
+    let path = "target/requirements.sqlite3";
+    remove_db_files(path);
+    let data = |tool_attrs: &str| Data {
+        hash: PrimaryKey { hash1: 1, hash2: 2 },
+        func: Func {
+            tool_attrs: vec![tool_attrs.to_owned()],
+            def_path: "a::f".to_owned(),
+            name: "a::f".to_owned(),
+            function: String::new(),
+        },
+    };
+    let mut db = Database::new(path)?;
+    let krate = test_crate("a", 0);
+    db.save_data(&krate, &[data("#[rapx::requires(Align(p, T), any(Alias, Init))]")])?;
+    // Requirements are replaced rather than appended.
+    db.save_data(&krate, &[data("#[rapx::requires(Align(p, T), any(Alias, Init), any(A, B))]")])?;
+    drop(db);
+
+    let db = Database::new(path)?;
+    let version: usize = db.conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    assert_eq!(version, safety_tool::db::MIGRATIONS.len());
+    let mut stmt =
+        db.conn.prepare("SELECT tag, args, any_group FROM requirement ORDER BY rowid")?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<rusqlite::Result<Vec<(String, String, Option<u32>)>>>()?;
+    let expected = [
+        ("Align", "p, T", None),
+        ("Alias", "", Some(0)),
+        ("Init", "", Some(0)),
+        ("A", "", Some(1)),
+        ("B", "", Some(1)),
+    ];
+    assert_eq!(rows.len(), expected.len());
+    for (row, (tag, args, group)) in rows.iter().zip(expected) {
+        assert_eq!((row.0.as_str(), row.1.as_str(), row.2), (tag, args, group));
+    }
+    drop(stmt);
+    drop(db);
+    remove_db_files(path);
+    Ok(())
+}
+
+#[test]
+fn replace_crate() -> Result<()> {
+    let path = "target/replace-crate.sqlite3";
+    remove_db_files(path);
+    let data = |idx: u64| Data {
+        hash: PrimaryKey { hash1: idx, hash2: idx },
+        func: Func {
+            tool_attrs: vec!["#[rapx::requires(Align)]".to_owned()],
+            def_path: format!("f{idx}"),
+            name: format!("f{idx}"),
+            function: String::new(),
         },
-    }])?;
-    dbg!(db.get_all_data()?);
-    std::fs::remove_file(path)?;
+    };
+    let (a, b) = (test_crate("a", 0), test_crate("b", 0));
+    let mut db = Database::new(path)?;
+    db.save_data(&a, &[data(1), data(2)])?;
+    db.save_data(&b, &[data(3)])?;
+    // Recompiling a crate drops functions that lose tags, while other crates are untouched.
+    db.save_data(&a, &[data(1)])?;
+
+    let def_paths = |db: &mut Database, krate| -> Result<Vec<String>> {
+        Ok(db.get_data(krate)?.into_iter().map(|data| data.func.def_path).collect())
+    };
+    assert_eq!(def_paths(&mut db, &a)?, ["f1"]);
+    assert_eq!(def_paths(&mut db, &b)?, ["f3"]);
+    let count: usize =
+        db.conn.query_row("SELECT count(*) FROM requirement", [], |row| row.get(0))?;
+    assert_eq!(count, 2);
+    drop(db);
+    remove_db_files(path);
     Ok(())
 }
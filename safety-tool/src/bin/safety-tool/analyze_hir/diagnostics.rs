@@ -1,14 +1,15 @@
+use super::db::{DiagnosticRecord, Location};
 use annotate_snippets::{Level, Renderer, Snippet};
-use rustc_hir::HirId;
+use rustc_hir::{HirId, def_id::LocalDefId};
 use rustc_middle::ty::TyCtxt;
 use rustc_span::{
     Span,
     source_map::{SourceMap, get_source_map},
 };
-use std::{ops::Range, sync::Arc};
+use std::{collections::BTreeMap, ops::Range, sync::Arc};
 
 /// A report / diagnostic to display.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 enum DiagnosticKind {
     // A non-existent tag is specified.
     // InvaidTag,
@@ -16,21 +17,71 @@ enum DiagnosticKind {
     MissingDischarge,
     /// The tag has been discharged.
     DuplicatedDischarge,
+    /// A ref id is annotated on only one site, thus links nothing.
+    SingleRefEndpoint,
+    /// A call is not preceded or followed by calls as required by `follows` or `precedes`.
+    UnorderedCall,
+    /// Tags requiring a reason are discharged without one.
+    MissingReason,
+    /// A tag is newer than or changed since the spec version declared by the crate.
+    SpecVersion,
+    /// A def path with extern tags resolves to no function in dependencies.
+    UnresolvedExternPath,
 }
 
-struct Diagnostic {
+impl DiagnosticKind {
+    /// Errors abort the compilation, while warnings don't.
+    fn is_error(self) -> bool {
+        !matches!(
+            self,
+            DiagnosticKind::SingleRefEndpoint
+                | DiagnosticKind::SpecVersion
+                | DiagnosticKind::UnresolvedExternPath
+        )
+    }
+}
+
+/// A diagnostic rendered for display, along with where it's reported in plain text.
+struct Rendered {
     render: Box<str>,
+    title: Box<str>,
+    /// None for diagnostics on the spec.
+    span: Option<Span>,
+}
+
+struct Diagnostic {
+    render: Rendered,
     kind: DiagnosticKind,
 }
 
 impl Diagnostic {
-    fn missing_discharge(render: Box<str>) -> Self {
+    fn missing_discharge(render: Rendered) -> Self {
         Diagnostic { render, kind: DiagnosticKind::MissingDischarge }
     }
 
-    pub fn duplicated_discharge(render: Box<str>) -> Self {
+    pub fn duplicated_discharge(render: Rendered) -> Self {
         Diagnostic { render, kind: DiagnosticKind::DuplicatedDischarge }
     }
+
+    fn single_ref_endpoint(render: Rendered) -> Self {
+        Diagnostic { render, kind: DiagnosticKind::SingleRefEndpoint }
+    }
+
+    fn unordered_call(render: Rendered) -> Self {
+        Diagnostic { render, kind: DiagnosticKind::UnorderedCall }
+    }
+
+    fn missing_reason(render: Rendered) -> Self {
+        Diagnostic { render, kind: DiagnosticKind::MissingReason }
+    }
+
+    fn spec_version(render: Rendered) -> Self {
+        Diagnostic { render, kind: DiagnosticKind::SpecVersion }
+    }
+
+    fn unresolved_extern_path(render: Rendered) -> Self {
+        Diagnostic { render, kind: DiagnosticKind::UnresolvedExternPath }
+    }
 }
 
 /// How to emit diagnostics.
@@ -77,11 +128,21 @@ impl ExitAndEmit {
     }
 }
 
+fn render_without_source(title: &str, info: &[String]) -> Rendered {
+    let msg = Level::Warning.title(title).footers(info.iter().map(|info| Level::Info.title(info)));
+    Rendered {
+        render: Renderer::styled().render(msg).to_string().into(),
+        title: title.into(),
+        span: None,
+    }
+}
+
 fn total(diagnostics: &[Diagnostic]) {
     use annotate_snippets::renderer::{AnsiColor, Style};
     use itertools::Itertools;
 
-    let counts = diagnostics.iter().counts_by(|d| d.kind);
+    // Sorted by kinds for stable output.
+    let counts: BTreeMap<_, _> = diagnostics.iter().counts_by(|d| d.kind).into_iter().collect();
     let style = Style::new().bold().fg_color(Some(AnsiColor::Red.into()));
     eprintln!("{style}Total counts of diagnostics from safety-tool: {counts:?}{style:#}\n");
 }
@@ -108,10 +169,36 @@ impl<'tcx> EmitDiagnostics<'tcx> {
     }
 
     #[must_use]
-    fn generate(&mut self, hir_id: HirId, title: &str, info: &[String]) -> Box<str> {
+    fn generate(&mut self, hir_id: HirId, title: &str, info: &[String]) -> Rendered {
+        self.generate_with(Level::Error, hir_id, title, "For this unsafe call.", info)
+    }
+
+    #[must_use]
+    fn generate_with(
+        &mut self,
+        level: Level,
+        hir_id: HirId,
+        title: &str,
+        label: &str,
+        info: &[String],
+    ) -> Rendered {
         let span_node = hir_span(hir_id, self.tcx);
+        self.generate_with_span(level, hir_id.owner.def_id, span_node, title, label, info)
+    }
+
+    /// Generate a diagnostic on a span in the body of the owner.
+    #[must_use]
+    fn generate_with_span(
+        &mut self,
+        level: Level,
+        owner: LocalDefId,
+        span_node: Span,
+        title: &str,
+        label: &str,
+        info: &[String],
+    ) -> Rendered {
         // error!(span_node = %self.src_map.span_to_snippet(span_node).unwrap());
-        let span_body = self.tcx.source_span(hir_id.owner);
+        let span_body = self.tcx.source_span(owner);
 
         // Point out an unsafe call with underlines.
         let range = match range_of_call(span_body, span_node) {
@@ -120,7 +207,7 @@ impl<'tcx> EmitDiagnostics<'tcx> {
             // Currently, only the macro callsite is reported.
             Err(range) => range,
         };
-        let anno_call = Level::Error.span(range).label("For this unsafe call.");
+        let anno_call = level.span(range).label(label);
 
         let src_body = self.src_map.span_to_snippet(span_body).unwrap();
         let file_and_line = self.src_map.lookup_line(span_body.lo()).unwrap();
@@ -129,11 +216,15 @@ impl<'tcx> EmitDiagnostics<'tcx> {
         let snippet = Snippet::source(&src_body).line_start(line_start).origin(&origin).fold(true);
 
         // Point out the problematic snippet.
-        let msg = Level::Error
+        let msg = level
             .title(title)
             .snippet(snippet.annotation(anno_call))
             .footers(info.iter().map(|info| Level::Info.title(info)));
-        Renderer::styled().render(msg).to_string().into()
+        Rendered {
+            render: Renderer::styled().render(msg).to_string().into(),
+            title: title.into(),
+            span: Some(span_node),
+        }
     }
 
     /// Add a diagnostic based on an unsafe call. Title is the first line of error msg.
@@ -147,17 +238,82 @@ impl<'tcx> EmitDiagnostics<'tcx> {
         self.diagnostics.push(Diagnostic::duplicated_discharge(render));
     }
 
-    /// Emit diagnostics, respecting EXIT_AND_EMIT.
+    pub fn push_single_ref_endpoint(&mut self, hir_id: HirId, id: &str) {
+        let title = format!("Ref `{id}` has only one endpoint");
+        let label = "No other site refers to it.";
+        let info = [format!("Annotate `#[ref({id})]` on sites sharing the safety justification.")];
+        let render = self.generate_with(Level::Warning, hir_id, &title, label, &info);
+        self.diagnostics.push(Diagnostic::single_ref_endpoint(render));
+    }
+
+    pub fn push_unordered_call(
+        &mut self,
+        owner: LocalDefId,
+        span: Span,
+        title: &str,
+        info: &[String],
+    ) {
+        let label = "For this call.";
+        let render = self.generate_with_span(Level::Error, owner, span, title, label, info);
+        self.diagnostics.push(Diagnostic::unordered_call(render));
+    }
+
+    pub fn push_missing_reason(&mut self, hir_id: HirId, tags: &[&str]) {
+        use itertools::Itertools;
+        let plural = if tags.len() == 1 { "Tag is" } else { "Tags are" };
+        let names = tags.iter().format_with(", ", |tag, f| f(&format_args!("`{tag}`")));
+        let title = format!("{plural} discharged without a reason: {names}");
+        let example = tags[0];
+        let info = [format!(
+            "A reason is required by the policy. Justify the discharge like \
+             `#[checked({example}: \"reason\")]`."
+        )];
+        let render = self.generate_with(Level::Error, hir_id, &title, "For this discharge.", &info);
+        self.diagnostics.push(Diagnostic::missing_reason(render));
+    }
+
+    pub fn push_spec_version(&mut self, hir_id: HirId, title: &str, info: &[String]) {
+        let label = "For this tag.";
+        let render = self.generate_with(Level::Warning, hir_id, title, label, info);
+        self.diagnostics.push(Diagnostic::spec_version(render));
+    }
+
+    /// A warning on the spec itself, thus no source code to point out.
+    pub fn push_spec_warning(&mut self, title: &str, info: &[String]) {
+        let render = render_without_source(title, info);
+        self.diagnostics.push(Diagnostic::spec_version(render));
+    }
+
+    pub fn push_unresolved_extern_path(&mut self, def_path: &str, file: &str) {
+        let title = format!("`{def_path}` with extern tags resolves to no function");
+        let info = [format!("Listed in {file}. Update or remove it if the function is moved.")];
+        let render = render_without_source(&title, &info);
+        self.diagnostics.push(Diagnostic::unresolved_extern_path(render));
+    }
+
+    /// Diagnostics in plain text to be saved into the database.
+    pub fn records(&self) -> Vec<DiagnosticRecord> {
+        let tcx = self.tcx;
+        let record = |d: &Diagnostic| DiagnosticRecord {
+            kind: format!("{:?}", d.kind),
+            level: if d.kind.is_error() { "error" } else { "warning" },
+            message: d.render.title.to_string(),
+            location: d.render.span.map(|span| Location::new(span, tcx)),
+        };
+        self.diagnostics.iter().map(record).collect()
+    }
+
+    /// Emit diagnostics, respecting EXIT_AND_EMIT. Only errors lead to abort.
     pub fn emit(self) {
         let Self { diagnostics, exit_and_emit, .. } = self;
         if !diagnostics.is_empty() {
             if exit_and_emit.should_emit() {
                 for diagnostic in &diagnostics {
-                    eprintln!("{}\n", diagnostic.render)
+                    eprintln!("{}\n", diagnostic.render.render)
                 }
                 total(&diagnostics);
             }
-            if exit_and_emit.should_abort() {
+            if exit_and_emit.should_abort() && diagnostics.iter().any(|d| d.kind.is_error()) {
                 std::process::abort()
             }
         }
@@ -1,14 +1,18 @@
-use crate::is_tool_attr;
+use crate::{Reachability, is_ref_attr, is_tool_attr};
 use rustc_hir::{BodyId, FnSig, HirId, ImplItemKind, ItemKind, Node, def_id::LocalDefId};
 use rustc_middle::ty::TyCtxt;
 use rustc_span::Ident;
 
 mod db;
 mod diagnostics;
+mod ordering;
+mod reason;
+mod refs;
 mod stat;
+mod version;
 mod visit;
 
-pub fn analyze_hir(tcx: TyCtxt) {
+pub fn analyze_hir(tcx: TyCtxt, reachability: &Reachability) {
     let mut stat = stat::new(tcx);
     let mut v_hir_fn = Vec::with_capacity(64);
 
@@ -42,8 +46,10 @@ pub fn analyze_hir(tcx: TyCtxt) {
     }
 
     let mut tool_attrs =
-        db::get_all_tool_attrs(v_hir_fn.iter().filter_map(|f| f.to_data(tcx))).unwrap();
+        db::get_all_tool_attrs(tcx, v_hir_fn.iter().filter_map(|f| f.to_data(tcx))).unwrap();
     let mut diagnostics = diagnostics::EmitDiagnostics::new(tcx);
+    let refs = refs::Refs::new(tcx);
+    let mut callsites = Vec::new();
 
     for hir_fn in &v_hir_fn {
         let fn_hir_id = hir_fn.hir_id;
@@ -67,8 +73,10 @@ pub fn analyze_hir(tcx: TyCtxt) {
         if !unsafe_calls.is_empty() {
             debug!(?unsafe_calls);
             for call in &unsafe_calls {
-                call.check_tool_attrs(hir_fn.hir_id, &mut tool_attrs, &mut diagnostics);
-                if let Some(collect_callee_tags) = call.stat(hir_fn.hir_id, tcx, &mut tool_attrs) {
+                call.check_tool_attrs(hir_fn.hir_id, &mut tool_attrs, &refs, &mut diagnostics);
+                let collect_callee_tags = call.stat(hir_fn.hir_id, tcx, &mut tool_attrs, &refs);
+                if let Some(collect_callee_tags) = collect_callee_tags {
+                    callsites.push(collect_callee_tags.callsite());
                     let callee = collect_callee_tags.into_stat_func();
                     stat_caller.unsafe_calls.push(callee);
                 }
@@ -78,6 +86,18 @@ pub fn analyze_hir(tcx: TyCtxt) {
         stat.funcs.push(stat_caller);
     }
 
+    ordering::check(tcx, reachability, &tool_attrs, &mut diagnostics);
+    reason::check(tcx, &mut diagnostics);
+    version::check(tcx, &mut diagnostics);
+    refs.check(&mut diagnostics);
+    for (def_path, file) in tool_attrs.unresolved_extern_paths(tcx) {
+        diagnostics.push_unresolved_extern_path(def_path, file.as_str());
+    }
+    stat.refs = refs.stat(tcx);
+
+    // Save before emitting, which may abort.
+    let audit = db::Audit { callsites, diagnostics: diagnostics.records() };
+    db::save_audit(tcx, &audit).unwrap();
     diagnostics.emit();
     stat.update_metrics();
     stat.write_to_file();
@@ -0,0 +1,224 @@
+//! Ordering constraints between calls, i.e. `follows` and `precedes` of tags in spec.
+//!
+//! A call performs the tags its callee requires. The analysis is intra-procedural on MIR:
+//! * a call requiring a tag with `follows` must be preceded by a call performing one of
+//!   these tags on every path from the function entry
+//! * a call requiring a tag with `precedes` must be followed by a call performing one of
+//!   these tags on every path to the function return
+//!
+//! Bodies are those visited in [`Reachability`]. Unwinding paths end without a return, thus
+//! never constrain the ordering. If the caller requires the tag itself, the ordering is
+//! delegated to its callers, thus not checked inside.
+use super::{db::ToolAttrs, diagnostics::EmitDiagnostics};
+use crate::{Reachability, internal};
+use itertools::Itertools;
+use rustc_data_structures::fx::FxHashSet;
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_middle::ty::TyCtxt;
+use rustc_public::{
+    CrateDef,
+    mir::{Body, TerminatorKind},
+};
+use rustc_span::Span;
+use safety_parser::configuration::{Str, Tag, cache, get_tag_opt};
+use std::collections::VecDeque;
+
+/// Tags performed by calls.
+type Performed<'a> = FxHashSet<&'a str>;
+
+pub fn check(
+    tcx: TyCtxt,
+    reachability: &Reachability,
+    tool_attrs: &ToolAttrs,
+    diagnostics: &mut EmitDiagnostics,
+) {
+    // Skip MIR analysis when no ordering is constrained in spec.
+    if !cache().map.values().any(|key| key.tag.is_temporal()) {
+        return;
+    }
+
+    for (item, body) in &reachability.local_bodies {
+        let caller = internal(tcx, item.def_id());
+        let Some(local) = caller.as_local() else { continue };
+        let calls = tagged_calls(body, tcx, tool_attrs);
+        if calls.iter().all(Option::is_none) {
+            continue;
+        }
+
+        let caller_tags = tool_attrs.tag_names(caller, tcx);
+        let ordering = Ordering { local, calls: &calls, caller_tags: &caller_tags };
+        ordering.check(body, tcx, diagnostics);
+    }
+}
+
+/// A call whose callee requires tags.
+struct Call<'a> {
+    callee: DefId,
+    tags: Vec<&'a str>,
+    span: Span,
+}
+
+/// Tagged calls indexed by basic blocks, since calls are terminators.
+fn tagged_calls<'a>(body: &Body, tcx: TyCtxt, tool_attrs: &'a ToolAttrs) -> Vec<Option<Call<'a>>> {
+    let locals = body.locals();
+    body.blocks
+        .iter()
+        .map(|block| {
+            let TerminatorKind::Call { func, .. } = &block.terminator.kind else { return None };
+            let (fn_def, _) = func.ty(locals).ok()?.kind().fn_def()?;
+            let callee = internal(tcx, fn_def.def_id());
+            let tags = tool_attrs.tag_names(callee, tcx);
+            let span = internal(tcx, block.terminator.span);
+            (!tags.is_empty()).then_some(Call { callee, tags, span })
+        })
+        .collect()
+}
+
+/// Intersection of performed tags, where None means all tags, i.e. not computed yet
+/// or an unreachable path.
+fn meet<'a>(a: Option<&Performed<'a>>, b: Option<&Performed<'a>>) -> Option<Performed<'a>> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.intersection(b).copied().collect()),
+        (Some(x), None) | (None, Some(x)) => Some(x.clone()),
+        (None, None) => None,
+    }
+}
+
+struct Ordering<'a, 'b> {
+    local: LocalDefId,
+    calls: &'b [Option<Call<'a>>],
+    caller_tags: &'b [&'a str],
+}
+
+impl<'a> Ordering<'a, '_> {
+    /// Tags performed on every path from the entry to the terminator of each block.
+    fn preceding(&self, body: &Body) -> Vec<Option<Performed<'a>>> {
+        let mut before = vec![None; body.blocks.len()];
+        before[0] = Some(Performed::default());
+
+        let mut worklist = VecDeque::from([0]);
+        while let Some(bb) = worklist.pop_front() {
+            let mut after = before[bb].clone().unwrap_or_default();
+            if let Some(call) = &self.calls[bb] {
+                after.extend(call.tags.iter().copied());
+            }
+            for succ in body.blocks[bb].terminator.successors() {
+                let new = meet(before[succ].as_ref(), Some(&after));
+                if before[succ] != new {
+                    before[succ] = new;
+                    worklist.push_back(succ);
+                }
+            }
+        }
+        before
+    }
+
+    /// Tags performed on every path from the terminator of each block to the return.
+    fn following(&self, body: &Body) -> Vec<Option<Performed<'a>>> {
+        let len = body.blocks.len();
+        // Tags performed from the start of each block.
+        let mut from_start: Vec<Option<Performed>> = vec![None; len];
+        // Tags performed after the terminator of each block.
+        let mut after = vec![None; len];
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for bb in (0..len).rev() {
+                let kind = &body.blocks[bb].terminator.kind;
+                let new_after = if matches!(kind, TerminatorKind::Return) {
+                    Some(Performed::default())
+                } else {
+                    body.blocks[bb]
+                        .terminator
+                        .successors()
+                        .iter()
+                        .fold(None, |acc, &succ| meet(acc.as_ref(), from_start[succ].as_ref()))
+                };
+                let new_start = new_after.clone().map(|mut performed| {
+                    if let Some(call) = &self.calls[bb] {
+                        performed.extend(call.tags.iter().copied());
+                    }
+                    performed
+                });
+                if after[bb] != new_after || from_start[bb] != new_start {
+                    after[bb] = new_after;
+                    from_start[bb] = new_start;
+                    changed = true;
+                }
+            }
+        }
+        after
+    }
+
+    fn check(&self, body: &Body, tcx: TyCtxt, diagnostics: &mut EmitDiagnostics) {
+        let before = self.preceding(body);
+        let after = self.following(body);
+
+        for (bb, call) in self.calls.iter().enumerate() {
+            let Some(call) = call else { continue };
+            for &tag in &call.tags {
+                if self.caller_tags.contains(&tag) {
+                    // The caller's callers are responsible for the ordering.
+                    continue;
+                }
+                let Some(spec) = get_tag_opt(tag) else { continue };
+
+                for constraint in [Constraint::Follows, Constraint::Precedes] {
+                    let performed = match constraint {
+                        Constraint::Follows => &before[bb],
+                        Constraint::Precedes => &after[bb],
+                    };
+                    let expected = constraint.expected(&spec);
+                    if let Some(performed) = performed
+                        && !expected.is_empty()
+                        && !expected.iter().any(|t| performed.contains(&**t))
+                    {
+                        self.push(tcx, diagnostics, call, tag, constraint);
+                    }
+                }
+            }
+        }
+    }
+
+    fn push(
+        &self,
+        tcx: TyCtxt,
+        diagnostics: &mut EmitDiagnostics,
+        call: &Call,
+        tag: &str,
+        constraint: Constraint,
+    ) {
+        let spec = get_tag_opt(tag).unwrap();
+        let expected = constraint
+            .expected(&spec)
+            .iter()
+            .format_with(", or ", |t, f| f(&format_args!("`{t}`")))
+            .to_string();
+        let (passive, verb) = match constraint {
+            Constraint::Follows => ("preceded", "follow"),
+            Constraint::Precedes => ("followed", "precede"),
+        };
+        let callee = tcx.def_path_str(call.callee);
+        let title = format!(
+            "Call to `{callee}` is not {passive} by a call requiring {expected} on every path"
+        );
+        let info = [format!("`{tag}` must {verb} {expected}.")];
+        diagnostics.push_unordered_call(self.local, call.span, &title, &info);
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Constraint {
+    Follows,
+    Precedes,
+}
+
+impl Constraint {
+    fn expected(self, spec: &Tag) -> &[Str] {
+        match self {
+            Constraint::Follows => &spec.follows,
+            Constraint::Precedes => &spec.precedes,
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! Reason-required policy: discharges of some tags must be justified by a reason string,
+//! like `#[checked(Alias: "the pointer is unique here")]`.
+//!
+//! Tags with `require_reason = true` in spec are enforced at `checked` sites, as well as
+//! all tags if `require_reason = true` in the `[policy]` section of spec.
+use super::{
+    db::{attribute_to_string, crate_tool_attrs},
+    diagnostics::EmitDiagnostics,
+    is_ref_attr,
+};
+use itertools::Itertools;
+use rustc_middle::ty::TyCtxt;
+use safety_parser::{
+    configuration::require_reason,
+    safety::{Property, SafetyAttr},
+    syn,
+};
+
+pub fn check(tcx: TyCtxt, diagnostics: &mut EmitDiagnostics) {
+    for (hir_id, attr) in crate_tool_attrs(tcx) {
+        if is_ref_attr(attr) {
+            continue;
+        }
+        let attr = attribute_to_string(tcx, attr);
+        let Ok(attr) = syn::parse_str::<SafetyAttr>(&attr) else { continue };
+        let checked = attr.attr.path().segments.last().is_some_and(|s| s.ident == "checked");
+        if !checked {
+            continue;
+        }
+
+        let mut missing = Vec::new();
+        for (prop, reason) in attr.args.property_reason() {
+            push_unreasoned(prop, reason, &mut missing);
+        }
+        let missing: Vec<_> = missing.iter().map(String::as_str).unique().collect();
+        if !missing.is_empty() {
+            diagnostics.push_missing_reason(hir_id, &missing);
+        }
+    }
+}
+
+/// Push names of tags requiring a reason but without one. Alternatives in `any` tags are
+/// flattened, and take the reason of the whole `any` tag if they have none.
+fn push_unreasoned(prop: &Property, reason: Option<&str>, missing: &mut Vec<String>) {
+    match prop.args_in_any_tag() {
+        Some(alternatives) => {
+            for any in &alternatives {
+                for prop in &any.tags {
+                    push_unreasoned(prop, any.desc.as_deref().or(reason), missing);
+                }
+            }
+        }
+        None => {
+            let name = prop.tag.name();
+            if reason.is_none_or(|reason| reason.trim().is_empty()) && require_reason(name) {
+                missing.push(name.to_owned());
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Entity reference system: `#[ref(id)]` links sites sharing a single safety justification.
+use super::{
+    db::{Property, attribute_to_string, crate_tool_attrs, get_attrs},
+    diagnostics::EmitDiagnostics,
+    is_ref_attr, stat,
+};
+use indexmap::IndexMap;
+use rustc_data_structures::fx::FxIndexMap;
+use rustc_hir::HirId;
+use rustc_middle::ty::TyCtxt;
+use safety_parser::safety::parse_ref_attr;
+
+#[derive(Debug, Default)]
+pub struct Refs {
+    /// HIR nodes annotated with each ref id, in the order of definition.
+    map: FxIndexMap<Box<str>, Vec<HirId>>,
+}
+
+impl Refs {
+    /// Collect ref attributes on all HIR nodes in the local crate.
+    pub fn new(tcx: TyCtxt) -> Self {
+        let mut map = FxIndexMap::<Box<str>, Vec<HirId>>::default();
+        for (hir_id, attr) in
+            crate_tool_attrs(tcx).into_iter().filter(|(_, attr)| is_ref_attr(attr))
+        {
+            let attr = attribute_to_string(tcx, attr);
+            for id in parse_ref_attr(&attr).unwrap_or_default() {
+                let sites = map.entry(id).or_default();
+                if !sites.contains(&hir_id) {
+                    sites.push(hir_id);
+                }
+            }
+        }
+
+        Refs { map }
+    }
+
+    /// Ref ids annotated on the HIR node.
+    fn ids(hir_id: HirId, tcx: TyCtxt) -> impl Iterator<Item = Box<str>> {
+        get_attrs(tcx, hir_id)
+            .filter(|attr| is_ref_attr(attr))
+            .filter_map(move |attr| parse_ref_attr(&attribute_to_string(tcx, attr)))
+            .flat_map(|ids| ids.into_vec())
+    }
+
+    /// The site whose discharge is shared with the given HIR node through `#[ref(id)]`.
+    ///
+    /// The first expression site carrying tags among those with the same id is the source.
+    /// Item sites like a `Drop` impl are only referred to: they never discharge tags.
+    pub fn source_site(&self, hir_id: HirId, tcx: TyCtxt) -> Option<HirId> {
+        for id in Self::ids(hir_id, tcx) {
+            let Some(sites) = self.map.get(&id) else { continue };
+            let source = sites.iter().copied().find(|&site| {
+                site != hir_id && !is_item(site) && !Property::new_with_hir_id(site, tcx).is_empty()
+            });
+            if source.is_some() {
+                return source;
+            }
+        }
+        None
+    }
+
+    /// Warn about ref ids with only one endpoint, since they link nothing.
+    pub fn check(&self, diagnostics: &mut EmitDiagnostics) {
+        for (id, sites) in &self.map {
+            if let [site] = sites.as_slice() {
+                diagnostics.push_single_ref_endpoint(*site, id);
+            }
+        }
+    }
+
+    /// All sites sharing each ref id for the report.
+    pub fn stat(&self, tcx: TyCtxt) -> IndexMap<Box<str>, Vec<stat::RefSite>> {
+        let site = |&hir_id: &HirId| {
+            let discharge = !is_item(hir_id) && !Property::new_with_hir_id(hir_id, tcx).is_empty();
+            stat::new_ref_site(hir_id, discharge, tcx)
+        };
+        self.map.iter().map(|(id, sites)| (id.clone(), sites.iter().map(site).collect())).collect()
+    }
+}
+
+/// The HIR node is an item itself rather than a node in its body.
+fn is_item(hir_id: HirId) -> bool {
+    hir_id == HirId::make_owner(hir_id.owner.def_id)
+}
@@ -1,5 +1,5 @@
 use camino::Utf8PathBuf;
-use rustc_hir::{HirId, def_id::DefId};
+use rustc_hir::{HirId, def::DefKind, def_id::DefId};
 use rustc_middle::ty::TyCtxt;
 use rustc_session::config::CrateType as RawCrateType;
 use rustc_span::Span;
@@ -12,6 +12,7 @@ pub fn new(tcx: TyCtxt) -> Stat {
         specs: Specs::new(),
         funcs: Vec::new(),
         metrics: Metrics::default(),
+        refs: Default::default(),
     }
 }
 
@@ -53,33 +54,66 @@ fn hir_span(fn_hir_id: HirId, tcx: TyCtxt) -> Span {
 }
 
 pub fn new_func(fn_hir_id: HirId, fn_def_id: DefId, tcx: TyCtxt) -> Func {
-    let span = hir_span(fn_hir_id, tcx);
+    let (path, span) = path_and_lines(hir_span(fn_hir_id, tcx), tcx);
+    Func {
+        name: tcx.def_path_str(fn_def_id),
+        module: module_path(fn_def_id, tcx),
+        safe: !is_unsafe(fn_def_id, tcx),
+        tags: Vec::new(),
+        path,
+        span,
+        unsafe_calls: Vec::new(),
+    }
+}
+
+/// Path of the module where the function is defined, qualified by the crate name.
+fn module_path(fn_def_id: DefId, tcx: TyCtxt) -> String {
+    let mut module = tcx.parent(fn_def_id);
+    while tcx.def_kind(module) != DefKind::Mod {
+        module = tcx.parent(module);
+    }
+    let krate = tcx.crate_name(module.krate);
+    if module.is_crate_root() {
+        krate.to_string()
+    } else if module.is_local() {
+        format!("{krate}::{}", tcx.def_path_str(module))
+    } else {
+        // Paths of extern items have been qualified by crate names.
+        tcx.def_path_str(module)
+    }
+}
 
+/// File path and lines like `start:end` or `line` of the span.
+fn path_and_lines(span: Span, tcx: TyCtxt) -> (Utf8PathBuf, String) {
     let src_map = tcx.sess.source_map();
     let file_lines = src_map
         .span_to_lines(span)
         .unwrap_or_else(|err| panic!("Failed to know {span:?}:\n{err:?}"));
 
-    Func {
-        name: tcx.def_path_str(fn_def_id),
-        safe: !is_unsafe(fn_def_id, tcx),
-        tags: Vec::new(),
-        path: file_lines.file.name.prefer_local().to_string().into(),
-        span: {
-            use std::fmt::Write;
-            let mut buf = String::new();
-            // The line index stars from 0, while the first line in editors start from 1.
-            match file_lines.lines.as_slice() {
-                [first, .., last] => {
-                    _ = write!(&mut buf, "{}:{}", first.line_index + 1, last.line_index + 1)
-                }
-                [line] => _ = write!(&mut buf, "{}", line.line_index + 1),
-                [] => (),
+    let path = file_lines.file.name.prefer_local().to_string().into();
+    let lines = {
+        use std::fmt::Write;
+        let mut buf = String::new();
+        // The line index stars from 0, while the first line in editors start from 1.
+        match file_lines.lines.as_slice() {
+            [first, .., last] => {
+                _ = write!(&mut buf, "{}:{}", first.line_index + 1, last.line_index + 1)
             }
-            buf
-        },
-        unsafe_calls: Vec::new(),
+            [line] => _ = write!(&mut buf, "{}", line.line_index + 1),
+            [] => (),
+        }
+        buf
+    };
+    (path, lines)
+}
+
+pub fn new_ref_site(hir_id: HirId, discharge: bool, tcx: TyCtxt) -> RefSite {
+    crossfig::switch! {
+        crate::asterinas => { let span = tcx.hir().span(hir_id); }
+        _ => { let span = tcx.hir_span(hir_id); }
     }
+    let (path, span) = path_and_lines(span, tcx);
+    RefSite { func: tcx.def_path_str(hir_id.owner.to_def_id()), path, span, discharge }
 }
 
 pub fn new_caller(fn_hir_id: HirId, tcx: TyCtxt, attrs: &[String]) -> Func {
@@ -0,0 +1,122 @@
+//! Spec versioning: tags in code are checked against the spec version declared by the crate.
+//!
+//! The declared version is `SP_VERSION` or `spec-version` in `[package.metadata.safety-tool]`,
+//! without which nothing is checked. Versions like `0.2` are treated as `0.2.0`.
+//! * a tag introduced in a newer spec than the declared one is warned wherever it's used
+//! * a tag whose definition changed after the declared spec is warned at `checked` sites,
+//!   since the discharge was written against the old definition
+use super::{
+    db::{attribute_to_string, crate_tool_attrs},
+    diagnostics::EmitDiagnostics,
+    is_ref_attr,
+};
+use cargo_metadata::semver::Version;
+use indexmap::IndexSet;
+use rustc_hir::HirId;
+use rustc_middle::ty::TyCtxt;
+use safety_parser::{
+    configuration::{CACHE, env::declared_spec_version, get_tag_opt},
+    safety::SafetyAttr,
+    syn,
+};
+
+pub fn check(tcx: TyCtxt, diagnostics: &mut EmitDiagnostics) {
+    let Some(declared) = declared_spec_version() else { return };
+    let Some(version) = parse_version(&declared) else {
+        let title = format!("Declared spec version `{declared}` is not a valid version");
+        diagnostics.push_spec_warning(&title, &[]);
+        return;
+    };
+
+    check_spec(&declared, &version, diagnostics);
+
+    for (hir_id, attr) in crate_tool_attrs(tcx) {
+        if is_ref_attr(attr) {
+            continue;
+        }
+        let attr = attribute_to_string(tcx, attr);
+        let Ok(attr) = syn::parse_str::<SafetyAttr>(&attr) else { continue };
+        let checked = attr.attr.path().segments.last().is_some_and(|s| s.ident == "checked");
+        for name in tag_names(&attr) {
+            check_tag(hir_id, &name, checked, &declared, &version, diagnostics);
+        }
+    }
+}
+
+/// Warn about spec packages older than the declared version.
+fn check_spec(declared: &str, version: &Version, diagnostics: &mut EmitDiagnostics) {
+    let specs: IndexSet<_> = CACHE.map.values().map(|key| (&key.src, &key.version)).collect();
+    for (src, spec_version) in specs {
+        let Some(spec_version) = spec_version.as_deref() else { continue };
+        if parse_version(spec_version).is_some_and(|v| v < *version) {
+            let title = format!(
+                "Spec `{src}` is at version {spec_version}, \
+                 older than the declared spec version {declared}"
+            );
+            let info = ["Tags may be missing or defined differently in this spec.".to_owned()];
+            diagnostics.push_spec_warning(&title, &info);
+        }
+    }
+}
+
+/// Tag names in the attribute, including those in `any` tags.
+fn tag_names(attr: &SafetyAttr) -> IndexSet<String> {
+    let mut names = IndexSet::new();
+    for (prop, _) in attr.args.property_reason() {
+        if let Some(v_sp) = prop.args_in_any_tag() {
+            let iter = v_sp.iter().flat_map(|sp| sp.tags.iter());
+            names.extend(iter.map(|tag| tag.tag.name().to_owned()));
+        } else {
+            names.insert(prop.tag.name().to_owned());
+        }
+    }
+    names
+}
+
+fn check_tag(
+    hir_id: HirId,
+    name: &str,
+    checked: bool,
+    declared: &str,
+    version: &Version,
+    diagnostics: &mut EmitDiagnostics,
+) {
+    let Some(tag) = get_tag_opt(name) else { return };
+    if let Some(since) = newer(&tag.since, version) {
+        let title = format!(
+            "Tag `{name}` is introduced in spec version {since}, \
+             newer than the declared spec version {declared}"
+        );
+        let info = [format!("Declare spec version {since} or later to use `{name}`.")];
+        diagnostics.push_spec_version(hir_id, &title, &info);
+    } else if checked && let Some(changed_in) = newer(&tag.changed_in, version) {
+        let title = format!(
+            "Tag `{name}` is changed in spec version {changed_in}, \
+             after the declared spec version {declared}"
+        );
+        let mut info = Vec::with_capacity(2);
+        if let Some(desc) = &tag.desc {
+            info.push(format!("`{name}`: {desc}"));
+        }
+        info.push(format!(
+            "Review the discharge against the current definition, \
+             and then declare spec version {changed_in} or later."
+        ));
+        diagnostics.push_spec_version(hir_id, &title, &info);
+    }
+}
+
+/// The version if it's newer than the declared one.
+fn newer<'a>(v: &'a Option<Box<str>>, declared: &Version) -> Option<&'a str> {
+    v.as_deref().filter(|v| parse_version(v).is_some_and(|v| v > *declared))
+}
+
+/// Parse a version leniently: missing minor or patch numbers are zeros.
+fn parse_version(version: &str) -> Option<Version> {
+    let version = version.trim().trim_start_matches('v');
+    Version::parse(version).ok().or_else(|| match version.split('.').count() {
+        1 => Version::parse(&format!("{version}.0.0")).ok(),
+        2 => Version::parse(&format!("{version}.0")).ok(),
+        _ => None,
+    })
+}
@@ -1,6 +1,10 @@
 use crate::analyze_hir::{
-    db::{Property, TagState, ToolAttrs, tool_attr_on_hir},
+    db::{
+        Callsite, Discharge, Location, PrimaryKey, Property, TagState, ToolAttrs,
+        qualified_def_path, tool_attr_on_hir,
+    },
     diagnostics::EmitDiagnostics,
+    refs::Refs,
     stat,
 };
 use rustc_hir::{
@@ -25,6 +29,7 @@ impl Call {
         &self,
         caller: HirId,
         tool_attrs: &mut ToolAttrs,
+        refs: &Refs,
         diagnostics: &mut EmitDiagnostics,
     ) {
         let tcx = diagnostics.tcx();
@@ -36,7 +41,13 @@ impl Call {
         let mut check = |hir_id: HirId| {
             debug!(?hir_id, ?caller);
 
-            let properties = Property::new_with_hir_id(hir_id, tcx);
+            let mut properties = Property::new_with_hir_id(hir_id, tcx);
+            if properties.is_empty()
+                && let Some(source) = refs.source_site(hir_id, tcx)
+            {
+                // No tags here, but refer to the discharge on another site.
+                properties = Property::new_with_hir_id(source, tcx);
+            }
 
             let is_empty = properties.is_empty();
             if !is_empty {
@@ -72,8 +83,9 @@ impl Call {
         caller: HirId,
         tcx: TyCtxt<'tcx>,
         tool_attrs: &mut ToolAttrs,
+        refs: &Refs,
     ) -> Option<CollectCalleeTags<'tcx>> {
-        CollectCalleeTags::new(self, caller, tcx, tool_attrs)
+        CollectCalleeTags::new(self, caller, tcx, tool_attrs, refs)
     }
 }
 
@@ -178,8 +190,9 @@ pub fn get_calls<'tcx>(
 pub struct CollectCalleeTags<'tcx> {
     tcx: TyCtxt<'tcx>,
     tags: Vec<stat::Tag>,
+    /// Tags and reasons in the nearest `checked` attributes.
+    discharges: Vec<Discharge>,
     callee: Call,
-    #[allow(dead_code)]
     caller: HirId,
 }
 
@@ -189,6 +202,7 @@ impl<'tcx> CollectCalleeTags<'tcx> {
         caller: HirId,
         tcx: TyCtxt<'tcx>,
         _tool_attrs: &mut ToolAttrs,
+        refs: &Refs,
     ) -> Option<Self> {
         // let Some(_tag_state) = tool_attrs.get_tags(callee.def_id, tcx) else {
         //     // No tool attrs to be checked on the callee.
@@ -197,29 +211,16 @@ impl<'tcx> CollectCalleeTags<'tcx> {
 
         let mut found_nearest_tags = false;
         let mut tags = Vec::new();
+        let mut discharges = Vec::new();
         // FIXME: the validity of attributes are not checked. Tags that do not target
         // any calls should be warned.
         for parent in parent_hirs(tcx, callee.hir_id) {
-            for attr_str in tool_attr_on_hir(parent, tcx) {
-                match syn::parse_str::<SafetyAttr>(&attr_str) {
-                    Ok(attr) => {
-                        let seg = &attr.attr.path().segments;
-                        if seg.first().map(|i| i.ident != crate::REGISTER_TOOL).unwrap_or(true) {
-                            // Skip non rapx attributes.
-                            continue;
-                        }
-                        if let Some(path) = seg.last()
-                            && path.ident == "checked"
-                        {
-                            // FIXME: we should only push valid tags
-                            // for the callee through tag_state
-                            stat::push_tag(attr.args.args, &mut tags);
-                            found_nearest_tags = true;
-                        }
-                    }
-                    Err(err) => eprintln!("{attr_str} is not parsed as SafetyAttr: {err}"),
-                }
+            let mut found = push_checked_tags(parent, tcx, &mut tags, &mut discharges);
+            if !found && let Some(source) = refs.source_site(parent, tcx) {
+                // Tags discharged on the site referred through `#[ref(id)]`.
+                found = push_checked_tags(source, tcx, &mut tags, &mut discharges);
             }
+            found_nearest_tags |= found;
             // Treat nearest parent tags as the call's tags.
             // This can be problematic if we allow partial discharging,
             // in which case we should continue bubbling up.
@@ -229,10 +230,67 @@ impl<'tcx> CollectCalleeTags<'tcx> {
                 break;
             }
         }
-        Some(CollectCalleeTags { tcx, tags, callee, caller })
+        Some(CollectCalleeTags { tcx, tags, discharges, callee, caller })
+    }
+
+    pub fn callsite(&self) -> Callsite {
+        let tcx = self.tcx;
+        let caller = self.caller.owner.to_def_id();
+        crossfig::switch! {
+            crate::asterinas => { let span = tcx.hir().span(self.callee.hir_id); }
+            _ => { let span = tcx.hir_span(self.callee.hir_id); }
+        }
+        Callsite {
+            caller: PrimaryKey::new(caller, tcx),
+            caller_path: qualified_def_path(tcx, caller),
+            callee: PrimaryKey::new(self.callee.def_id, tcx),
+            callee_path: qualified_def_path(tcx, self.callee.def_id),
+            location: Location::new(span, tcx),
+            discharges: self.discharges.clone(),
+        }
     }
 
     pub fn into_stat_func(self) -> stat::Func {
         stat::new_callee(self.callee.hir_id, self.callee.def_id, self.tcx, self.tags)
     }
 }
+
+/// Push tags in `#[rapx::checked]` on the HIR node, and their reasons as discharges.
+/// Returns true if any is found.
+fn push_checked_tags(
+    hir_id: HirId,
+    tcx: TyCtxt,
+    tags: &mut Vec<stat::Tag>,
+    discharges: &mut Vec<Discharge>,
+) -> bool {
+    let mut found = false;
+    for attr_str in tool_attr_on_hir(hir_id, tcx) {
+        match syn::parse_str::<SafetyAttr>(&attr_str) {
+            Ok(attr) => {
+                let seg = &attr.attr.path().segments;
+                if seg.first().map(|i| i.ident != crate::REGISTER_TOOL).unwrap_or(true) {
+                    // Skip non rapx attributes.
+                    continue;
+                }
+                if let Some(path) = seg.last()
+                    && path.ident == "checked"
+                {
+                    for (prop, reason) in attr.args.property_reason() {
+                        let reason = reason.map(str::to_owned);
+                        let discharge = Discharge { tag: prop.tag.name().to_owned(), reason };
+                        // The attribute on a statement is seen again on its expression.
+                        if !discharges.contains(&discharge) {
+                            discharges.push(discharge);
+                        }
+                    }
+                    // FIXME: we should only push valid tags
+                    // for the callee through tag_state
+                    stat::push_tag(attr.args.args, tags);
+                    found = true;
+                }
+            }
+            Err(err) => eprintln!("{attr_str} is not parsed as SafetyAttr: {err}"),
+        }
+    }
+    found
+}
@@ -44,14 +44,15 @@ use eyre::Result;
 use rustc_data_structures::fx::FxHashSet;
 use rustc_middle::ty::TyCtxt;
 use rustc_public::{
-    CompilerError, CrateDef, ItemKind,
+    CompilerError, CrateDef, CrateItem, ItemKind,
     mir::{
-        MirVisitor,
+        Body, MirVisitor,
         mono::{Instance, InstanceKind},
         visit::Location,
     },
     ty::Ty,
 };
+use safety_parser::safety::REF;
 use std::ops::ControlFlow;
 
 #[macro_use]
@@ -75,8 +76,9 @@ fn main() {
         if emit_tag_json {
             output_json::run(tcx);
         } else {
-            analyze_hir::analyze_hir(tcx);
-            analyze(tcx);
+            let reachability = Reachability::from_local_fns();
+            analyze_hir::analyze_hir(tcx, &reachability);
+            analyze(tcx, &reachability);
         }
         compilation_status()
     });
@@ -86,31 +88,20 @@ fn main() {
     }
 }
 
+/// When STOP_COMPILATION is set to non-0, stop compiling.
+fn stop_compilation() -> bool {
+    std::env::var("STOP_COMPILATION").map(|s| s != "0").unwrap_or(false)
+}
+
 fn compilation_status() -> ControlFlow<()> {
-    // When STOP_COMPILATION is set to non-0, stop compiling.
-    if std::env::var("STOP_COMPILATION").map(|s| s != "0").unwrap_or(false) {
+    if stop_compilation() {
         ControlFlow::<(), ()>::Break(())
     } else {
         ControlFlow::<(), ()>::Continue(())
     }
 }
 
-fn analyze(tcx: TyCtxt) {
-    let mut reachability = Reachability::default();
-    let local_items = rustc_public::all_local_items();
-    let functions = local_items.iter().filter(|item| matches!(item.kind(), ItemKind::Fn));
-
-    for fun in functions {
-        let instance = match Instance::try_from(*fun) {
-            Ok(instance) => instance,
-            Err(_) => {
-                // eprintln!("Failed to get the instance for {fun:?}:\n{err:?}");
-                continue;
-            }
-        };
-        reachability.add_instance(instance);
-    }
-
+fn analyze(tcx: TyCtxt, reachability: &Reachability) {
     let local_crate = rustc_public::local_crate();
     println!(
         "********* {name:?} {typ:?} has reached {len} instances *********",
@@ -125,19 +116,48 @@ fn analyze(tcx: TyCtxt) {
 struct Reachability {
     /// Collect monomorphized instances.
     instances: FxHashSet<Instance>,
+    /// MIR bodies of local functions, shared with analyses on them, e.g. ordering of calls.
+    local_bodies: Vec<(CrateItem, Body)>,
 }
 
 impl Reachability {
-    fn add_instance(&mut self, instance: Instance) {
+    fn from_local_fns() -> Self {
+        let mut reachability = Reachability::default();
+        let local_items = rustc_public::all_local_items();
+        let functions = local_items.into_iter().filter(|item| matches!(item.kind(), ItemKind::Fn));
+
+        for fun in functions {
+            match Instance::try_from(fun) {
+                Ok(instance) => {
+                    // The body has been visited if the function is called by a visited one.
+                    let body = reachability.add_instance(instance).or_else(|| instance.body());
+                    reachability.local_bodies.extend(body.map(|body| (fun, body)));
+                }
+                // Generic functions are not instances, but their bodies are still analyzed.
+                Err(_) => {
+                    crossfig::switch! {
+                        asterinas => { let body = fun.has_body().then(|| fun.body()); }
+                        _ => { let body = fun.body(); }
+                    }
+                    reachability.local_bodies.extend(body.map(|body| (fun, body)));
+                }
+            }
+        }
+        reachability
+    }
+
+    /// Returns the body if it's visited for the first time.
+    fn add_instance(&mut self, instance: Instance) -> Option<Body> {
         if self.instances.insert(instance)
             && instance.has_body()
             && matches!(instance.kind, InstanceKind::Item)
         {
             // recurse if this is the first time of insertion
-            if let Some(body) = instance.body() {
-                self.visit_body(&body);
-            }
+            let body = instance.body()?;
+            self.visit_body(&body);
+            return Some(body);
         }
+        None
     }
 
     fn print_tag_std_attrs(&self, tcx: TyCtxt) {
@@ -171,6 +191,30 @@ fn is_tool_attr(attr: &rustc_hir::Attribute) -> bool {
     }
 }
 
+/// Tool attribute linking sites in the entity reference system, i.e. `#[rapx::r#ref(id)]`.
+fn is_ref_attr(attr: &rustc_hir::Attribute) -> bool {
+    crossfig::switch! {
+        asterinas => {
+            if let rustc_hir::AttrKind::Normal(tool_attr) = &attr.kind
+                && tool_attr.path.segments[0].as_str() == REGISTER_TOOL
+                && tool_attr.path.segments.get(1).is_some_and(|seg| seg.as_str() == REF)
+            {
+                return true;
+            }
+            false
+        }
+        _  => {
+            if let rustc_hir::Attribute::Unparsed(tool_attr) = attr
+                && tool_attr.path.segments[0].as_str() == REGISTER_TOOL
+                && tool_attr.path.segments.get(1).is_some_and(|seg| seg.as_str() == REF)
+            {
+                return true;
+            }
+            false
+        }
+    }
+}
+
 fn print_tag_std_attrs_through_internal_apis(tcx: TyCtxt<'_>, instance: &Instance) {
     let def_id = internal(tcx, instance.def.def_id());
 
@@ -195,7 +239,7 @@ impl MirVisitor for Reachability {
         if let Some((fn_def, args)) = ty.kind().fn_def() {
             // Add an instance.
             let instance = Instance::resolve(fn_def, args).unwrap();
-            self.add_instance(instance);
+            _ = self.add_instance(instance);
         }
     }
 }
@@ -0,0 +1,337 @@
+//! Schema of the sqlite database shared by safety-tool during compilation and
+//! cargo-safety-tool subcommands.
+//!
+//! Rows are scoped by crates: a crate is identified by its name and `StableCrateId`, and all
+//! rows of it are replaced when the crate is recompiled.
+use crate::Result;
+use eyre::Context;
+use rusqlite::{Connection, ErrorCode, OpenFlags, TransactionBehavior};
+use std::{thread, time::Duration};
+
+/// Schema changes applied in order. The count of applied migrations is the `user_version`
+/// of the database, so append new migrations rather than editing existing ones.
+pub const MIGRATIONS: &[&str] = &[
+    // Tool attrs of tagged functions as JSON strings.
+    "
+CREATE TABLE IF NOT EXISTS hir (
+  hash1 INTEGER NOT NULL,
+  hash2 INTEGER NOT NULL,
+  tool_attrs TEXT NOT NULL,
+  def_path TEXT NOT NULL,
+  function TEXT NOT NULL,
+  timestamp TEXT NOT NULL,
+  PRIMARY KEY (hash1, hash2)
+) STRICT;
+",
+    // Normalized tables of tags required by functions, unsafe callsites, discharges on them,
+    // and diagnostics. Alternatives in the same `any` tag share `any_group`.
+    "
+CREATE TABLE requirement (
+  hash1 INTEGER NOT NULL,
+  hash2 INTEGER NOT NULL,
+  tag TEXT NOT NULL,
+  args TEXT NOT NULL,
+  any_group INTEGER
+) STRICT;
+CREATE INDEX requirement_fn ON requirement (hash1, hash2);
+CREATE INDEX requirement_tag ON requirement (tag);
+
+CREATE TABLE callsite (
+  id INTEGER PRIMARY KEY,
+  caller_hash1 INTEGER NOT NULL,
+  caller_hash2 INTEGER NOT NULL,
+  caller TEXT NOT NULL,
+  callee_hash1 INTEGER NOT NULL,
+  callee_hash2 INTEGER NOT NULL,
+  callee TEXT NOT NULL,
+  file TEXT NOT NULL,
+  line INTEGER NOT NULL,
+  col INTEGER NOT NULL
+) STRICT;
+CREATE INDEX callsite_caller ON callsite (caller_hash1, caller_hash2);
+CREATE INDEX callsite_callee ON callsite (callee_hash1, callee_hash2);
+
+CREATE TABLE discharge (
+  callsite INTEGER NOT NULL REFERENCES callsite (id) ON DELETE CASCADE,
+  tag TEXT NOT NULL,
+  reason TEXT
+) STRICT;
+CREATE INDEX discharge_callsite ON discharge (callsite);
+CREATE INDEX discharge_tag ON discharge (tag);
+
+CREATE TABLE diagnostic (
+  hash1 INTEGER NOT NULL,
+  hash2 INTEGER NOT NULL,
+  kind TEXT NOT NULL,
+  level TEXT NOT NULL,
+  message TEXT NOT NULL,
+  file TEXT,
+  line INTEGER,
+  col INTEGER
+) STRICT;
+CREATE INDEX diagnostic_owner ON diagnostic (hash1, hash2);
+",
+    // Scope rows by crates. Rows before can't be attributed to any crate, thus are dropped
+    // to be regenerated on the next compilation.
+    "
+DROP TABLE diagnostic;
+DROP TABLE discharge;
+DROP TABLE callsite;
+DROP TABLE requirement;
+DROP TABLE hir;
+
+CREATE TABLE crate (
+  id INTEGER PRIMARY KEY,
+  name TEXT NOT NULL,
+  version TEXT NOT NULL,
+  hash INTEGER NOT NULL,
+  timestamp TEXT NOT NULL,
+  UNIQUE (name, hash)
+) STRICT;
+
+CREATE TABLE hir (
+  crate INTEGER NOT NULL REFERENCES crate (id) ON DELETE CASCADE,
+  hash1 INTEGER NOT NULL,
+  hash2 INTEGER NOT NULL,
+  tool_attrs TEXT NOT NULL,
+  def_path TEXT NOT NULL,
+  function TEXT NOT NULL,
+  PRIMARY KEY (hash1, hash2)
+) STRICT;
+CREATE INDEX hir_crate ON hir (crate);
+
+CREATE TABLE requirement (
+  hash1 INTEGER NOT NULL,
+  hash2 INTEGER NOT NULL,
+  tag TEXT NOT NULL,
+  args TEXT NOT NULL,
+  any_group INTEGER,
+  FOREIGN KEY (hash1, hash2) REFERENCES hir (hash1, hash2) ON DELETE CASCADE
+) STRICT;
+CREATE INDEX requirement_fn ON requirement (hash1, hash2);
+CREATE INDEX requirement_tag ON requirement (tag);
+
+CREATE TABLE callsite (
+  id INTEGER PRIMARY KEY,
+  crate INTEGER NOT NULL REFERENCES crate (id) ON DELETE CASCADE,
+  caller_hash1 INTEGER NOT NULL,
+  caller_hash2 INTEGER NOT NULL,
+  caller TEXT NOT NULL,
+  callee_hash1 INTEGER NOT NULL,
+  callee_hash2 INTEGER NOT NULL,
+  callee TEXT NOT NULL,
+  file TEXT NOT NULL,
+  line INTEGER NOT NULL,
+  col INTEGER NOT NULL
+) STRICT;
+CREATE INDEX callsite_crate ON callsite (crate);
+CREATE INDEX callsite_caller ON callsite (caller_hash1, caller_hash2);
+CREATE INDEX callsite_callee ON callsite (callee_hash1, callee_hash2);
+
+CREATE TABLE discharge (
+  callsite INTEGER NOT NULL REFERENCES callsite (id) ON DELETE CASCADE,
+  tag TEXT NOT NULL,
+  reason TEXT
+) STRICT;
+CREATE INDEX discharge_callsite ON discharge (callsite);
+CREATE INDEX discharge_tag ON discharge (tag);
+
+CREATE TABLE diagnostic (
+  crate INTEGER NOT NULL REFERENCES crate (id) ON DELETE CASCADE,
+  kind TEXT NOT NULL,
+  level TEXT NOT NULL,
+  message TEXT NOT NULL,
+  file TEXT,
+  line INTEGER,
+  col INTEGER
+) STRICT;
+CREATE INDEX diagnostic_crate ON diagnostic (crate);
+",
+    // Def paths qualified by crate names, e.g. `core::ptr::read`, as those in callsites.
+    "
+ALTER TABLE hir ADD COLUMN name TEXT NOT NULL DEFAULT '';
+CREATE INDEX hir_name ON hir (name);
+",
+];
+
+/// Wait for other processes holding the lock, e.g. rustc wrappers in `cargo build -j32`.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Retries when the database is still busy after the timeout.
+const RETRIES: u32 = 5;
+
+/// Path to the database specified by the environment variable `DATA_SQLITE3`.
+///
+/// Recommend setting it to an absolute path. The default path is relative to the folder
+/// where the crate is being compiled, leading to a sqlite3 file in each crate folder.
+pub fn path() -> String {
+    const DATA_SQLITE3: &str = "data.sqlite3";
+    std::env::var("DATA_SQLITE3").unwrap_or_else(|_| DATA_SQLITE3.to_owned())
+}
+
+/// Open the database, and migrate it to the latest schema.
+pub fn open(path: &str) -> Result<Connection> {
+    let mut conn = Connection::open(path).with_context(|| format!("Failed to open {path}"))?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    // WAL lets readers run concurrently with a writer, and the writer doesn't block readers.
+    retry(|| conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(())))
+        .with_context(|| format!("Failed to enable WAL for {path}"))?;
+    // Safe from corruption in WAL mode, while fewer syncs speed up writes.
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    conn.pragma_update(None, "foreign_keys", "ON")?;
+
+    let version = retry(|| migrate(&mut conn))
+        .with_context(|| format!("Failed to migrate the schema of {path}"))?;
+    ensure!(
+        version <= MIGRATIONS.len(),
+        "The schema version {version} of {path} is newer than the supported {}. \
+         Remove the database or upgrade safety-tool.",
+        MIGRATIONS.len()
+    );
+    Ok(conn)
+}
+
+/// Open the database for queries only: it's neither created nor migrated, and statements
+/// writing to it, or creating other databases through `ATTACH`, fail.
+pub fn open_read_only(path: &str) -> Result<Connection> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Failed to open {path}"))?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+
+    let version: usize = retry(|| conn.pragma_query_value(None, "user_version", |row| row.get(0)))?;
+    ensure!(
+        version == MIGRATIONS.len(),
+        "The schema version {version} of {path} differs from the supported {}. \
+         Compile crates through cargo safety-tool to migrate it, or upgrade safety-tool.",
+        MIGRATIONS.len()
+    );
+    Ok(conn)
+}
+
+/// Apply pending migrations in a transaction, and return the schema version before migration.
+fn migrate(conn: &mut Connection) -> rusqlite::Result<usize> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let version: usize = tx.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version < MIGRATIONS.len() {
+        for (idx, sql) in MIGRATIONS.iter().enumerate().skip(version) {
+            debug!(version = idx + 1, "Migrating the database");
+            tx.execute_batch(sql)?;
+        }
+        tx.pragma_update(None, "user_version", MIGRATIONS.len())?;
+    }
+    tx.commit()?;
+    Ok(version)
+}
+
+/// Retry the operation with exponential backoff if the database is busy or locked.
+pub fn retry<T>(mut f: impl FnMut() -> rusqlite::Result<T>) -> rusqlite::Result<T> {
+    let mut delay = Duration::from_millis(50);
+    for _ in 0..RETRIES {
+        match f() {
+            Err(err) if is_busy(&err) => {
+                warn!(%err, ?delay, "Database is busy, retrying");
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            res => return res,
+        }
+    }
+    f()
+}
+
+fn is_busy(err: &rusqlite::Error) -> bool {
+    matches!(err.sqlite_error_code(), Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked))
+}
+
+/// A crate in the database.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Crate {
+    pub name: String,
+    pub version: String,
+}
+
+/// Remove crates for which `keep` returns false, along with all their rows.
+/// Returns removed crates.
+pub fn gc(conn: &mut Connection, keep: impl Fn(&Crate) -> bool) -> Result<Vec<Crate>> {
+    let removed = retry(|| {
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let mut removed = Vec::new();
+        {
+            let mut stmt = tx.prepare("SELECT id, name, version FROM crate ORDER BY name")?;
+            let mut delete = tx.prepare("DELETE FROM crate WHERE id = ?1")?;
+            let rows = stmt.query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, Crate { name: row.get(1)?, version: row.get(2)? }))
+            })?;
+            for row in rows {
+                let (id, krate) = row?;
+                if !keep(&krate) {
+                    delete.execute([id])?;
+                    removed.push(krate);
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(removed)
+    })
+    .context("Failed to remove crates")?;
+    Ok(removed)
+}
+
+/// Remove crates not in `cargo metadata`, which is run with the args.
+///
+/// Crates compiled by safety-tool are named after cargo targets with `-` replaced by `_`,
+/// along with the version of the package.
+pub fn gc_with_cargo_metadata(args: &[String]) -> Result<()> {
+    let metadata = cargo_metadata::MetadataCommand::new().other_options(args.to_vec()).exec()?;
+    let crates: Vec<_> = metadata
+        .packages
+        .iter()
+        .flat_map(|pkg| {
+            pkg.targets.iter().map(|target| Crate {
+                name: target.name.replace('-', "_"),
+                version: pkg.version.to_string(),
+            })
+        })
+        .collect();
+
+    let path = path();
+    let removed = gc(&mut open(&path)?, |krate| crates.contains(krate))?;
+    println!("Removed {} crates from {path}", removed.len());
+    for Crate { name, version } in &removed {
+        println!("  {name} {version}");
+    }
+    Ok(())
+}
+
+#[test]
+fn gc_crates() -> Result<()> {
+    let path = "target/gc.sqlite3";
+    let remove_db_files = || {
+        for suffix in ["", "-wal", "-shm"] {
+            _ = std::fs::remove_file(format!("{path}{suffix}"));
+        }
+    };
+    remove_db_files();
+    let mut conn = open(path)?;
+    conn.execute_batch(
+        "
+INSERT INTO crate (id, name, version, hash, timestamp) VALUES
+  (1, 'a', '0.1.0', 1, ''),
+  (2, 'b', '0.1.0', 2, '');
+INSERT INTO hir (crate, hash1, hash2, tool_attrs, def_path, function) VALUES
+  (1, 1, 1, '[]', 'a::f', ''),
+  (2, 2, 2, '[]', 'b::f', '');
+INSERT INTO requirement (hash1, hash2, tag, args) VALUES (2, 2, 'Align', '');
+",
+    )?;
+
+    let removed = gc(&mut conn, |krate| krate.name == "a")?;
+    assert_eq!(removed, [Crate { name: "b".to_owned(), version: "0.1.0".to_owned() }]);
+    let count = |table: &str| -> rusqlite::Result<usize> {
+        conn.query_row(&format!("SELECT count(*) FROM {table}"), [], |row| row.get(0))
+    };
+    assert_eq!((count("crate")?, count("hir")?, count("requirement")?), (1, 1, 0));
+    drop(conn);
+    remove_db_files();
+    Ok(())
+}
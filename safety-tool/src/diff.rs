@@ -0,0 +1,266 @@
+//! Semver diff of safety tags between two versions of a crate.
+//!
+//! Tags are read from either `$UPG_DIR/_tags/$crate.json` emitted by `output_json`, or
+//! `$SP_OUT_DIR/$crate.json` emitted as [`Stat`]. Callsites in `Stat` files of the current
+//! workspace are checked against tags newly required by upstream functions, so that a
+//! dependency upgrade can be gated on new safety obligations.
+use crate::{
+    Result, Utf8Path, Utf8PathBuf,
+    stat::{Stat, Tag, TagType},
+};
+use indexmap::{IndexMap, IndexSet};
+use itertools::Itertools;
+use safety_parser::{configuration::ANY, json::Ouput, safety::Property};
+use serde::Serialize;
+use std::{env, fmt, fs};
+
+/// Tags declared on functions: function name to tag renderings with args and their keys.
+///
+/// A tag key is the tag name, or the whole rendering for an `any` tag, since multiple `any`
+/// tags can be declared on a function. Tags are told apart by renderings, since a tag can be
+/// declared multiple times with different args, e.g. `Align(p, T)` and `Align(q, U)`.
+#[derive(Debug, Default)]
+pub struct FnTags(IndexMap<String, IndexMap<String, String>>);
+
+impl FnTags {
+    /// Read tags from a JSON file, either emitted by `output_json` or as [`Stat`].
+    pub fn from_file(path: &Utf8Path) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+        Ok(if value.get("v_fn").is_some() {
+            FnTags::from_output(&serde_json::from_value(value)?)
+        } else {
+            FnTags::from_stat(&serde_json::from_value(value)?)
+        })
+    }
+
+    /// Function names in `output_json` have been prefixed with the crate name.
+    pub fn from_output(output: &Ouput) -> Self {
+        let map = output.v_fn.iter().map(|(name, v_props)| {
+            let tags = v_props.iter().flat_map(|props| &props.tags).map(|prop| key_prop(&prop.sp));
+            (name.clone(), tags.map(|(key, rendered)| (rendered, key)).collect())
+        });
+        FnTags(map.collect())
+    }
+
+    /// Function names in `Stat` are local to the crate, thus prefixed with the crate name here
+    /// to be matched with callees in downstream crates.
+    pub fn from_stat(stat: &Stat) -> Self {
+        let krate = &stat.krate.name;
+        let map = stat.funcs.iter().filter(|func| !func.tags.is_empty()).map(|func| {
+            let tags = func.tags.iter().map(key_tag).map(|(key, rendered)| (rendered, key));
+            (format!("{krate}::{}", func.name), tags.collect())
+        });
+        FnTags(map.collect())
+    }
+}
+
+fn render_prop(prop: &Property) -> String {
+    let name = prop.tag.name();
+    if prop.args.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{name}({})", prop.args_as_string().join(", "))
+    }
+}
+
+fn key_prop(prop: &Property) -> (String, String) {
+    let rendered = render_prop(prop);
+    let name = prop.tag.name();
+    let key = if name == ANY { rendered.clone() } else { name.to_owned() };
+    (key, rendered)
+}
+
+/// The tag key and its rendering with args.
+pub(crate) fn key_tag(tag: &Tag) -> (String, String) {
+    match &tag.tag {
+        TagType::Vanilla(prop) => key_prop(prop),
+        TagType::Any(v_props) => {
+            let props = v_props.iter().flat_map(|props| &props.tags).map(render_prop);
+            let rendered = format!("{ANY}({})", props.format(", "));
+            (rendered.clone(), rendered)
+        }
+    }
+}
+
+/// Tag changes on a function between two versions.
+#[derive(Debug, Default, Serialize)]
+pub struct FuncDiff {
+    /// New safety obligations for callers.
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// Tags with the same key but different args, as `(old, new)`.
+    pub changed: Vec<(String, String)>,
+}
+
+impl FuncDiff {
+    /// A tag declared once on both versions with different args is changed rather than
+    /// removed and added.
+    fn new(old: Option<&IndexMap<String, String>>, new: Option<&IndexMap<String, String>>) -> Self {
+        let empty = IndexMap::new();
+        let (old, new) = (old.unwrap_or(&empty), new.unwrap_or(&empty));
+        let once =
+            |tags: &IndexMap<String, String>, key| tags.values().filter(|k| *k == key).count() == 1;
+        let mut removed: Vec<_> = old.iter().filter(|(r, _)| !new.contains_key(*r)).collect();
+        let mut diff = FuncDiff::default();
+        for (rendered, key) in new.iter().filter(|(r, _)| !old.contains_key(*r)) {
+            let prev = removed.iter().position(|(_, k)| *k == key);
+            match prev.filter(|_| once(old, key) && once(new, key)) {
+                Some(idx) => diff.changed.push((removed.remove(idx).0.clone(), rendered.clone())),
+                None => diff.added.push(rendered.clone()),
+            }
+        }
+        diff.removed = removed.into_iter().map(|(r, _)| r.clone()).collect();
+        diff
+    }
+
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Keys of added tags.
+    fn added_keys<'a>(&self, new: &'a IndexMap<String, String>) -> Vec<&'a str> {
+        new.iter().filter(|(r, _)| self.added.contains(r)).map(|(_, key)| &**key).collect()
+    }
+}
+
+/// A callsite in the current workspace that doesn't discharge tags newly added to the callee.
+#[derive(Debug, Serialize)]
+pub struct Callsite {
+    pub caller: String,
+    pub callee: String,
+    pub path: Utf8PathBuf,
+    pub span: String,
+    pub undischarged: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    /// Functions whose tags are changed, in the order of the new version and then removed ones.
+    pub funcs: IndexMap<String, FuncDiff>,
+    pub callsites: Vec<Callsite>,
+}
+
+impl Report {
+    pub fn new(old: &FnTags, new: &FnTags, downstream: &[Stat]) -> Self {
+        let names: IndexSet<&String> = new.0.keys().chain(old.0.keys()).collect();
+        let funcs: IndexMap<_, _> = names
+            .into_iter()
+            .map(|name| (name.clone(), FuncDiff::new(old.0.get(name), new.0.get(name))))
+            .filter(|(_, diff)| !diff.is_empty())
+            .collect();
+
+        let mut callsites = Vec::new();
+        for caller in downstream.iter().flat_map(|stat| &stat.funcs) {
+            for call in &caller.unsafe_calls {
+                let (Some(diff), Some(tags)) = (funcs.get(&call.name), new.0.get(&call.name))
+                else {
+                    continue;
+                };
+                let discharged: Vec<_> = call.tags.iter().map(key_tag).collect();
+                let undischarged: Vec<_> = diff
+                    .added_keys(tags)
+                    .into_iter()
+                    .filter(|key| discharged.iter().all(|(k, _)| k != key))
+                    .map(String::from)
+                    .collect();
+                if !undischarged.is_empty() {
+                    callsites.push(Callsite {
+                        caller: caller.name.clone(),
+                        callee: call.name.clone(),
+                        path: call.path.clone(),
+                        span: call.span.clone(),
+                        undischarged,
+                    });
+                }
+            }
+        }
+
+        Report { funcs, callsites }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Safety tags changed on {} functions.", self.funcs.len())?;
+        for (name, diff) in &self.funcs {
+            writeln!(f, "{name}")?;
+            for tag in &diff.added {
+                writeln!(f, "  + {tag}")?;
+            }
+            for tag in &diff.removed {
+                writeln!(f, "  - {tag}")?;
+            }
+            for (old, new) in &diff.changed {
+                writeln!(f, "  ~ {old} => {new}")?;
+            }
+        }
+
+        writeln!(f, "\nUndischarged callsites: {}", self.callsites.len())?;
+        for site in &self.callsites {
+            let Callsite { caller, callee, path, span, undischarged } = site;
+            writeln!(f, "{path}:{span} in `{caller}` calls `{callee}`")?;
+            writeln!(f, "  missing {}", undischarged.iter().format(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Read `Stat` JSON files, or those directly in directories. Other JSON files are skipped.
+pub fn read_stats(paths: &[Utf8PathBuf]) -> Result<Vec<Stat>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            for entry in path.read_dir_utf8()? {
+                let file = entry?.into_path();
+                if file.extension() == Some("json") {
+                    files.push(file);
+                }
+            }
+        } else {
+            files.push(path.clone());
+        }
+    }
+    files.sort_unstable();
+
+    let mut stats = Vec::with_capacity(files.len());
+    for file in files {
+        let value: serde_json::Value = serde_json::from_str(&fs::read_to_string(&file)?)?;
+        if value.get("crate").is_some() {
+            stats.push(serde_json::from_value(value)?);
+        }
+    }
+    Ok(stats)
+}
+
+const USAGE: &str = "Usage: cargo safety-tool diff [--json] <OLD> <NEW> [STAT_PATH]...
+
+OLD and NEW are tags JSON in `$UPG_DIR/_tags/` or Stat JSON in `$SP_OUT_DIR`.
+STAT_PATH is a Stat JSON file or a directory of them for the current workspace,
+defaulting to `$SP_OUT_DIR` if set.";
+
+/// Entry of `cargo safety-tool diff`. An error is returned if any callsite becomes undischarged.
+pub fn run(args: &[String]) -> Result<()> {
+    let json = args.iter().any(|arg| arg == "--json");
+    let paths: Vec<Utf8PathBuf> =
+        args.iter().filter(|arg| *arg != "--json").map(Utf8PathBuf::from).collect();
+    let [old, new, stat_paths @ ..] = paths.as_slice() else {
+        bail!("Expect two files to be compared.\n{USAGE}");
+    };
+
+    let stat_paths = match (stat_paths, env::var("SP_OUT_DIR")) {
+        ([], Ok(dir)) => vec![Utf8PathBuf::from(dir)],
+        _ => stat_paths.to_vec(),
+    };
+    let downstream = read_stats(&stat_paths)?;
+    let report = Report::new(&FnTags::from_file(old)?, &FnTags::from_file(new)?, &downstream);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{report}");
+    }
+
+    let count = report.callsites.len();
+    ensure!(count == 0, "{count} callsites become undischarged.");
+    Ok(())
+}
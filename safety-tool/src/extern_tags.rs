@@ -0,0 +1,279 @@
+//! Tags required by extern functions with no tool attrs in source, e.g. unsafe APIs of the
+//! standard library listed in `data/std.json`, keyed by qualified def paths like
+//! `core::ptr::read`. Inherent methods are keyed by the module of the impl, e.g.
+//! `core::ptr::const_ptr::as_ref`, while trait methods by the trait path.
+//!
+//! Tags are written as arguments of `#[rapx::requires]`, in JSON or TOML:
+//!
+//! ```json
+//! { "core::alloc::layout::for_value_raw": ["any(Size, ValidNum, Typed)"] }
+//! ```
+//!
+//! ```toml
+//! "libc::unix::memcpy" = ["ValidPtr", "NonOverlap"]
+//! ```
+//!
+//! The legacy format lists tags per argument index, and chains alternatives through a trailing
+//! `|`, e.g. `{ "0": ["Null | ", "ValidPtr2Ref"] }`. It's normalized on load.
+use crate::{Result, Utf8Path, Utf8PathBuf};
+use eyre::Context;
+use indexmap::{IndexMap, IndexSet};
+use safety_parser::{
+    configuration::env::{config_exists, toml_file_paths, without_check},
+    safety::parse_attr_and_get_properties,
+};
+use serde::Deserialize;
+
+/// Extern tag files separated in the way of `PATH`. Tags of the same def path in later files
+/// take precedence.
+pub const SP_EXTERN_TAGS: &str = "SP_EXTERN_TAGS";
+/// Extern tag files in the folder of each spec TOML, e.g. alongside `safety-tags.toml` or the
+/// file in `SP_FILE`. They take precedence over files in `SP_EXTERN_TAGS`.
+pub const LOCAL_EXTERN_TAGS: [&str; 2] = ["extern-tags.toml", "extern-tags.json"];
+
+/// Legacy tag names renamed to those in `sp-core.toml`. A leading `!` is read as `Non` before
+/// renaming, e.g. `!Null` as `NonNull`, and `!Size` as `NonSize` then `Size`.
+const RENAMED: &[(&str, &str)] = &[
+    ("Aligned", "Align"),
+    ("CopyTrait", "Trait"),
+    ("DoubleAligned", "Align"),
+    ("InBounded", "InBound"),
+    ("NonSize", "Size"),
+    ("Ownning", "Owning"),
+    ("ValidPtr2Ref", "Ptr2Ref"),
+    ("ValidSlice", "ValidNum"),
+    ("ValidTraitObj", "Typed"),
+];
+
+/// Legacy tags with no counterpart in `sp-core.toml`, which are dropped: `Null` as an
+/// alternative allows null pointers, while `Function_sp` and `System_sp` are placeholders of
+/// properties specific to the function or the system. A chain of alternatives containing any
+/// of them is dropped as a whole, rather than narrowed to the rest.
+const DROPPED: &[&str] = &["Null", "Function_sp", "System_sp"];
+
+#[derive(Debug, Default)]
+pub struct ExternTags {
+    map: IndexMap<String, Listed>,
+}
+
+/// Tags of a def path, and the file listing them.
+#[derive(Debug)]
+pub struct Listed {
+    pub tags: Vec<String>,
+    pub file: Utf8PathBuf,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    Tags(Vec<String>),
+    /// Tags on each argument index.
+    Legacy(IndexMap<String, Vec<String>>),
+}
+
+impl ExternTags {
+    /// Files in `SP_EXTERN_TAGS`, and then extern tag files alongside the spec.
+    pub fn load() -> Result<Self> {
+        let mut tags = Self::default();
+        if let Some(paths) = std::env::var_os(SP_EXTERN_TAGS) {
+            for path in std::env::split_paths(&paths) {
+                let Some(path) = Utf8Path::from_path(&path) else {
+                    bail!("{SP_EXTERN_TAGS} contains a non UTF-8 path {path:?}")
+                };
+                tags.extend_from_file(path)?;
+            }
+        }
+        let spec_paths = if config_exists() { toml_file_paths() } else { Vec::new() };
+        let dirs: IndexSet<_> =
+            spec_paths.iter().filter_map(|path| Utf8Path::new(path).parent()).collect();
+        for dir in dirs {
+            for file in LOCAL_EXTERN_TAGS {
+                let path = dir.join(file);
+                if path.exists() {
+                    tags.extend_from_file(&path)?;
+                }
+            }
+        }
+        Ok(tags)
+    }
+
+    pub fn extend_from_file(&mut self, path: &Utf8Path) -> Result<()> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read extern tags from {path}"))?;
+        self.extend_from_str(&text, path)
+            .with_context(|| format!("Malformed extern tags in {path}"))
+    }
+
+    /// Parse the text as TOML if the file has the `.toml` extension, otherwise as JSON.
+    pub fn extend_from_str(&mut self, text: &str, file: &Utf8Path) -> Result<()> {
+        let entries: IndexMap<String, Entry> = if file.extension() == Some("toml") {
+            toml::from_str(text)?
+        } else {
+            serde_json::from_str(text)?
+        };
+        for (def_path, entry) in entries {
+            let tags = match entry {
+                Entry::Tags(tags) => tags,
+                Entry::Legacy(args) => match normalize_legacy(args.values().flatten()) {
+                    tags if tags.is_empty() => continue,
+                    tags => tags,
+                },
+            };
+            for tag in &tags {
+                ensure!(is_valid_tag(tag), "`{tag}` of {def_path} is not a valid tag");
+            }
+            self.map.insert(def_path, Listed { tags, file: file.to_owned() });
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, def_path: &str) -> Option<&[String]> {
+        self.map.get(def_path).map(|listed| &*listed.tags)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Listed)> {
+        self.map.iter().map(|(def_path, listed)| (&**def_path, listed))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Serialize in the normalized format.
+    pub fn to_json(&self) -> String {
+        // Serializing strings never fails.
+        let map: IndexMap<_, _> =
+            self.iter().map(|(def_path, listed)| (def_path, &listed.tags)).collect();
+        serde_json::to_string_pretty(&map).unwrap()
+    }
+}
+
+/// The attribute requiring tags, e.g. `#[rapx::requires(Align, any(Init, Typed))]`.
+pub fn requires_attr(tags: &[String]) -> String {
+    format!("#[rapx::requires({})]", tags.join(", "))
+}
+
+fn is_valid_tag(tag: &str) -> bool {
+    let attr = requires_attr(std::slice::from_ref(&tag.to_owned()));
+    without_check(|| !parse_attr_and_get_properties(&attr).is_empty())
+}
+
+/// Merge tags on all arguments without duplicates, turn chains of `|` into `any` tags, and
+/// rename legacy tags. Empty tags are skipped, and chains with dropped tags are dropped.
+fn normalize_legacy<'a>(legacy: impl IntoIterator<Item = &'a String>) -> Vec<String> {
+    let mut tags = IndexSet::new();
+    let mut alternatives = Vec::new();
+    let mut dropped = false;
+    for tag in legacy {
+        let (name, chained) = match tag.trim().strip_suffix('|') {
+            Some(name) => (name.trim(), true),
+            None => (tag.trim(), false),
+        };
+        match rename(name) {
+            Some(name) if DROPPED.contains(&&*name) => dropped = true,
+            Some(name) => alternatives.push(name),
+            None => (),
+        }
+        if !chained {
+            tags.extend(any(&mut alternatives, &mut dropped));
+        }
+    }
+    tags.extend(any(&mut alternatives, &mut dropped));
+    tags.into_iter().collect()
+}
+
+/// The tag of a chain of alternatives, which is then reset.
+fn any(alternatives: &mut Vec<String>, dropped: &mut bool) -> Option<String> {
+    let tag = if *dropped || alternatives.is_empty() {
+        None
+    } else if alternatives.len() == 1 {
+        Some(alternatives[0].clone())
+    } else {
+        Some(format!("any({})", alternatives.join(", ")))
+    };
+    alternatives.clear();
+    *dropped = false;
+    tag
+}
+
+/// Renamed tag, or none if it's empty.
+fn rename(name: &str) -> Option<String> {
+    let name = match name.strip_prefix('!') {
+        Some(negated) => format!("Non{negated}"),
+        None => name.to_owned(),
+    };
+    if name.is_empty() {
+        return None;
+    }
+    let renamed = RENAMED.iter().find(|(legacy, _)| *legacy == name);
+    Some(renamed.map_or(name, |(_, new)| (*new).to_owned()))
+}
+
+#[test]
+fn normalize_legacy_tags() -> Result<()> {
+    let json = Utf8Path::new("extern-tags.json");
+    let mut tags = ExternTags::default();
+    tags.extend_from_str(
+        r#"{
+  "core::alloc::layout::for_value_raw": {"0": ["Size |", "ValidSlice |", "ValidTraitObj", ""]},
+  "core::ptr::non_null::as_uninit_ref": {"0": ["!Null | ", "ValidPtr2Ref"]},
+  "core::ptr::const_ptr::as_ref": {"0": ["Null | ", "ValidPtr2Ref"]},
+  "core::ptr::const_ptr::as_uninit_slice": {"0": ["Null | ", "ValidPtr", "Alive"]},
+  "std::env::set_var": {"0": ["System_sp"]},
+  "core::ptr::copy": {"0": ["ValidPtr", "Aligned"], "1": ["ValidPtr", "Aligned", "!Size"]},
+  "core::ptr::read": ["ValidPtr", "Align", "any(Init, Typed)"]
+}"#,
+        json,
+    )?;
+    let expected = [
+        ("core::alloc::layout::for_value_raw", &["any(Size, ValidNum, Typed)"][..]),
+        ("core::ptr::non_null::as_uninit_ref", &["any(NonNull, Ptr2Ref)"]),
+        ("core::ptr::const_ptr::as_uninit_slice", &["Alive"]),
+        ("core::ptr::copy", &["ValidPtr", "Align", "Size"]),
+        ("core::ptr::read", &["ValidPtr", "Align", "any(Init, Typed)"]),
+    ];
+    for (def_path, expected) in expected {
+        assert_eq!(tags.get(def_path).unwrap(), expected);
+    }
+    // null pointers are allowed, so the chain isn't narrowed to `Ptr2Ref`
+    assert!(tags.get("core::ptr::const_ptr::as_ref").is_none());
+    assert!(tags.get("std::env::set_var").is_none());
+
+    assert!(tags.extend_from_str(r#"{"core::ptr::read": ["Align("]}"#, json).is_err());
+
+    let toml = Utf8Path::new("extern-tags.toml");
+    tags.extend_from_str(r#""core::ptr::read" = ["ValidPtr", "any(Init, Typed)"]"#, toml)?;
+    let (def_path, listed) = tags.iter().last().unwrap();
+    assert_eq!(def_path, "core::ptr::read");
+    assert_eq!(listed.tags, ["ValidPtr", "any(Init, Typed)"]);
+    assert_eq!(listed.file, toml);
+
+    let mut std = ExternTags::default();
+    std.extend_from_file(Utf8Path::new("../data/std.json"))?;
+    assert!(std.get("core::ptr::read").is_some());
+    Ok(())
+}
+
+/// Tags in `data/std.json` are all defined in `sp-core.toml`.
+#[test]
+fn std_tags_defined() -> Result<()> {
+    use safety_parser::{
+        configuration::{get_tag_opt, reload},
+        safety::parse_attr_and_get_tags,
+    };
+
+    reload(&["assets/sp-core.toml".to_owned()]).map_err(|err| eyre::eyre!(err))?;
+    let mut std = ExternTags::default();
+    std.extend_from_file(Utf8Path::new("../data/std.json"))?;
+    for (def_path, listed) in std.iter() {
+        let tags = without_check(|| parse_attr_and_get_tags(&requires_attr(&listed.tags)));
+        for tag in &tags {
+            ensure!(
+                get_tag_opt(tag.name()).is_some(),
+                "`{}` of {def_path} is undefined",
+                tag.name()
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,441 @@
+//! Static, self-contained HTML dashboard of `Stat` data, for reviewing annotation progress
+//! without reading JSON. It's written next to each Stat JSON by [`Stat::write_to_file`], and
+//! for the workspace by `cargo safety-tool report --html`.
+//!
+//! Tables are sortable by clicking headers, and call trees are folded with `<details>`, through
+//! inline CSS and JS only.
+use crate::{
+    Utf8Path, Utf8PathBuf,
+    diff::key_tag,
+    report::{CallNode, CrateCoverage, Report, Workspace, render_tags},
+    stat::{Func, MetricsCoverage, Stat, Tag, TagType},
+};
+use indexmap::{IndexMap, IndexSet};
+use itertools::Itertools;
+use std::{
+    fmt::{self, Write},
+    fs,
+};
+
+/// Source lines beyond this are cut off in a callsite snippet.
+const MAX_SNIPPET_LINES: usize = 12;
+
+/// Render the dashboard of crates, usually of a single crate or all crates in a workspace.
+pub fn render(stats: &[Stat]) -> String {
+    let mut html = String::new();
+    // Writing to a String never fails.
+    Dashboard { stats, report: Report::new(stats), sources: Sources::default() }
+        .write(&mut html)
+        .unwrap();
+    html
+}
+
+struct Dashboard<'a> {
+    stats: &'a [Stat],
+    report: Report,
+    sources: Sources,
+}
+
+impl Dashboard<'_> {
+    fn write(mut self, out: &mut String) -> fmt::Result {
+        let title = match self.stats {
+            [stat] => format!("Safety tags of {}", stat.krate.name),
+            _ => "Safety tags of the workspace".to_owned(),
+        };
+        writeln!(
+            out,
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>{title}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>",
+            title = Escape(&title)
+        )?;
+        self.summary(out)?;
+        self.crates(out)?;
+        self.functions(out)?;
+        self.callsites(out)?;
+        self.tag_usage(out)?;
+        self.call_trees(out)?;
+        writeln!(out, "<script>{SCRIPT}</script>\n</body>\n</html>")
+    }
+
+    fn summary(&self, out: &mut String) -> fmt::Result {
+        let Report { total, crates, total_tags, used_tags, unused, .. } = &self.report;
+        let unsafe_fns: usize = crates.iter().map(|c| c.unsafe_fns).sum();
+        let tagged: usize = crates.iter().map(|c| c.tagged_unsafe_fns).sum();
+        let cards = [
+            ("Functions", total.funcs.to_string()),
+            ("Unsafe functions tagged", format!("{tagged} / {unsafe_fns}")),
+            ("Unsafe calls", total.unsafe_calls.to_string()),
+            ("Tags discharged", total.discharged_tags.to_string()),
+            ("Tags used", format!("{used_tags} / {total_tags}")),
+        ];
+        writeln!(out, "<section class=\"cards\">")?;
+        for (name, value) in cards {
+            writeln!(out, "<div class=\"card\"><b>{value}</b><span>{name}</span></div>")?;
+        }
+        writeln!(out, "</section>")?;
+        if !unused.is_empty() {
+            let tags = unused.iter().format_with(" ", |tag, f| f(&Tagged("tag", tag)));
+            writeln!(out, "<p>Unused tags: {tags}</p>")?;
+        }
+        Ok(())
+    }
+
+    fn crates(&self, out: &mut String) -> fmt::Result {
+        section(out, "Crates")?;
+        let columns = [
+            "Crate",
+            "Type",
+            "Functions",
+            "Unsafe functions",
+            "Tagged",
+            "Coverage",
+            "Unsafe calls",
+            "Tags discharged",
+        ];
+        table_head(out, "crates", &columns)?;
+        for c in &self.report.crates {
+            let CrateCoverage { name, typ, funcs, unsafe_fns, tagged_unsafe_fns } = c;
+            let coverage = match c.coverage() {
+                Some(p) => format!("<td data-sort=\"{p}\">{p:.1}%</td>"),
+                None => "<td data-sort=\"-1\">-</td>".to_owned(),
+            };
+            writeln!(
+                out,
+                "<tr><td>{}</td><td>{typ:?}</td><td>{}</td><td>{unsafe_fns}</td>\
+                 <td>{tagged_unsafe_fns}</td>{coverage}<td>{}</td><td>{}</td></tr>",
+                Escape(name),
+                funcs.funcs,
+                funcs.unsafe_calls,
+                funcs.discharged_tags
+            )?;
+        }
+        table_end(out)
+    }
+
+    fn functions(&self, out: &mut String) -> fmt::Result {
+        section(out, "Functions")?;
+        let columns = [
+            "Crate",
+            "Function",
+            "Module",
+            "Safety",
+            "Tags",
+            "Declared tags",
+            "Unsafe calls",
+            "Tags discharged",
+            "Location",
+        ];
+        table_head(out, "funcs", &columns)?;
+        for stat in self.stats {
+            for func in &stat.funcs {
+                let tags = render_tags(&func.tags);
+                let discharged: usize = func.unsafe_calls.iter().map(|c| c.tags.len()).sum();
+                writeln!(
+                    out,
+                    "<tr><td>{}</td><td><code>{}</code></td><td>{}</td><td>{}</td>\
+                     <td>{}</td><td>{}</td><td>{}</td><td>{discharged}</td><td>{}:{}</td></tr>",
+                    Escape(&stat.krate.name),
+                    Escape(&func.name),
+                    Escape(&func.module),
+                    if func.safe { "safe" } else { "unsafe" },
+                    tags.len(),
+                    tags.iter().format_with(" ", |tag, f| f(&Tagged("tag", tag))),
+                    func.unsafe_calls.len(),
+                    Escape(func.path.as_str()),
+                    Escape(&func.span),
+                )?;
+            }
+        }
+        table_end(out)
+    }
+
+    fn callsites(&mut self, out: &mut String) -> fmt::Result {
+        section(out, "Callsites")?;
+        let columns = ["Caller", "Callee", "Required", "Discharged", "Status", "Location"];
+        table_head(out, "callsites", &columns)?;
+        let workspace = Workspace::new(self.stats);
+        for (idx, stat) in self.stats.iter().enumerate() {
+            for func in &stat.funcs {
+                for call in &func.unsafe_calls {
+                    let callee = workspace.resolve(idx, &call.name).map(|key| workspace.func(key));
+                    let required = callee.map(|f| render_tags(&f.tags)).unwrap_or_default();
+                    let discharged = render_tags(&call.tags);
+                    let (class, status) = status(callee, call);
+                    let snippet = self.sources.snippet(&stat.krate.path, &call.path, &call.span);
+                    writeln!(
+                        out,
+                        "<tr><td><code>{}::{}</code></td><td><code>{}</code></td><td>{}</td>\
+                         <td>{}</td><td class=\"{class}\">{}</td><td>{}</td></tr>",
+                        Escape(&stat.krate.name),
+                        Escape(&func.name),
+                        Escape(&call.name),
+                        required.iter().format_with(" ", |tag, f| f(&Tagged("tag", tag))),
+                        discharged.iter().format_with(" ", |tag, f| f(&Tagged("checked", tag))),
+                        Escape(&status),
+                        Location { path: &call.path, span: &call.span, snippet },
+                    )?;
+                }
+            }
+        }
+        table_end(out)
+    }
+
+    fn tag_usage(&self, out: &mut String) -> fmt::Result {
+        section(out, "Tag usage")?;
+        let mut used = IndexMap::<&str, MetricsCoverage>::new();
+        for stat in self.stats {
+            for (tag, coverage) in &stat.metrics.used {
+                used.entry(tag).or_default().merge(coverage);
+            }
+        }
+        used.sort_unstable_by(|a_name, a_cov, b_name, b_cov| {
+            (b_cov.occurence, a_name).cmp(&(a_cov.occurence, b_name))
+        });
+        let max = used.values().map(|c| c.occurence).max().unwrap_or(0).max(1);
+
+        let columns = ["Tag", "Occurrences", "Requires", "Checked", "Delegated", "In any"];
+        table_head(out, "tags", &columns)?;
+        for (tag, c) in &used {
+            let width = c.occurence as f64 * 100.0 / max as f64;
+            writeln!(
+                out,
+                "<tr><td>{}</td><td data-sort=\"{occurence}\"><div class=\"bar\" \
+                 style=\"width: {width:.1}%\"></div>{occurence}</td><td>{}</td><td>{}</td>\
+                 <td>{}</td><td>{}</td></tr>",
+                Tagged("tag", tag),
+                c.requires,
+                c.checked,
+                c.delegated,
+                c.in_any,
+                occurence = c.occurence,
+            )?;
+        }
+        table_end(out)
+    }
+
+    fn call_trees(&self, out: &mut String) -> fmt::Result {
+        section(out, "Call trees")?;
+        writeln!(out, "<ul class=\"tree\">")?;
+        for node in &self.report.call_trees {
+            call_tree(out, node)?;
+        }
+        writeln!(out, "</ul>")
+    }
+}
+
+/// A discharge status of the callsite as a CSS class and its description.
+fn status(callee: Option<&Func>, call: &Func) -> (&'static str, String) {
+    let discharged: IndexSet<_> = call.tags.iter().flat_map(tag_names).collect();
+    match callee {
+        Some(callee) => {
+            let missing = callee
+                .tags
+                .iter()
+                .filter(|tag| !tag_names(tag).into_iter().any(|name| discharged.contains(&name)));
+            let missing = missing.map(|tag| key_tag(tag).0).join(", ");
+            if missing.is_empty() {
+                ("ok", "Discharged".to_owned())
+            } else {
+                ("missing", format!("Missing: {missing}"))
+            }
+        }
+        // Tags required by functions out of the workspace are unknown.
+        None if discharged.is_empty() => ("unknown", "Unknown".to_owned()),
+        None => ("ok", "Checked".to_owned()),
+    }
+}
+
+/// The tag key, and names of tags in it if it's an `any` tag.
+fn tag_names(tag: &Tag) -> Vec<String> {
+    let mut names = vec![key_tag(tag).0];
+    if let TagType::Any(v_props) = &tag.tag {
+        let props = v_props.iter().flat_map(|props| &props.tags);
+        names.extend(props.map(|prop| prop.tag.name().to_owned()));
+    }
+    names
+}
+
+fn call_tree(out: &mut String, node: &CallNode) -> fmt::Result {
+    let class = if node.krate.is_some() { "local" } else { "extern" };
+    let mut label = format!("<code class=\"{class}\">{}</code>", Escape(&node.name));
+    for tag in &node.tags {
+        _ = write!(label, " {}", Tagged("tag", tag));
+    }
+    for tag in node.discharged.iter().flatten() {
+        _ = write!(label, " {}", Tagged("checked", tag));
+    }
+    if node.repeated {
+        label.push_str(" <span class=\"muted\">(expanded elsewhere)</span>");
+    }
+
+    if node.calls.is_empty() {
+        return writeln!(out, "<li>{label}</li>");
+    }
+    writeln!(out, "<li><details open><summary>{label}</summary><ul>")?;
+    for call in &node.calls {
+        call_tree(out, call)?;
+    }
+    writeln!(out, "</ul></details></li>")
+}
+
+fn section(out: &mut String, title: &str) -> fmt::Result {
+    writeln!(out, "<h2>{title}</h2>")
+}
+
+fn table_head(out: &mut String, id: &str, columns: &[&str]) -> fmt::Result {
+    writeln!(
+        out,
+        "<input class=\"filter\" data-table=\"{id}\" placeholder=\"Filter\">\n\
+         <table id=\"{id}\" class=\"sortable\"><thead><tr>{}</tr></thead><tbody>",
+        columns.iter().format_with("", |col, f| f(&format_args!("<th>{col}</th>")))
+    )
+}
+
+fn table_end(out: &mut String) -> fmt::Result {
+    writeln!(out, "</tbody></table>")
+}
+
+/// Source files read for snippets.
+#[derive(Default)]
+struct Sources {
+    files: IndexMap<Utf8PathBuf, Option<String>>,
+}
+
+impl Sources {
+    /// Lines of the span like `start:end` or `line`. A relative path is resolved against
+    /// ancestors of the crate root file, because it's relative to the package directory
+    /// where the crate is compiled.
+    fn snippet(&mut self, krate_root: &Utf8Path, path: &Utf8Path, span: &str) -> Option<String> {
+        let parse = |line: &str| line.parse::<usize>().ok();
+        let (start, end) = match span.split_once(':') {
+            Some((start, end)) => (parse(start)?, parse(end)?),
+            None => (parse(span)?, parse(span)?),
+        };
+        let len = (end + 1).checked_sub(start)?;
+        let path = if path.is_absolute() {
+            path.to_owned()
+        } else {
+            let mut dirs = krate_root.ancestors().skip(1);
+            dirs.find_map(|dir| Some(dir.join(path)).filter(|path| path.is_file()))?
+        };
+        let text = self.files.entry(path).or_insert_with_key(|path| fs::read_to_string(path).ok());
+        let text = text.as_deref()?;
+
+        let mut snippet = String::new();
+        let lines = text.lines().enumerate().skip(start.checked_sub(1)?);
+        for (idx, line) in lines.take(len.min(MAX_SNIPPET_LINES)) {
+            _ = writeln!(snippet, "{:>5} | {line}", idx + 1);
+        }
+        if len > MAX_SNIPPET_LINES {
+            snippet.push_str("      | ...\n");
+        }
+        (!snippet.is_empty()).then_some(snippet)
+    }
+}
+
+/// A callsite location, foldable to show the source snippet.
+struct Location<'a> {
+    path: &'a Utf8Path,
+    span: &'a str,
+    snippet: Option<String>,
+}
+
+impl fmt::Display for Location<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let location = format!("{}:{}", self.path, self.span);
+        match &self.snippet {
+            Some(snippet) => write!(
+                f,
+                "<details><summary>{}</summary><pre>{}</pre></details>",
+                Escape(&location),
+                Escape(snippet)
+            ),
+            None => write!(f, "{}", Escape(&location)),
+        }
+    }
+}
+
+/// A tag in a span of the CSS class.
+struct Tagged<'a>(&'static str, &'a str);
+
+impl fmt::Display for Tagged<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<span class=\"{}\">{}</span>", self.0, Escape(self.1))
+    }
+}
+
+/// Text escaped for HTML.
+struct Escape<'a>(&'a str);
+
+impl fmt::Display for Escape<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '&' => f.write_str("&amp;")?,
+                '<' => f.write_str("&lt;")?,
+                '>' => f.write_str("&gt;")?,
+                '"' => f.write_str("&quot;")?,
+                '\'' => f.write_str("&#39;")?,
+                c => f.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+const STYLE: &str = r#"
+body { font-family: system-ui, sans-serif; margin: 2em; color: #222; }
+h2 { margin-top: 2em; border-bottom: 1px solid #ddd; }
+code, pre { font-family: ui-monospace, monospace; font-size: 0.9em; }
+.cards { display: flex; gap: 1em; flex-wrap: wrap; }
+.card { border: 1px solid #ddd; border-radius: 6px; padding: 0.8em 1.2em; }
+.card b { display: block; font-size: 1.6em; }
+.card span, .muted { color: #777; }
+table { border-collapse: collapse; width: 100%; }
+th, td { border: 1px solid #ddd; padding: 0.3em 0.6em; text-align: left; vertical-align: top; }
+th { background: #f4f4f4; cursor: pointer; user-select: none; white-space: nowrap; }
+th[data-order="asc"]::after { content: " \25B2"; }
+th[data-order="desc"]::after { content: " \25BC"; }
+.filter { margin: 0.5em 0; padding: 0.3em; width: 20em; }
+.tag, .checked { display: inline-block; border-radius: 4px; padding: 0 0.4em; margin: 1px;
+  font-family: ui-monospace, monospace; font-size: 0.85em; }
+.tag { background: #fdebd0; }
+.checked { background: #d5f5e3; }
+td.ok { color: #1e8449; }
+td.missing { color: #c0392b; font-weight: bold; }
+td.unknown { color: #777; }
+.bar { display: inline-block; height: 0.8em; background: #5dade2; margin-right: 0.4em; }
+td[data-sort] .bar { max-width: 20em; }
+pre { background: #f8f8f8; padding: 0.5em; overflow-x: auto; }
+.tree, .tree ul { list-style: none; padding-left: 1.2em; }
+.tree li { margin: 0.2em 0; }
+.tree .extern { color: #555; }
+"#;
+
+const SCRIPT: &str = r#"
+for (const th of document.querySelectorAll("table.sortable th")) {
+  th.addEventListener("click", () => {
+    const body = th.closest("table").tBodies[0];
+    const idx = th.cellIndex;
+    const asc = th.dataset.order !== "asc";
+    for (const other of th.parentNode.children) delete other.dataset.order;
+    th.dataset.order = asc ? "asc" : "desc";
+    const key = (row) => row.cells[idx].dataset.sort ?? row.cells[idx].textContent;
+    const rows = [...body.rows].sort((a, b) => {
+      const x = key(a), y = key(b);
+      const numeric = x !== "" && y !== "" && !isNaN(x) && !isNaN(y);
+      const cmp = numeric ? x - y : x.localeCompare(y);
+      return asc ? cmp : -cmp;
+    });
+    body.append(...rows);
+  });
+}
+for (const input of document.querySelectorAll("input.filter")) {
+  input.addEventListener("input", () => {
+    const text = input.value.toLowerCase();
+    for (const row of document.getElementById(input.dataset.table).tBodies[0].rows) {
+      row.hidden = !row.textContent.toLowerCase().includes(text);
+    }
+  });
+}
+"#;
@@ -1,6 +1,12 @@
 #![cfg_attr(feature = "asterinas", feature(let_chains))]
 
+pub mod db;
+pub mod diff;
+pub mod extern_tags;
+pub mod html;
 pub mod logger;
+pub mod query;
+pub mod report;
 pub mod stat;
 pub mod utils;
 
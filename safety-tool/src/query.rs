@@ -0,0 +1,297 @@
+//! Queries over the database collected by safety-tool, for `cargo safety-tool query`.
+use crate::{Result, Utf8Path, db};
+use rusqlite::{Connection, ToSql, types::Value};
+use std::fmt::Write;
+
+const USAGE: &str = "\
+Usage: cargo safety-tool query [--format table|json|csv] <QUERY>
+
+Queries:
+  requires <TAG>      Functions requiring the tag
+  undischarged        Callsites not discharging tags required by callees
+  tags <DEF_PATH>     Tags required by the function, e.g. `core::ptr::read`
+  sql <SQL>           Free-form read-only SQL
+
+The database is specified by DATA_SQLITE3, or `data.sqlite3` in the current folder.";
+
+/// Functions requiring the tag, including those requiring it in `any` tags.
+const REQUIRES: &str = "
+SELECT crate.name AS crate, hir.name AS function, requirement.args, requirement.any_group
+FROM requirement
+JOIN hir USING (hash1, hash2)
+JOIN crate ON crate.id = hir.crate
+WHERE requirement.tag = ?1
+ORDER BY crate.name, hir.name
+";
+
+/// Tags required by callees, but not discharged on callsites. An `any` tag is undischarged if
+/// no alternative in it is discharged, in which case all alternatives are listed.
+const UNDISCHARGED: &str = "
+SELECT crate.name AS crate, callsite.caller, callsite.callee, requirement.tag,
+       requirement.any_group, callsite.file, callsite.line, callsite.col
+FROM callsite
+JOIN crate ON crate.id = callsite.crate
+JOIN requirement
+  ON requirement.hash1 = callsite.callee_hash1 AND requirement.hash2 = callsite.callee_hash2
+WHERE NOT EXISTS (
+  SELECT 1 FROM discharge
+  WHERE discharge.callsite = callsite.id AND discharge.tag = requirement.tag
+) AND (requirement.any_group IS NULL OR NOT EXISTS (
+  SELECT 1 FROM requirement AS alt
+  JOIN discharge ON discharge.callsite = callsite.id AND discharge.tag = alt.tag
+  WHERE alt.hash1 = requirement.hash1 AND alt.hash2 = requirement.hash2
+    AND alt.any_group = requirement.any_group
+))
+ORDER BY callsite.file, callsite.line, callsite.col, requirement.rowid
+";
+
+/// Tags required by the function, matched by the qualified def path or the debug one.
+const TAGS: &str = "
+SELECT crate.name AS crate, hir.name AS function, requirement.tag, requirement.args,
+       requirement.any_group
+FROM requirement
+JOIN hir USING (hash1, hash2)
+JOIN crate ON crate.id = hir.crate
+WHERE hir.name = ?1 OR hir.def_path = ?1
+ORDER BY crate.name, requirement.rowid
+";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Table,
+    Json,
+    Csv,
+}
+
+impl Format {
+    fn new(s: &str) -> Result<Self> {
+        Ok(match s {
+            "table" => Format::Table,
+            "json" => Format::Json,
+            "csv" => Format::Csv,
+            _ => bail!("Unknown format {s:?}: choose one among table, json, and csv.\n{USAGE}"),
+        })
+    }
+}
+
+/// Columns and values of rows from a query.
+#[derive(Debug)]
+pub struct Rows {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+pub fn run(args: &[String]) -> Result<()> {
+    let mut format = Format::default();
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if let Some(fmt) = arg.strip_prefix("--format=") {
+            format = Format::new(fmt)?;
+        } else if arg == "--format" {
+            let Some(fmt) = args.next() else { bail!("Expect a format.\n{USAGE}") };
+            format = Format::new(fmt)?;
+        } else if arg == "-h" || arg == "--help" {
+            println!("{USAGE}");
+            return Ok(());
+        } else {
+            rest.push(arg.as_str());
+        }
+    }
+
+    let path = db::path();
+    ensure!(
+        Utf8Path::new(&path).exists(),
+        "{path} doesn't exist. Compile crates through cargo safety-tool first, \
+         or set DATA_SQLITE3 to the database."
+    );
+    let conn = db::open_read_only(&path)?;
+    let rows = match rest.as_slice() {
+        ["requires", tag] => query(&conn, REQUIRES, &[tag])?,
+        ["undischarged"] => query(&conn, UNDISCHARGED, &[])?,
+        ["tags", def_path] => query(&conn, TAGS, &[def_path])?,
+        ["sql", sql] => query_read_only(&conn, sql)?,
+        _ => bail!("Unknown query: {rest:?}\n{USAGE}"),
+    };
+    print!("{}", rows.render(format));
+    Ok(())
+}
+
+pub fn query(conn: &Connection, sql: &str, params: &[&dyn ToSql]) -> Result<Rows> {
+    let mut stmt = conn.prepare(sql)?;
+    let columns = stmt.column_names().into_iter().map(str::to_owned).collect::<Vec<_>>();
+    let len = columns.len();
+    let rows = stmt
+        .query_map(params, |row| (0..len).map(|idx| row.get(idx)).collect())?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(Rows { columns, rows })
+}
+
+/// Free-form SQL must not modify the database, which is also guaranteed by connections from
+/// [`db::open_read_only`], since statements like `ATTACH` are deemed read-only by SQLite.
+pub fn query_read_only(conn: &Connection, sql: &str) -> Result<Rows> {
+    ensure!(conn.prepare(sql)?.readonly(), "Only read-only SQL is allowed: {sql}");
+    query(conn, sql, &[])
+}
+
+impl Rows {
+    pub fn render(&self, format: Format) -> String {
+        let mut buf = String::new();
+        match format {
+            Format::Table => self.render_table(&mut buf),
+            Format::Json => {
+                let objects: Vec<serde_json::Map<_, _>> = self
+                    .rows
+                    .iter()
+                    .map(|row| self.columns.iter().cloned().zip(row.iter().map(to_json)).collect())
+                    .collect();
+                // Serializing JSON values never fails.
+                buf = serde_json::to_string_pretty(&objects).unwrap();
+                buf.push('\n');
+            }
+            Format::Csv => {
+                let mut line = |cells: &[String]| {
+                    let cells: Vec<_> = cells.iter().map(|cell| csv_escape(cell)).collect();
+                    _ = writeln!(buf, "{}", cells.join(","));
+                };
+                line(&self.columns);
+                for row in &self.rows {
+                    line(&row.iter().map(to_text).collect::<Vec<_>>());
+                }
+            }
+        }
+        buf
+    }
+
+    /// Columns are left-aligned and separated by two spaces.
+    fn render_table(&self, buf: &mut String) {
+        let cells: Vec<Vec<String>> =
+            self.rows.iter().map(|row| row.iter().map(to_text).collect()).collect();
+        let mut widths: Vec<_> = self.columns.iter().map(|col| col.chars().count()).collect();
+        for row in &cells {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let mut line = |cells: &[String]| {
+            let mut line = String::new();
+            for (cell, width) in cells.iter().zip(&widths) {
+                _ = write!(line, "{cell:width$}  ");
+            }
+            _ = writeln!(buf, "{}", line.trim_end());
+        };
+        line(&self.columns);
+        line(&widths.iter().map(|&width| "-".repeat(width)).collect::<Vec<_>>());
+        for row in &cells {
+            line(row);
+        }
+        _ = writeln!(buf, "({} rows)", cells.len());
+    }
+}
+
+fn to_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Integer(i) => i.to_string(),
+        Value::Real(r) => r.to_string(),
+        Value::Text(s) => s.clone(),
+        Value::Blob(b) => format!("<{} bytes>", b.len()),
+    }
+}
+
+fn to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Integer(i) => (*i).into(),
+        Value::Real(r) => (*r).into(),
+        Value::Text(s) => s.as_str().into(),
+        Value::Blob(_) => to_text(value).into(),
+    }
+}
+
+fn csv_escape(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_owned()
+    }
+}
+
+#[test]
+fn canned_queries() -> Result<()> {
+    let path = "target/query.sqlite3";
+    let remove_db_files = || {
+        for suffix in ["", "-wal", "-shm"] {
+            _ = std::fs::remove_file(format!("{path}{suffix}"));
+        }
+    };
+    remove_db_files();
+    let conn = db::open(path)?;
+    conn.execute_batch(
+        "
+INSERT INTO crate (id, name, version, hash, timestamp) VALUES (1, 'a', '0.1.0', 1, '');
+INSERT INTO hir (crate, hash1, hash2, tool_attrs, def_path, name, function) VALUES
+  (1, 1, 1, '[]', 'a[1234]::read', 'a::read', ''),
+  (1, 2, 2, '[]', 'a[1234]::write', 'a::write', '');
+INSERT INTO requirement (hash1, hash2, tag, args, any_group) VALUES
+  (1, 1, 'Align', 'p, T', NULL),
+  (1, 1, 'Init', '', 0),
+  (1, 1, 'Zeroed', '', 0),
+  (2, 2, 'Align', 'p, T', NULL);
+INSERT INTO callsite (id, crate, caller_hash1, caller_hash2, caller, callee_hash1, callee_hash2,
+                      callee, file, line, col) VALUES
+  (1, 1, 9, 9, 'a::f', 1, 1, 'a::read', 'src/lib.rs', 3, 5),
+  (2, 1, 9, 9, 'a::f', 1, 1, 'a::read', 'src/lib.rs', 7, 5);
+INSERT INTO discharge (callsite, tag, reason) VALUES
+  (1, 'Align', 'aligned, \"really\"'),
+  (1, 'Zeroed', NULL),
+  (2, 'Align', NULL);
+",
+    )?;
+
+    let rows = query(&conn, REQUIRES, &[&"Align"])?;
+    let expected = "\
+crate,function,args,any_group
+a,a::read,\"p, T\",
+a,a::write,\"p, T\",
+";
+    assert_eq!(rows.render(Format::Csv), expected);
+
+    let rows = query(&conn, UNDISCHARGED, &[])?;
+    let expected = "\
+crate  caller  callee   tag     any_group  file        line  col
+-----  ------  -------  ------  ---------  ----------  ----  ---
+a      a::f    a::read  Init    0          src/lib.rs  7     5
+a      a::f    a::read  Zeroed  0          src/lib.rs  7     5
+(2 rows)
+";
+    assert_eq!(rows.render(Format::Table), expected);
+
+    let rows = query(&conn, TAGS, &[&"a[1234]::read"])?;
+    let tags: Vec<_> = rows.rows.iter().map(|row| to_text(&row[2])).collect();
+    assert_eq!(tags, ["Align", "Init", "Zeroed"]);
+
+    let sql = "SELECT reason FROM discharge WHERE callsite = 1 ORDER BY rowid";
+    let json = query_read_only(&conn, sql)?.render(Format::Json);
+    let expected = "[\n  {\n    \"reason\": \"aligned, \\\"really\\\"\"\n  },\n  {\n    \"reason\": null\n  }\n]\n";
+    assert_eq!(json, expected);
+    assert!(query_read_only(&conn, "DELETE FROM discharge").is_err());
+    drop(conn);
+
+    let conn = db::open_read_only(path)?;
+    assert_eq!(query(&conn, TAGS, &[&"a::write"])?.rows.len(), 1);
+    let attached = "target/query-attached.sqlite3";
+    assert!(query_read_only(&conn, &format!("ATTACH '{attached}' AS a")).is_err());
+    assert!(!Utf8Path::new(attached).exists());
+    drop(conn);
+
+    // an outdated schema is reported rather than migrated
+    db::open(path)?.pragma_update(None, "user_version", 0)?;
+    let err = db::open_read_only(path).unwrap_err().to_string();
+    assert!(err.contains("schema version 0"), "{err}");
+
+    remove_db_files();
+    Ok(())
+}
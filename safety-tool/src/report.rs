@@ -0,0 +1,346 @@
+//! Workspace report merging `Stat` files of all crates in `$SP_OUT_DIR`, emitted by
+//! [`Stat::write_to_file`], for `cargo safety-tool report`.
+//!
+//! Unlike metrics in each `Stat`, a tag is unused only if no crate in the workspace uses it,
+//! and call trees go across crates: a call into a library crate in the workspace is expanded
+//! with the callee's own unsafe calls.
+use crate::{
+    Result, Utf8PathBuf,
+    diff::{key_tag, read_stats},
+    query::{Format, Rows},
+    stat::{CrateType, Func, MetricsFuncsTotal, Stat, Tag},
+};
+use indexmap::{IndexMap, IndexSet};
+use itertools::Itertools;
+use rusqlite::types::Value;
+use serde::Serialize;
+use std::{env, fmt};
+
+const USAGE: &str = "\
+Usage: cargo safety-tool report [--json | --html] [STAT_PATH]...
+
+STAT_PATH is a Stat JSON file or a directory of them, defaulting to `$SP_OUT_DIR` if set.
+--html prints a self-contained HTML dashboard of the workspace.";
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    /// Functions in all crates.
+    pub total: MetricsFuncsTotal,
+    pub crates: Vec<CrateCoverage>,
+    /// Tags defined in the spec of any crate.
+    pub total_tags: usize,
+    /// Tags used in at least one crate.
+    pub used_tags: usize,
+    /// Tags used in no crate, in alphabet order.
+    pub unused: Vec<Box<str>>,
+    /// Call trees rooted at functions called by no function in the workspace.
+    pub call_trees: Vec<CallNode>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CrateCoverage {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub typ: CrateType,
+    pub funcs: MetricsFuncsTotal,
+    pub unsafe_fns: usize,
+    /// Unsafe functions with tags declared.
+    pub tagged_unsafe_fns: usize,
+}
+
+impl CrateCoverage {
+    fn new(stat: &Stat) -> Self {
+        let funcs = &stat.metrics.funcs;
+        CrateCoverage {
+            name: stat.krate.name.clone(),
+            typ: stat.krate.typ,
+            funcs: funcs.total.clone(),
+            unsafe_fns: funcs.r#unsafe.total.funcs,
+            tagged_unsafe_fns: funcs.r#unsafe.total.funcs_with_tags_declared,
+        }
+    }
+
+    /// Percentage of tagged unsafe functions, or None if there is no unsafe function.
+    pub fn coverage(&self) -> Option<f64> {
+        (self.unsafe_fns != 0)
+            .then(|| self.tagged_unsafe_fns as f64 * 100.0 / self.unsafe_fns as f64)
+    }
+}
+
+/// A function in a call tree.
+#[derive(Debug, Serialize)]
+pub struct CallNode {
+    /// Qualified by the crate name if the function is in the workspace, otherwise the path as
+    /// called.
+    pub name: String,
+    /// The crate defining the function, if it's in the workspace.
+    #[serde(rename = "crate")]
+    pub krate: Option<String>,
+    pub safe: bool,
+    /// Tags declared on the function.
+    pub tags: Vec<String>,
+    /// Tags discharged on the callsite. None for roots.
+    pub discharged: Option<Vec<String>>,
+    /// Where the function is defined for roots, or the callsite for callees.
+    pub path: Utf8PathBuf,
+    pub span: String,
+    /// Unsafe calls in the function.
+    pub calls: Vec<CallNode>,
+    /// True if the function has been expanded before in the tree, thus calls are omitted.
+    pub repeated: bool,
+}
+
+impl CallNode {
+    /// The function name with tags declared, and tags discharged on the callsite if any.
+    pub fn label(&self) -> String {
+        let mut label = self.name.clone();
+        if !self.tags.is_empty() {
+            label = format!("{label} {{ {} }}", self.tags.join(", "));
+        }
+        if let Some(discharged) = self.discharged.as_ref().filter(|tags| !tags.is_empty()) {
+            label = format!("{label} checked {{ {} }}", discharged.join(", "));
+        }
+        label
+    }
+
+    fn tree(&self) -> termtree::Tree<String> {
+        let tree = termtree::Tree::new(self.label());
+        tree.with_leaves(self.calls.iter().map(CallNode::tree))
+    }
+}
+
+/// Index of a `Stat`, and the local function name in it.
+pub(crate) type FnKey<'a> = (usize, &'a str);
+
+/// Functions in the workspace, where calls are resolved within the crate first, and then into
+/// library crates through names qualified by crate names.
+pub(crate) struct Workspace<'a> {
+    stats: &'a [Stat],
+    locals: Vec<IndexMap<&'a str, &'a Func>>,
+    libs: IndexMap<String, FnKey<'a>>,
+}
+
+impl<'a> Workspace<'a> {
+    pub(crate) fn new(stats: &'a [Stat]) -> Self {
+        let locals: Vec<IndexMap<_, _>> = stats
+            .iter()
+            .map(|stat| stat.funcs.iter().map(|func| (&*func.name, func)).collect())
+            .collect();
+        let mut libs = IndexMap::new();
+        for (idx, stat) in stats.iter().enumerate() {
+            if stat.krate.typ == CrateType::Lib {
+                let krate = &stat.krate.name;
+                libs.extend(
+                    stat.funcs.iter().map(|f| (format!("{krate}::{}", f.name), (idx, &*f.name))),
+                );
+            }
+        }
+        Workspace { stats, locals, libs }
+    }
+
+    pub(crate) fn resolve(&self, idx: usize, callee: &'a str) -> Option<FnKey<'a>> {
+        if self.locals[idx].contains_key(callee) {
+            Some((idx, callee))
+        } else {
+            self.libs.get(callee).copied()
+        }
+    }
+
+    pub(crate) fn func(&self, (idx, name): FnKey<'a>) -> &'a Func {
+        self.locals[idx][name]
+    }
+
+    fn call_trees(&self) -> Vec<CallNode> {
+        let mut called = IndexSet::new();
+        let mut candidates = Vec::new();
+        for (idx, funcs) in self.locals.iter().enumerate() {
+            for (&name, func) in funcs {
+                called.extend(func.unsafe_calls.iter().filter_map(|c| self.resolve(idx, &c.name)));
+                // Skip functions that have no unsafe calls and no tags.
+                if !func.unsafe_calls.is_empty() || !func.tags.is_empty() {
+                    candidates.push((idx, name));
+                }
+            }
+        }
+
+        let mut expanded = IndexSet::new();
+        let mut trees = Vec::new();
+        for key in candidates.iter().filter(|key| !called.contains(*key)) {
+            trees.push(self.node(*key, None, &mut expanded));
+        }
+        // Functions only called in cycles are never reached from roots above.
+        for key in &candidates {
+            if !expanded.contains(key) {
+                trees.push(self.node(*key, None, &mut expanded));
+            }
+        }
+        trees
+    }
+
+    fn node(
+        &self,
+        key: FnKey<'a>,
+        callsite: Option<&'a Func>,
+        expanded: &mut IndexSet<FnKey<'a>>,
+    ) -> CallNode {
+        let func = self.func(key);
+        let repeated = !expanded.insert(key);
+        let calls = if repeated {
+            Vec::new()
+        } else {
+            let calls = func.unsafe_calls.iter();
+            calls.map(|call| self.callee(key.0, call, expanded)).collect()
+        };
+        let site = callsite.unwrap_or(func);
+        CallNode {
+            name: format!("{}::{}", self.stats[key.0].krate.name, func.name),
+            krate: Some(self.stats[key.0].krate.name.clone()),
+            safe: func.safe,
+            tags: render_tags(&func.tags),
+            discharged: callsite.map(|call| render_tags(&call.tags)),
+            path: site.path.clone(),
+            span: site.span.clone(),
+            calls,
+            repeated,
+        }
+    }
+
+    fn callee(&self, idx: usize, call: &'a Func, expanded: &mut IndexSet<FnKey<'a>>) -> CallNode {
+        if let Some(key) = self.resolve(idx, &call.name) {
+            return self.node(key, Some(call), expanded);
+        }
+        CallNode {
+            name: call.name.clone(),
+            krate: None,
+            safe: call.safe,
+            tags: Vec::new(),
+            discharged: Some(render_tags(&call.tags)),
+            path: call.path.clone(),
+            span: call.span.clone(),
+            calls: Vec::new(),
+            repeated: false,
+        }
+    }
+}
+
+/// Rendered tags without duplicates.
+pub(crate) fn render_tags(tags: &[Tag]) -> Vec<String> {
+    tags.iter().map(|tag| key_tag(tag).1).unique().collect()
+}
+
+impl Report {
+    pub fn new(stats: &[Stat]) -> Self {
+        let mut total = MetricsFuncsTotal::default();
+        let mut defined = IndexSet::new();
+        let mut used = IndexSet::new();
+        for stat in stats {
+            total.merge(&stat.metrics.funcs.total);
+            defined.extend(stat.specs.map.keys());
+            used.extend(stat.metrics.used.keys());
+        }
+        let mut unused: Vec<Box<str>> =
+            defined.iter().filter(|tag| !used.contains(*tag)).map(|tag| (*tag).clone()).collect();
+        unused.sort_unstable();
+
+        Report {
+            total,
+            crates: stats.iter().map(CrateCoverage::new).collect(),
+            total_tags: defined.len(),
+            used_tags: defined.len() - unused.len(),
+            unused,
+            call_trees: Workspace::new(stats).call_trees(),
+        }
+    }
+
+    fn crate_rows(&self) -> Rows {
+        let columns = [
+            "crate",
+            "type",
+            "funcs",
+            "unsafe_fns",
+            "tagged_unsafe_fns",
+            "coverage",
+            "unsafe_calls",
+            "discharged_tags",
+        ];
+        let text = |s: String| Value::Text(s);
+        let rows = self.crates.iter().map(|c| {
+            let coverage = c.coverage().map(|p| format!("{p:.1}%")).unwrap_or_else(|| "-".into());
+            vec![
+                text(c.name.clone()),
+                text(format!("{:?}", c.typ)),
+                text(c.funcs.funcs.to_string()),
+                text(c.unsafe_fns.to_string()),
+                text(c.tagged_unsafe_fns.to_string()),
+                text(coverage),
+                text(c.funcs.unsafe_calls.to_string()),
+                text(c.funcs.discharged_tags.to_string()),
+            ]
+        });
+        Rows { columns: columns.map(String::from).to_vec(), rows: rows.collect() }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { total, crates, .. } = self;
+        let unsafe_fns: usize = crates.iter().map(|c| c.unsafe_fns).sum();
+        let tagged: usize = crates.iter().map(|c| c.tagged_unsafe_fns).sum();
+        writeln!(
+            f,
+            "{} crates: {} functions, {tagged} of {unsafe_fns} unsafe functions tagged, \
+             {} unsafe calls, {} tags discharged.",
+            crates.len(),
+            total.funcs,
+            total.unsafe_calls,
+            total.discharged_tags
+        )?;
+        writeln!(f, "{} of {} tags are used.\n", self.used_tags, self.total_tags)?;
+        writeln!(f, "{}", self.crate_rows().render(Format::Table))?;
+
+        writeln!(f, "Unused tags across the workspace: {}", self.unused.len())?;
+        if !self.unused.is_empty() {
+            writeln!(f, "{}", self.unused.iter().format(", "))?;
+        }
+
+        writeln!(f, "\nCall trees: {}", self.call_trees.len())?;
+        for tree in &self.call_trees {
+            write!(f, "{}", tree.tree())?;
+        }
+        Ok(())
+    }
+}
+
+/// Entry of `cargo safety-tool report`.
+pub fn run(args: &[String]) -> Result<()> {
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{USAGE}");
+        return Ok(());
+    }
+    let json = args.iter().any(|arg| arg == "--json");
+    let html = args.iter().any(|arg| arg == "--html");
+    let paths: Vec<Utf8PathBuf> = args
+        .iter()
+        .filter(|arg| *arg != "--json" && *arg != "--html")
+        .map(Utf8PathBuf::from)
+        .collect();
+    let paths = match (paths.is_empty(), env::var("SP_OUT_DIR")) {
+        (true, Ok(dir)) => vec![Utf8PathBuf::from(dir)],
+        (true, Err(_)) => bail!("Expect Stat JSON files or SP_OUT_DIR.\n{USAGE}"),
+        _ => paths,
+    };
+    let stats = read_stats(&paths)?;
+    ensure!(!stats.is_empty(), "No Stat JSON is found in {paths:?}.");
+
+    if html {
+        print!("{}", crate::html::render(&stats));
+        return Ok(());
+    }
+    let report = Report::new(&stats);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{report}");
+    }
+    Ok(())
+}
@@ -1,13 +1,12 @@
 use camino::{Utf8Path, Utf8PathBuf};
-use indexmap::{IndexMap, IndexSet};
+use indexmap::IndexMap;
 use itertools::Itertools;
-use owo_colors::{Color, OwoColorize, colors};
 use safety_parser::{
     configuration::{CACHE, GenDocOption, Key},
     safety::{PropertiesAndReason, Property},
 };
 use serde::{Deserialize, Serialize};
-use std::{env, fmt, fs, sync::LazyLock};
+use std::{env, fmt, fs};
 
 #[derive(Deserialize, Serialize)]
 pub struct Stat {
@@ -16,6 +15,9 @@ pub struct Stat {
     pub specs: Specs,
     pub funcs: Vec<Func>,
     pub metrics: Metrics,
+    /// Sites sharing each ref id in the entity reference system.
+    #[serde(default)]
+    pub refs: IndexMap<Box<str>, Vec<RefSite>>,
 }
 
 impl fmt::Debug for Stat {
@@ -25,6 +27,7 @@ impl fmt::Debug for Stat {
             // .field("specs", &self.specs)
             .field("funcs", &self.funcs)
             .field("metrics", &self.metrics)
+            .field("refs", &self.refs)
             .finish()
     }
 }
@@ -38,7 +41,7 @@ impl Stat {
             for callee in &func.unsafe_calls {
                 callee.update_specs(&mut self.specs);
             }
-            func.update_metrics(&mut self.metrics.funcs);
+            func.update_metrics(&mut self.metrics);
         }
 
         // Merge metrics of funcs.
@@ -67,6 +70,9 @@ impl Stat {
         // Sort by unsafe call counts.
         self.metrics.funcs.safe.unsafe_calls.sort_unstable_keys();
         self.metrics.funcs.r#unsafe.unsafe_calls.sort_unstable_keys();
+        // Sort by module paths and file paths in alphabet order.
+        self.metrics.modules.sort_unstable_keys();
+        self.metrics.files.sort_unstable_keys();
 
         // Tags in spec have been in alphabet order, and unused list are in the insertion order.
     }
@@ -81,55 +87,14 @@ impl Stat {
             && let Ok(file) = fs::File::create(path)
         {
             _ = serde_json::to_writer_pretty(file, self);
-            self.write_call_tree();
+            self.write_html();
         }
     }
 
-    pub fn write_call_tree(&self) {
-        use std::io::Write;
-        let Some(mut file) = self.krate.output_tree_file_path() else { return };
-
-        let local_fns: IndexMap<_, _> = self.funcs.iter().map(|f| (f.name.as_str(), f)).collect();
-        let mut intree = IndexSet::new();
-        for func in &self.funcs {
-            if func.unsafe_calls.is_empty() && func.has_no_tag() {
-                // Skip functions that have no unsafe calls and no tags.
-                continue;
-            }
-
-            let mut tree = Tree::new(func.root_node());
-            intree.insert(&*func.name);
-
-            call_tree(&mut tree, func, &mut intree, &local_fns);
-            _ = writeln!(&mut file, "{tree}");
-        }
-    }
-}
-
-type Tree = termtree::Tree<String>;
-
-// Recursively add the call nodes.
-fn call_tree<'a>(
-    tree: &mut Tree,
-    func: &'a Func,
-    intree: &mut IndexSet<&'a str>,
-    local_fns: &IndexMap<&'a str, &'a Func>,
-) {
-    for call in &func.unsafe_calls {
-        let name = &*call.name;
-        if let Some(local_fn) = local_fns.get(name) {
-            // Push down the leaves when the call is local and never called before.
-            if intree.insert(name) {
-                let mut leaf = Tree::new(local_fn.root_node());
-                call_tree(&mut leaf, local_fn, intree, local_fns);
-                tree.push(leaf);
-            } else {
-                // Don't go down when the node exists, but generate the node with tags.
-                tree.push(local_fn.root_node());
-            }
-        } else {
-            // When the call is external, only the name is available.
-            tree.push(name.to_owned());
+    /// Write the HTML dashboard of this crate, next to the JSON file.
+    pub fn write_html(&self) {
+        if let Some(path) = self.krate.output_file_path("html") {
+            _ = fs::write(path, crate::html::render(std::slice::from_ref(self)));
         }
     }
 }
@@ -169,17 +134,13 @@ impl Krate {
     fn output_json_file_path(&self) -> Option<Utf8PathBuf> {
         self.output_file_path("json")
     }
-
-    fn output_tree_file_path(&self) -> Option<fs::File> {
-        fs::File::create(self.output_file_path("txt")?).ok()
-    }
 }
 
 /// `TyCtxt::crate_type` returns a list:
 /// * Executable is compiled alone: a crate won't be a bin and lib at the same time
 /// * multiple types of library is possible, when `[lib] crate-type = [ ... ]` in
 ///   Cargo.toml or multiple `--crate-type` are specified.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub enum CrateType {
     /// i.e. CrateType::Executable in rustc_session
     Bin,
@@ -217,7 +178,7 @@ impl Specs {
 
     /// This method should be called after all tag usage is collected.
     fn update_metrics(&self, metrics: &mut Metrics) {
-        metrics.total_tags = self.map.len().try_into().unwrap();
+        metrics.total_tags = self.map.len();
         for (name, item) in &self.map {
             if item.usage.is_unused() {
                 // This tag is unused.
@@ -248,8 +209,8 @@ pub struct SpecItem {
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Usage {
-    pub types: IndexMap<TagTypeUsage, u16>,
-    pub predicates: IndexMap<Predicate, u16>,
+    pub types: IndexMap<TagTypeUsage, usize>,
+    pub predicates: IndexMap<Predicate, usize>,
     pub functions: Vec<Box<str>>,
 }
 
@@ -278,6 +239,9 @@ impl Usage {
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Func {
     pub name: String,
+    /// The module defining the function, qualified by the crate name, e.g. `kernel::mm`.
+    #[serde(default)]
+    pub module: String,
     pub safe: bool,
     pub path: Utf8PathBuf,
     pub span: String,
@@ -313,54 +277,40 @@ impl Func {
         }
     }
 
-    fn update_metrics(&self, metrics_funcs: &mut MetricsFunctions) {
-        let m = if self.safe { &mut metrics_funcs.safe } else { &mut metrics_funcs.r#unsafe };
-        m.total.funcs += 1;
-
-        if !self.tags.is_empty() {
-            m.total.funcs_with_tags_declared += 1;
-        }
-        if self.unsafe_calls.iter().any(|c| !c.tags.is_empty()) {
-            m.total.funcs_with_tags_discharged += 1;
-        }
-
-        m.total.declared_tags += self.tags.len() as u16;
-        m.total.discharged_tags +=
-            self.unsafe_calls.iter().map(|c| c.tags.len() as u16).sum::<u16>();
-
-        let unsafe_calls = self.unsafe_calls.len() as u16;
-        m.total.unsafe_calls += unsafe_calls;
-        m.unsafe_calls.entry(unsafe_calls).and_modify(|c| *c += 1).or_insert(1);
-    }
-
-    fn has_no_tag(&self) -> bool {
-        // There must be a tag as an element in self.tags. It could possibly be
-        // empty for Any TagType, but that's still an `any` tag.
-        self.tags.is_empty()
+    fn update_metrics(&self, metrics: &mut Metrics) {
+        let total = self.metrics_total();
+        metrics.modules.entry(self.module.as_str().into()).or_default().merge(&total);
+        metrics.files.entry(self.path.clone()).or_default().merge(&total);
+
+        let funcs = &mut metrics.funcs;
+        let m = if self.safe { &mut funcs.safe } else { &mut funcs.r#unsafe };
+        m.total.merge(&total);
+        m.unsafe_calls.entry(total.unsafe_calls).and_modify(|c| *c += 1).or_insert(1);
     }
 
-    fn root_node(&self) -> String {
-        if self.has_no_tag() {
-            self.name.to_owned()
-        } else {
-            let color = *SP_COLOR;
-            let tags = self.tags.iter().map(|tag| &tag.tag).format_with(", ", |ele, f| {
-                if color {
-                    f(&format_args!("{}", ele.fg::<colors::css::Orange>().bold()))
-                } else {
-                    f(ele)
-                }
-            });
-            let gray = if color { colors::xterm::DustyGray::ANSI_FG } else { "" };
-            let end = if color { "\x1B[0m" } else { "" };
-            format!("{} {}", self.name, format_args!("{gray}{{{end} {tags} {gray}}}{end}"))
+    /// Metrics counting this function only.
+    fn metrics_total(&self) -> MetricsFuncsTotal {
+        MetricsFuncsTotal {
+            funcs: 1,
+            funcs_with_tags_declared: (!self.tags.is_empty()).into(),
+            funcs_with_tags_discharged: self.unsafe_calls.iter().any(|c| !c.tags.is_empty()).into(),
+            declared_tags: self.tags.len(),
+            discharged_tags: self.unsafe_calls.iter().map(|c| c.tags.len()).sum(),
+            unsafe_calls: self.unsafe_calls.len(),
         }
     }
 }
 
-// When `SP_COLOR` is unset or set to 0, no color for tag names.
-static SP_COLOR: LazyLock<bool> =
-    LazyLock::new(|| env::var("SP_COLOR").map(|s| s != "0").unwrap_or(false));
+/// A site annotated with `#[ref(id)]`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RefSite {
+    /// The function where the site is, or the function itself.
+    pub func: String,
+    pub path: Utf8PathBuf,
+    pub span: String,
+    /// True if tags are discharged on this site, and shared with other sites.
+    pub discharge: bool,
+}
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Tag {
@@ -416,9 +366,9 @@ pub enum Predicate {
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Metrics {
     /// Amount of tags in specification.
-    pub total_tags: u16,
+    pub total_tags: usize,
     /// How many tags are acutally used?
-    pub used_tags: u16,
+    pub used_tags: usize,
     /// How many times are these tags used in details?
     pub used: IndexMap<Box<str>, MetricsCoverage>,
     /// How many times are these tags used in general?
@@ -426,22 +376,28 @@ pub struct Metrics {
     /// Unused tag names.
     pub unused: Vec<Box<str>>,
     pub funcs: MetricsFunctions,
+    /// Metrics of functions aggregated per module, keyed by [`Func::module`].
+    #[serde(default)]
+    pub modules: IndexMap<Box<str>, MetricsFuncsTotal>,
+    /// Metrics of functions aggregated per source file.
+    #[serde(default)]
+    pub files: IndexMap<Utf8PathBuf, MetricsFuncsTotal>,
 }
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct MetricsCoverage {
     /// Sum of the requires, checked, and delegated (or equivalently as_vanilla + in_any).
-    pub occurence: u16,
+    pub occurence: usize,
     /// How many times does the tag is used in `requires` predicate?
-    pub requires: u16,
+    pub requires: usize,
     /// How many times does the tag is used in `checked` predicate?
-    pub checked: u16,
+    pub checked: usize,
     /// How many times does the tag is used in `delegated` predicate?
-    pub delegated: u16,
+    pub delegated: usize,
     /// How many times does the tag is used individually?
-    pub as_vanilla: u16,
+    pub as_vanilla: usize,
     /// How many times does the tag is used in `any` tag?
-    pub in_any: u16,
+    pub in_any: usize,
 }
 
 impl MetricsCoverage {
@@ -467,7 +423,7 @@ impl MetricsCoverage {
     }
 
     /// Add the number in detail to self.
-    fn merge(&mut self, detail: &Self) {
+    pub fn merge(&mut self, detail: &Self) {
         self.occurence += detail.occurence;
         self.as_vanilla += detail.as_vanilla;
         self.in_any += detail.in_any;
@@ -494,21 +450,22 @@ impl MetricsFunctions {
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct MetricsFuncs {
     pub total: MetricsFuncsTotal,
-    pub unsafe_calls: IndexMap<u16, u16>,
+    /// Counts of functions keyed by how many unsafe calls each has.
+    pub unsafe_calls: IndexMap<usize, usize>,
 }
 
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct MetricsFuncsTotal {
-    pub funcs: u16,
-    pub funcs_with_tags_declared: u16,
-    pub funcs_with_tags_discharged: u16,
-    pub declared_tags: u16,
-    pub discharged_tags: u16,
-    pub unsafe_calls: u16,
+    pub funcs: usize,
+    pub funcs_with_tags_declared: usize,
+    pub funcs_with_tags_discharged: usize,
+    pub declared_tags: usize,
+    pub discharged_tags: usize,
+    pub unsafe_calls: usize,
 }
 
 impl MetricsFuncsTotal {
-    fn merge(&mut self, other: &Self) {
+    pub fn merge(&mut self, other: &Self) {
         self.funcs += other.funcs;
         self.funcs_with_tags_declared += other.funcs_with_tags_declared;
         self.funcs_with_tags_discharged += other.funcs_with_tags_discharged;
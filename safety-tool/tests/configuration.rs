@@ -16,6 +16,17 @@ fn deserialize() {
     dbg!(&toml);
 }
 
+/// Type names in hover docs, diagnostics, and completion are spelled as in specs and attrs.
+#[test]
+fn tag_type_names() {
+    use safety_parser::configuration::TagType;
+
+    for typ in [TagType::Precond, TagType::Hazard, TagType::Option] {
+        assert_eq!(TagType::try_new(typ.as_str()), Some(typ));
+        assert_eq!(toml::Value::try_from(typ).unwrap().as_str(), Some(typ.as_str()));
+    }
+}
+
 #[test]
 fn core() {
     let config = &Configuration::read_toml("assets/sp-core.toml");
@@ -27,3 +38,87 @@ fn rust_for_linux() {
     let config = &Configuration::read_toml("assets/sp-rust-for-linux.toml");
     expect!["40"].assert_eq(&config.tag.len().to_string());
 }
+
+#[test]
+fn temporal_tags() {
+    const TOML: &str = r#"
+[tag.IrqRequested]
+follows = "RequestIrq"
+
+[tag.RequestIrq]
+precedes = ["IrqRequested", "FreeIrq"]
+"#;
+    let toml: Configuration = toml::from_str(TOML).unwrap();
+    expect![[r#"["RequestIrq"]"#]].assert_eq(&format!("{:?}", toml.tag["IrqRequested"].follows));
+    expect![[r#"["IrqRequested", "FreeIrq"]"#]]
+        .assert_eq(&format!("{:?}", toml.tag["RequestIrq"].precedes));
+    assert!(toml.tag["RequestIrq"].follows.is_empty());
+}
+
+#[test]
+fn versioned_tags() {
+    let config = &Configuration::read_toml("tests/snippets/sp-versions.toml");
+    let version = config.package.as_ref().and_then(|p| p.version.as_deref());
+    expect![[r#"Some("0.3.0")"#]].assert_eq(&format!("{version:?}"));
+    let revised = &config.tag["Revised"];
+    expect![[r#"(Some("0.1"), Some("0.3"))"#]]
+        .assert_eq(&format!("{:?}", (&revised.since, &revised.changed_in)));
+    assert!(config.tag["Fresh"].changed_in.is_none());
+}
+
+#[test]
+fn require_reason() {
+    let config = &Configuration::read_toml("tests/snippets/sp-reason.toml");
+    assert!(!config.policy.require_reason);
+    assert!(config.tag["Alias"].require_reason);
+    assert!(!config.tag["Align"].require_reason);
+
+    let config = &Configuration::read_toml("tests/snippets/sp-reason-policy.toml");
+    assert!(config.policy.require_reason);
+}
+
+#[test]
+fn load_cache() {
+    use safety_parser::configuration::Cache;
+
+    let core = "assets/sp-core.toml".to_owned();
+    let cache = Cache::load(std::slice::from_ref(&core)).unwrap();
+    expect!["26"].assert_eq(&cache.map.len().to_string());
+
+    // errors rather than panics
+    let err = Cache::load(&["tests/snippets/no-such-spec.toml".to_owned()]).unwrap_err();
+    assert!(err.starts_with("Failed to read tests/snippets/no-such-spec.toml"), "{err}");
+    let err = Cache::load(&[core.clone(), core]).unwrap_err();
+    assert!(err.contains("has been defined"), "{err}");
+
+    // unknown placeholders are warned, and the desc is kept as is
+    let cache = Cache::load(&["tests/snippets/sp-placeholder.toml".to_owned()]).unwrap();
+    let desc = cache.map["Align"].tag.desc.as_deref();
+    assert_eq!(desc, Some("{p} is aligned for {T}"));
+}
+
+#[test]
+fn desc_placeholders() {
+    use safety_parser::configuration::placeholders;
+
+    assert_eq!(placeholders("{p} is aligned for {T}"), ["p", "T"]);
+    assert_eq!(placeholders("{ a.b | fmt } \\{c} {{ if d }}x{{ endif }} {# e #}"), ["a"]);
+    assert!(placeholders("no {placeholder").is_empty());
+}
+
+#[test]
+fn reload_spec() {
+    use safety_parser::configuration::{get_tag_opt, reload, spec_exists};
+
+    let count = reload(&["assets/sp-core.toml".to_owned()]).unwrap();
+    expect!["26"].assert_eq(&count.to_string());
+    assert!(spec_exists());
+    assert!(get_tag_opt("Align").is_some());
+
+    // the previous spec is kept on errors
+    reload(&["tests/snippets/no-such-spec.toml".to_owned()]).unwrap_err();
+    assert!(get_tag_opt("Align").is_some());
+
+    reload(&["tests/snippets/sp-versions.toml".to_owned()]).unwrap();
+    assert!(get_tag_opt("Align").is_none());
+}
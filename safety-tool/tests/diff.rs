@@ -0,0 +1,91 @@
+use expect_test::expect;
+use safety_tool::diff::{FnTags, Report, read_stats};
+use serde_json::{Value, json};
+
+const DEMO_TAGS: &str = "tests/demo/upg/_tags/demo.json";
+
+fn demo_tags() -> Value {
+    serde_json::from_str(&std::fs::read_to_string(DEMO_TAGS).unwrap()).unwrap()
+}
+
+fn fn_tags(value: Value) -> FnTags {
+    FnTags::from_output(&serde_json::from_value(value).unwrap())
+}
+
+#[test]
+fn same_version() {
+    let tags = FnTags::from_file(DEMO_TAGS.into()).unwrap();
+    let stat = FnTags::from_file("tests/demo/out/demo.json".into()).unwrap();
+    let report = Report::new(&tags, &stat, &[]);
+    expect![[r#"
+        Safety tags changed on 0 functions.
+
+        Undischarged callsites: 0
+    "#]]
+    .assert_eq(&report.to_string());
+}
+
+#[test]
+fn upgrade_demo() {
+    let old = demo_tags();
+    let mut new = old.clone();
+
+    // A new obligation on the function called in bin-demo.
+    let get = new["v_fn"]["demo::MyStruct::get"][0]["tags"].as_array_mut().unwrap();
+    let mut layout = get[0].clone();
+    layout["sp"] = json!({ "tag": { "typ": null, "name": "Layout" }, "args": ["self.ptr"] });
+    get.push(layout);
+
+    // A relaxed obligation and a changed one.
+    let test = new["v_fn"]["demo::test"][0]["tags"].as_array_mut().unwrap();
+    test.retain(|tag| tag["sp"]["tag"]["name"] != "Alias");
+    test[0]["sp"]["args"] = json!(["self.ptr", "u8", "self.cap"]);
+
+    let downstream = read_stats(&["tests/demo/out".into()]).unwrap();
+    let report = Report::new(&fn_tags(old), &fn_tags(new), &downstream);
+    expect![[r#"
+        Safety tags changed on 2 functions.
+        demo::test
+          - Alias(self.ptr)
+          ~ Init(self.ptr, u8, self.len) => Init(self.ptr, u8, self.cap)
+        demo::MyStruct::get
+          + Layout(self.ptr)
+
+        Undischarged callsites: 1
+        src/main.rs:23 in `main` calls `demo::MyStruct::get`
+          missing Layout
+    "#]]
+    .assert_eq(&report.to_string());
+}
+
+#[test]
+fn same_tag_with_different_args() {
+    let mut old = demo_tags();
+    let tags = old["v_fn"]["demo::test"][0]["tags"].as_array_mut().unwrap();
+    let init = tags.iter().find(|tag| tag["sp"]["tag"]["name"] == "Init").unwrap().clone();
+    let with_args = |args| {
+        let mut tag = init.clone();
+        tag["sp"]["args"] = args;
+        tag
+    };
+    // `Init` declared twice with different args is kept as two tags.
+    tags.push(with_args(json!(["self.ptr", "u16", "self.len"])));
+    let mut new = old.clone();
+
+    // One of them is removed, and the other one is changed.
+    let tags = new["v_fn"]["demo::test"][0]["tags"].as_array_mut().unwrap();
+    tags.retain(|tag| tag["sp"]["tag"]["name"] != "Init");
+    tags.push(with_args(json!(["self.ptr", "u32", "self.len"])));
+
+    let report = Report::new(&fn_tags(old), &fn_tags(new), &[]);
+    expect![[r#"
+        Safety tags changed on 1 functions.
+        demo::test
+          + Init(self.ptr, u32, self.len)
+          - Init(self.ptr, u8, self.len)
+          - Init(self.ptr, u16, self.len)
+
+        Undischarged callsites: 0
+    "#]]
+    .assert_eq(&report.to_string());
+}
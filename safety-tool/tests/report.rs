@@ -0,0 +1,40 @@
+use expect_test::{expect, expect_file};
+use safety_tool::{diff::read_stats, html, report::Report};
+
+#[test]
+fn demo_workspace() {
+    let stats = read_stats(&["tests/demo/out".into()]).unwrap();
+    let report = Report::new(&stats);
+    expect![[r#"
+        2 crates: 4 functions, 2 of 2 unsafe functions tagged, 5 unsafe calls, 14 tags discharged.
+        10 of 27 tags are used.
+
+        crate  type  funcs  unsafe_fns  tagged_unsafe_fns  coverage  unsafe_calls  discharged_tags
+        -----  ----  -----  ----------  -----------------  --------  ------------  ---------------
+        demo   Bin   1      0           0                  -         3             14
+        demo   Lib   3      2           2                  100.0%    2             0
+        (2 rows)
+
+        Unused tags across the workspace: 17
+        Allocated, Layout, NoPadding, NonOverlap, NonOwned, NonVolatile, Opened, Owning, Pinned, Ptr2Ref, Size, Trait, Typed, Unreachable, Unwrap, ValidCStr, ValidString
+
+        Call trees: 2
+        demo::main
+        ├── core::fmt::rt::<impl std::fmt::Arguments<'a>>::new_v1_formatted
+        ├── core::fmt::rt::<impl std::fmt::Arguments<'a>>::new_v1_formatted
+        └── demo::MyStruct::get { NonNull(self.ptr), ValidPtr(self.ptr, u8, self.len), Init(self.ptr, u8, self.len), Alive(self.ptr, _), Alias(self.ptr), Align(self.ptr, u8), ValidNum(self.len * sizeof(u8), [0, isize::MAX]) } checked { NonNull(p), ValidPtr(p, u8, l), Init(p, u8, l), Alive(p, l), Alias(p), Align(p, u8), ValidNum(l, [0, isize::MAX]) }
+            └── std::slice::from_raw_parts_mut
+        demo::test { Init(self.ptr, u8, self.len), InBound(self.ptr, u8, self.len), ValidNum(self.len * sizeof(u8), [0, isize::MAX]), Alias(self.ptr), RustdocLinkToItem(crate::test), any(Deref(self.ptr, u8, 1), Alive(self.ptr, _)) }
+        └── std::intrinsics::unreachable
+    "#]].assert_eq(&report.to_string());
+}
+
+#[test]
+fn demo_dashboard() {
+    let mut stats = read_stats(&["tests/demo/out".into()]).unwrap();
+    // Resolve relative paths of callsites against the demo here for source snippets.
+    for stat in &mut stats {
+        stat.krate.path = "tests/demo/src/main.rs".into();
+    }
+    expect_file!["./snapshots/report_demo.html"].assert_eq(&html::render(&stats));
+}
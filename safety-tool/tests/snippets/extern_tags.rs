@@ -0,0 +1,25 @@
+#![feature(stmt_expr_attributes)]
+#![feature(register_tool)]
+#![register_tool(rapx)]
+#![allow(dead_code)]
+
+pub fn read(p: *const u8) -> u8 {
+    #[rapx::checked(ValidPtr, Align, Init, Trait)]
+    unsafe {
+        core::ptr::read(p)
+    }
+}
+
+pub fn as_ref(p: *const u8) -> Option<&'static u8> {
+    #[rapx::checked(Ptr2Ref)]
+    unsafe {
+        p.as_ref()
+    }
+}
+
+pub fn write(p: *mut u8) {
+    #[rapx::checked(ValidPtr)]
+    unsafe {
+        core::ptr::write(p, 0)
+    }
+}
@@ -0,0 +1,33 @@
+#![feature(stmt_expr_attributes)]
+#![feature(register_tool)]
+#![register_tool(rapx)]
+#![allow(dead_code, unused_variables)]
+
+#[rapx::requires(RequestIrq(irq))]
+pub fn request_irq(irq: u32) {}
+
+#[rapx::requires(IrqRequested(irq))]
+pub unsafe fn free_irq(irq: u32) {}
+
+pub fn request_on_a_path(irq: u32, cond: bool) {
+    if cond {
+        request_irq(irq);
+    }
+    #[rapx::checked(IrqRequested(irq))]
+    unsafe { free_irq(irq) };
+}
+
+#[rapx::requires(Locked)]
+pub unsafe fn lock() {}
+
+#[rapx::requires(Unlock)]
+pub fn unlock() {}
+
+pub fn return_before_unlock(cond: bool) {
+    #[rapx::checked(Locked)]
+    unsafe { lock() };
+    if cond {
+        return;
+    }
+    unlock();
+}
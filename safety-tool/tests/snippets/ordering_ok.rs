@@ -0,0 +1,41 @@
+#![feature(stmt_expr_attributes)]
+#![feature(register_tool)]
+#![register_tool(rapx)]
+#![allow(dead_code, unused_variables)]
+
+#[rapx::requires(RequestIrq(irq))]
+pub fn request_irq(irq: u32) {}
+
+#[rapx::requires(IrqRequested(irq))]
+pub unsafe fn free_irq(irq: u32) {}
+
+pub fn request_then_free(irq: u32, cond: bool) {
+    request_irq(irq);
+    if cond {
+        return;
+    }
+    #[rapx::checked(IrqRequested(irq))]
+    unsafe { free_irq(irq) };
+}
+
+#[rapx::requires(IrqRequested(irq))]
+pub unsafe fn delegated(irq: u32) {
+    #[rapx::checked(IrqRequested(irq))]
+    unsafe { free_irq(irq) };
+}
+
+#[rapx::requires(Locked)]
+pub unsafe fn lock() {}
+
+#[rapx::requires(Unlock)]
+pub fn unlock() {}
+
+pub fn lock_then_unlock(cond: bool) {
+    #[rapx::checked(Locked)]
+    unsafe { lock() };
+    if cond {
+        unlock();
+    } else {
+        unlock();
+    }
+}
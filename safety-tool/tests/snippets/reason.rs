@@ -0,0 +1,17 @@
+#![feature(stmt_expr_attributes)]
+#![feature(register_tool)]
+#![register_tool(rapx)]
+#![allow(dead_code)]
+
+#[rapx::requires(Alias, Align)]
+pub unsafe fn get() {}
+
+pub fn with_reason() {
+    #[rapx::checked(Alias: "the pointer is unique here"; Align)]
+    unsafe { get() };
+}
+
+pub fn without_reason() {
+    #[rapx::checked(Alias, Align)]
+    unsafe { get() };
+}
@@ -0,0 +1,17 @@
+#![feature(stmt_expr_attributes)]
+#![feature(register_tool)]
+#![register_tool(rapx)]
+#![allow(dead_code)]
+
+#[rapx::requires(Align)]
+pub unsafe fn get() {}
+
+pub fn with_reason() {
+    #[rapx::checked(Align; any(Alias, Align): "the pointer is unique here")]
+    unsafe { get() };
+}
+
+pub fn without_reason() {
+    #[rapx::checked(Align; any(Alias, Align))]
+    unsafe { get() };
+}
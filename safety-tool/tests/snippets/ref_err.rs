@@ -0,0 +1,15 @@
+#![feature(stmt_expr_attributes)]
+#![feature(register_tool)]
+#![register_tool(rapx)]
+#![allow(dead_code)]
+
+pub fn no_discharge() {
+    #[rapx::r#ref(undischarged)]
+    unsafe { call() };
+
+    #[rapx::r#ref(undischarged)]
+    unsafe { call() };
+}
+
+#[rapx::requires(SP1)]
+unsafe fn call() {}
@@ -0,0 +1,36 @@
+#![feature(stmt_expr_attributes)]
+#![feature(register_tool)]
+#![register_tool(rapx)]
+#![allow(dead_code)]
+
+struct Guard;
+
+impl Drop for Guard {
+    #[rapx::r#ref(guarded)] // calls below rely on this drop impl
+    fn drop(&mut self) {}
+}
+
+pub fn discharge_once() {
+    let _guard = Guard;
+
+    #[rapx::r#ref(guarded)]
+    #[rapx::checked(SP1: "guard is alive")]
+    unsafe { call() };
+
+    #[rapx::r#ref(guarded)] // same as above
+    unsafe { call() };
+}
+
+pub fn refer_from_another_fn() {
+    #[rapx::r#ref(guarded)] // see discharge_once
+    unsafe { call() };
+}
+
+pub fn single_endpoint() {
+    #[rapx::r#ref(lonely)]
+    #[rapx::checked(SP1)]
+    unsafe { call() };
+}
+
+#[rapx::requires(SP1)]
+unsafe fn call() {}
@@ -0,0 +1,15 @@
+#![feature(stmt_expr_attributes)]
+#![feature(register_tool)]
+#![register_tool(rapx)]
+#![allow(dead_code)]
+
+#[rapx::inner(SP1, SP2)]
+unsafe fn call() {}
+
+pub fn twice() {
+    // Each undischarged callsite is reported, not only the first one.
+    #[rapx::checked(SP1)]
+    unsafe { call() };
+    #[rapx::checked(SP2)]
+    unsafe { call() };
+}
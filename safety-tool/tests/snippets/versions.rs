@@ -0,0 +1,17 @@
+#![feature(stmt_expr_attributes)]
+#![feature(register_tool)]
+#![register_tool(rapx)]
+#![allow(dead_code)]
+
+#[rapx::requires(Stable, Revised)]
+pub unsafe fn stable() {}
+
+#[rapx::requires(Fresh)]
+pub unsafe fn fresh() {}
+
+pub fn call() {
+    #[rapx::checked(Stable, Revised)]
+    unsafe { stable() };
+    #[rapx::checked(Fresh)]
+    unsafe { fresh() };
+}
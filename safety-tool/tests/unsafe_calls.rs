@@ -129,6 +129,12 @@ fn duplicated_discharge() {
     should_panic(file, outfile, Default::default());
 }
 
+#[test]
+fn undischarged_twice() {
+    let [file, outfile] = &testcase("undischarged_twice");
+    should_panic(file, outfile, Default::default());
+}
+
 #[test]
 fn any_err() {
     let [file, outfile] = &testcase("any_err");
@@ -165,6 +171,24 @@ fn fine(file: &str, outfile: &str, opts: CompilationOptions) {
     expect_file![outfile].assert_eq(&out);
 }
 
+/// Tags of std APIs without tool attrs in source are looked up in `data/std.json`.
+#[test]
+fn extern_tags() {
+    let [file, outfile] = &testcase("extern_tags");
+    let envs = &[("SP_FILE", "assets/sp-core.toml"), ("SP_EXTERN_TAGS", "../data/std.json")];
+    should_panic(file, outfile, CompilationOptions { envs, ..Default::default() });
+}
+
+/// Extern tags are also read from `extern-tags.toml` alongside the spec, and paths that
+/// resolve to no function are warned.
+#[test]
+fn extern_tags_sidecar() {
+    let [file, _] = &testcase("extern_tags");
+    let envs = &[("SP_FILE", "tests/snippets/extern-tags/safety-tags.toml")];
+    let opts = CompilationOptions { envs, ..Default::default() };
+    should_panic(file, "snapshots/extern_tags_sidecar.txt", opts);
+}
+
 #[test]
 fn unsafe_calls_method() {
     let [file, outfile] = &testcase("unsafe_calls_method");
@@ -213,3 +237,120 @@ fn unsafe_calls_panic_with_dep() {
     let [file, outfile] = &testcase("unsafe_calls_panic_with_dep");
     should_panic(file, outfile, opts);
 }
+
+#[test]
+fn ref_ok() {
+    let [file, outfile] = &testcase("ref_ok");
+    fine(file, outfile, Default::default());
+}
+
+#[test]
+fn ref_err() {
+    let [file, outfile] = &testcase("ref_err");
+    should_panic(file, outfile, Default::default());
+}
+
+const ORDERING: CompilationOptions = CompilationOptions {
+    args: &["--crate-type=lib"],
+    // A separate database avoids tagged functions checked against other specs.
+    envs: &[
+        ("SP_FILE", "tests/snippets/sp-ordering.toml"),
+        ("DATA_SQLITE3", "target/ordering.sqlite3"),
+    ],
+    stop: true,
+};
+
+#[test]
+fn ordering_ok() {
+    let [file, outfile] = &testcase("ordering_ok");
+    fine(file, outfile, ORDERING);
+}
+
+#[test]
+fn ordering_err() {
+    let [file, outfile] = &testcase("ordering_err");
+    should_panic(file, outfile, ORDERING);
+}
+
+#[test]
+fn versions_metadata() {
+    let [file, outfile] = &testcase("versions");
+    let envs = &[
+        ("SP_FILE", "tests/snippets/sp-versions.toml"),
+        ("DATA_SQLITE3", "target/versions.sqlite3"),
+        ("CARGO_MANIFEST_DIR", "tests/snippets/versions"),
+    ];
+    fine(file, outfile, CompilationOptions { envs, ..Default::default() });
+}
+
+#[test]
+fn versions_env() {
+    let [file, _] = &testcase("versions");
+    let envs = &[
+        ("SP_FILE", "tests/snippets/sp-versions.toml"),
+        ("DATA_SQLITE3", "target/versions.sqlite3"),
+        ("SP_VERSION", "0.4"),
+    ];
+    fine(file, "snapshots/versions_env.txt", CompilationOptions { envs, ..Default::default() });
+}
+
+#[test]
+fn reason_per_tag() {
+    let [file, _] = &testcase("reason");
+    let envs =
+        &[("SP_FILE", "tests/snippets/sp-reason.toml"), ("DATA_SQLITE3", "target/reason.sqlite3")];
+    let opts = CompilationOptions { envs, ..Default::default() };
+    should_panic(file, "snapshots/reason_per_tag.txt", opts);
+}
+
+/// `require_reason` applies to tags in `any` too.
+#[test]
+fn reason_any() {
+    let [file, outfile] = &testcase("reason_any");
+    let envs = &[
+        ("SP_FILE", "tests/snippets/sp-reason.toml"),
+        ("DATA_SQLITE3", "target/reason-any.sqlite3"),
+    ];
+    let opts = CompilationOptions { envs, ..Default::default() };
+    should_panic(file, outfile, opts);
+}
+
+#[test]
+fn reason_policy() {
+    let [file, _] = &testcase("reason");
+    let envs = &[
+        ("SP_FILE", "tests/snippets/sp-reason-policy.toml"),
+        ("DATA_SQLITE3", "target/reason-policy.sqlite3"),
+    ];
+    let opts = CompilationOptions { envs, ..Default::default() };
+    should_panic(file, "snapshots/reason_policy.txt", opts);
+}
+
+#[test]
+fn audit_tables() {
+    let [file, _] = &testcase("reason");
+    let db = "target/audit.sqlite3";
+    for suffix in ["", "-wal", "-shm"] {
+        _ = std::fs::remove_file(format!("{db}{suffix}"));
+    }
+    let envs = &[("SP_FILE", "tests/snippets/sp-reason.toml"), ("DATA_SQLITE3", db)];
+    compile(file, CompilationOptions { envs, ..Default::default() });
+
+    let conn = rusqlite::Connection::open(db).unwrap();
+    let query = |sql: &str| -> Vec<String> {
+        let mut stmt = conn.prepare(sql).unwrap();
+        stmt.query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect()
+    };
+
+    let requirements = query("SELECT tag FROM requirement ORDER BY tag");
+    assert_eq!(requirements, ["Alias", "Align"]);
+
+    let no_reason = query(
+        "SELECT caller FROM callsite JOIN discharge ON discharge.callsite = callsite.id
+         WHERE discharge.tag = 'Alias' AND discharge.reason IS NULL",
+    );
+    assert_eq!(no_reason, ["reason::without_reason"]);
+
+    let diagnostics = query("SELECT kind FROM diagnostic");
+    assert_eq!(diagnostics, ["MissingReason"]);
+}